@@ -0,0 +1,338 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use chrono::Local;
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+
+use crate::error::{ClipItError, Context, Result};
+use crate::storage::{copy_database, Storage, SCHEMA_VERSION};
+use crate::SharedState;
+
+/// Folder next to `clipit.db` that holds the snapshots.
+pub const BACKUP_DIR_NAME: &str = "backups";
+
+const SNAPSHOT_PREFIX: &str = "clipit-";
+const SNAPSHOT_EXTENSION: &str = "db";
+
+/// How often the scheduler checks whether a snapshot is due.
+const SCHEDULE_TICK: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupInfo {
+    /// File name inside the backups folder; what `restore_backup` takes.
+    pub name: String,
+    pub path: String,
+    /// Unix milliseconds, from the file's modification time.
+    pub created_at: i64,
+    pub size_bytes: u64,
+}
+
+/// Snapshots the database at `db_path` into `dir` over a separate read-only
+/// connection, so the capture loop keeps writing while it runs. The snapshot
+/// is written under a temporary name and renamed once complete.
+pub fn create_backup(db_path: &Path, dir: &Path) -> Result<BackupInfo> {
+    fs::create_dir_all(dir)?;
+    let name = format!(
+        "{SNAPSHOT_PREFIX}{}.{SNAPSHOT_EXTENSION}",
+        Local::now().format("%Y%m%d-%H%M%S-%3f")
+    );
+    let path = dir.join(&name);
+    let tmp = path.with_extension(format!("{SNAPSHOT_EXTENSION}.tmp"));
+
+    let written = (|| {
+        let source = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let mut snapshot = Connection::open(&tmp)?;
+        copy_database(&source, &mut snapshot)?;
+        // The copy inherits WAL mode; a snapshot should be a single file.
+        snapshot.pragma_update(None, "journal_mode", "DELETE")?;
+        snapshot.close().map_err(|(_, err)| err)
+    })();
+    if let Err(err) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(err).context("failed to write backup");
+    }
+    fs::rename(&tmp, &path)?;
+    backup_info(&path)
+}
+
+/// Snapshots in `dir`, newest first. Names embed their timestamp, so they
+/// sort chronologically.
+pub fn list_backups(dir: &Path) -> Result<Vec<BackupInfo>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut backups = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if is_snapshot_name(&path.file_name().unwrap_or_default().to_string_lossy()) {
+            backups.push(backup_info(&path)?);
+        }
+    }
+    backups.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(backups)
+}
+
+/// Deletes all but the `keep` newest snapshots and returns what was removed.
+pub fn rotate_backups(dir: &Path, keep: usize) -> Result<Vec<BackupInfo>> {
+    let stale: Vec<BackupInfo> = list_backups(dir)?.into_iter().skip(keep.max(1)).collect();
+    for backup in &stale {
+        fs::remove_file(&backup.path)?;
+    }
+    Ok(stale)
+}
+
+/// Checks that `path` is an intact Clip It database this version can open.
+pub fn validate_backup(path: &Path) -> Result<()> {
+    let unusable = |reason: String| ClipItError::invalid_input(format!("backup {reason}"));
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|err| unusable(format!("can't be opened: {err}")))?;
+
+    let problems: Vec<String> = conn
+        .prepare("PRAGMA integrity_check")
+        .and_then(|mut stmt| stmt.query_map([], |row| row.get(0))?.collect())
+        .map_err(|err| unusable(format!("can't be read: {err}")))?;
+    if problems.first().map(String::as_str) != Some("ok") {
+        return Err(unusable(format!("is damaged: {}", problems.join("; "))));
+    }
+
+    let tables: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name IN ('items', 'settings')",
+        [],
+        |row| row.get(0),
+    )?;
+    if tables != 2 {
+        return Err(unusable("is not a Clip It database".to_string()));
+    }
+
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version > SCHEMA_VERSION {
+        return Err(unusable(format!(
+            "is from a newer version of Clip It (schema {version}, this build reads up to {SCHEMA_VERSION})"
+        )));
+    }
+    Ok(())
+}
+
+/// Validates the snapshot `name` from `dir` and swaps it in for the live
+/// database. The current database is snapshotted first, and that snapshot is
+/// returned so the restore itself can be undone.
+pub fn restore_backup(
+    storage: &mut Storage,
+    db_path: &Path,
+    dir: &Path,
+    name: &str,
+) -> Result<BackupInfo> {
+    if !is_snapshot_name(name) {
+        return Err(ClipItError::invalid_input(format!(
+            "\"{name}\" is not a backup name"
+        )));
+    }
+    let path = dir.join(name);
+    if !path.is_file() {
+        return Err(ClipItError::NotFound {
+            entity: "backup",
+            id: None,
+        });
+    }
+    validate_backup(&path)?;
+
+    let previous = create_backup(db_path, dir)?;
+    storage.restore_from(&path)?;
+    Ok(previous)
+}
+
+/// Takes a snapshot whenever the newest one is older than
+/// `backup_interval_hours`, then trims the folder to `backup_keep`. Both
+/// settings are read on every tick.
+pub fn start_scheduler(state: Arc<SharedState>) {
+    thread::spawn(move || loop {
+        if let Err(err) = run_schedule(&state) {
+            eprintln!("scheduled backup failed: {err}");
+        }
+        thread::sleep(SCHEDULE_TICK);
+    });
+}
+
+fn run_schedule(state: &SharedState) -> Result<()> {
+    let (interval_hours, keep) = {
+        let settings = state.settings.read()?;
+        (settings.backup_interval_hours, settings.backup_keep)
+    };
+    if interval_hours <= 0 {
+        return Ok(());
+    }
+
+    let interval_ms = interval_hours * 60 * 60 * 1000;
+    let newest = list_backups(&state.backup_dir)?
+        .first()
+        .map(|backup| backup.created_at);
+    let due = newest.is_none_or(|at| Local::now().timestamp_millis() - at >= interval_ms);
+    if due {
+        create_backup(&state.db_path, &state.backup_dir)?;
+        rotate_backups(&state.backup_dir, keep as usize)?;
+    }
+    Ok(())
+}
+
+fn is_snapshot_name(name: &str) -> bool {
+    name.starts_with(SNAPSHOT_PREFIX)
+        && name.ends_with(&format!(".{SNAPSHOT_EXTENSION}"))
+        && !name.contains(['/', '\\'])
+}
+
+fn backup_info(path: &Path) -> Result<BackupInfo> {
+    let meta = fs::metadata(path)?;
+    let created_at = meta
+        .modified()
+        .ok()
+        .and_then(|at| at.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default();
+    Ok(BackupInfo {
+        name: path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        path: path.display().to_string(),
+        created_at,
+        size_bytes: meta.len(),
+    })
+}
+
+/// Where snapshots of the database at `db_path` live.
+pub fn backup_dir(db_path: &Path) -> PathBuf {
+    db_path.with_file_name(BACKUP_DIR_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{create_backup, list_backups, restore_backup, rotate_backups, validate_backup};
+    use crate::storage::{SearchItem, SearchParams, Storage};
+    use crate::test_support::{insert_text, temp_dir};
+
+    fn items(storage: &Storage) -> Vec<SearchItem> {
+        storage
+            .search_items(&SearchParams {
+                limit: 50,
+                ..Default::default()
+            })
+            .expect("search")
+            .items
+    }
+
+    fn texts(storage: &Storage) -> Vec<String> {
+        items(storage).into_iter().map(|item| item.text).collect()
+    }
+
+    #[test]
+    fn snapshots_while_open_and_restores_them() {
+        let dir = temp_dir("restore");
+        let db_path = dir.join("clipit.db");
+        let backups = dir.join("backups");
+        let mut storage = Storage::open(&db_path).expect("open db");
+        let kept = insert_text(&storage, "before backup");
+        storage.set_favorite(kept, true).expect("favorite");
+
+        let snapshot = create_backup(&db_path, &backups).expect("backup");
+        validate_backup(std::path::Path::new(&snapshot.path)).expect("valid snapshot");
+        insert_text(&storage, "after backup");
+        assert_eq!(texts(&storage).len(), 2);
+
+        let previous =
+            restore_backup(&mut storage, &db_path, &backups, &snapshot.name).expect("restore");
+        assert_eq!(texts(&storage), ["before backup"]);
+        assert!(items(&storage)[0].favorite);
+        insert_text(&storage, "after restore");
+
+        restore_backup(&mut storage, &db_path, &backups, &previous.name).expect("undo");
+        assert_eq!(texts(&storage).len(), 2);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rejects_damaged_foreign_and_unknown_snapshots() {
+        let dir = temp_dir("validate");
+        let db_path = dir.join("clipit.db");
+        let backups = dir.join("backups");
+        let mut storage = Storage::open(&db_path).expect("open db");
+        insert_text(&storage, "keep me");
+
+        let snapshot = create_backup(&db_path, &backups).expect("backup");
+        let mut bytes = std::fs::read(&snapshot.path).expect("read");
+        let len = bytes.len();
+        bytes[len / 2..].fill(0xA5);
+        std::fs::write(&snapshot.path, bytes).expect("corrupt");
+        let err =
+            restore_backup(&mut storage, &db_path, &backups, &snapshot.name).expect_err("damaged");
+        assert_eq!(err.code(), "invalid_input");
+
+        let foreign = backups.join("clipit-foreign.db");
+        rusqlite::Connection::open(&foreign)
+            .and_then(|conn| conn.execute_batch("CREATE TABLE notes(text TEXT);"))
+            .expect("foreign db");
+        assert!(validate_backup(&foreign).is_err());
+
+        let newer = backups.join("clipit-newer.db");
+        rusqlite::Connection::open(&newer)
+            .and_then(|conn| {
+                conn.execute_batch(
+                    "CREATE TABLE items(id INTEGER); CREATE TABLE settings(key TEXT);
+                     PRAGMA user_version = 999;",
+                )
+            })
+            .expect("newer db");
+        assert!(validate_backup(&newer).is_err());
+
+        assert_eq!(
+            restore_backup(&mut storage, &db_path, &backups, "../clipit.db")
+                .expect_err("traversal")
+                .code(),
+            "invalid_input"
+        );
+        assert_eq!(
+            restore_backup(&mut storage, &db_path, &backups, "clipit-missing.db")
+                .expect_err("missing")
+                .code(),
+            "not_found"
+        );
+        assert_eq!(texts(&storage), ["keep me"]);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rotation_keeps_the_newest_snapshots() {
+        let dir = temp_dir("rotate");
+        let db_path = dir.join("clipit.db");
+        let backups = dir.join("backups");
+        let _storage = Storage::open(&db_path).expect("open db");
+
+        let mut names = Vec::new();
+        for _ in 0..4 {
+            names.push(create_backup(&db_path, &backups).expect("backup").name);
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        std::fs::write(backups.join("notes.txt"), "not a snapshot").expect("write");
+
+        let removed = rotate_backups(&backups, 2).expect("rotate");
+        assert_eq!(removed.len(), 2);
+        let left: Vec<_> = list_backups(&backups)
+            .expect("list")
+            .into_iter()
+            .map(|b| b.name)
+            .collect();
+        assert_eq!(left, [names[3].clone(), names[2].clone()]);
+        assert!(backups.join("notes.txt").exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
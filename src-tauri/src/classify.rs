@@ -0,0 +1,331 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+/// Only the head of very large payloads is inspected, so classifying a huge log
+/// dump costs the same as classifying a page of text.
+const CLASSIFY_MAX_BYTES: usize = 64 * 1024;
+
+pub const SUBTYPE_URL: &str = "url";
+pub const SUBTYPE_EMAIL: &str = "email";
+pub const SUBTYPE_COLOR: &str = "color";
+pub const SUBTYPE_JSON: &str = "json";
+pub const SUBTYPE_NUMBER: &str = "number";
+pub const SUBTYPE_PHONE: &str = "phone";
+pub const SUBTYPE_CODE: &str = "code";
+pub const SUBTYPE_PROSE: &str = "prose";
+
+static EMAIL_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?i)(mailto:)?[a-z0-9._%+-]+@[a-z0-9-]+(\.[a-z0-9-]+)*\.[a-z]{2,}$")
+        .expect("valid email regex")
+});
+static HEX_COLOR_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^#([0-9a-fA-F]{3,4}|[0-9a-fA-F]{6}|[0-9a-fA-F]{8})$").expect("valid hex regex")
+});
+static FN_COLOR_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?i)(rgb|rgba|hsl|hsla)\(\s*[0-9.]+%?\s*(,\s*[0-9.]+%?\s*){2}(,\s*[0-9.]+%?\s*)?\)$",
+    )
+    .expect("valid color function regex")
+});
+static NUMBER_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^-?(\d{1,3}(,\d{3})+|\d+)?(\.\d+)?([eE][+-]?\d+)?$").expect("valid number regex")
+});
+static PHONE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\+?[\d\s()-]+$").expect("valid phone regex"));
+static ISO_DATE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\d{4}-\d{2}-\d{2}$").expect("valid date regex"));
+
+/// Marker substrings per language. A language wins when it has the most hits,
+/// with at least two hits or one hit on a marker listed in `STRONG_MARKERS`.
+const LANGUAGE_MARKERS: &[(&str, &[&str])] = &[
+    (
+        "rust",
+        &[
+            "fn ", "let mut ", "impl ", "pub fn", "-> ", "::", "#[derive", "&self", "Option<",
+            "Result<",
+        ],
+    ),
+    (
+        "python",
+        &[
+            "def ", "import ", "self.", "elif ", "None", "print(", "__init__", "from ",
+        ],
+    ),
+    (
+        "typescript",
+        &[
+            "interface ",
+            ": string",
+            ": number",
+            "export type ",
+            "readonly ",
+            "as const",
+        ],
+    ),
+    (
+        "javascript",
+        &[
+            "const ",
+            "function ",
+            "=> ",
+            "console.log",
+            "let ",
+            "require(",
+            "export default",
+        ],
+    ),
+    (
+        "go",
+        &["func ", "package ", ":= ", "fmt.", "go func", "chan "],
+    ),
+    (
+        "java",
+        &[
+            "public class ",
+            "System.out",
+            "private ",
+            "public static void",
+            "@Override",
+        ],
+    ),
+    (
+        "c",
+        &["#include", "int main(", "printf(", "malloc(", "->", "std::"],
+    ),
+    (
+        "sql",
+        &[
+            "SELECT ",
+            " FROM ",
+            "WHERE ",
+            "INSERT INTO",
+            "CREATE TABLE",
+            "UPDATE ",
+            "JOIN ",
+        ],
+    ),
+    (
+        "shell",
+        &[
+            "#!/bin/", "sudo ", "echo ", "export ", " | grep", "&& ", "$ ",
+        ],
+    ),
+    (
+        "html",
+        &["<div", "</", "<html", "<span", "class=\"", "<!DOCTYPE"],
+    ),
+    (
+        "css",
+        &["{\n", "px;", "color:", "margin:", "padding:", "display:"],
+    ),
+];
+
+const STRONG_MARKERS: &[&str] = &[
+    "#[derive",
+    "#include",
+    "#!/bin/",
+    "<!DOCTYPE",
+    "public static void",
+    "CREATE TABLE",
+    "INSERT INTO",
+    "console.log",
+    "__init__",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Classification {
+    pub subtype: &'static str,
+    pub language: Option<&'static str>,
+}
+
+impl Classification {
+    fn plain(subtype: &'static str) -> Self {
+        Self {
+            subtype,
+            language: None,
+        }
+    }
+}
+
+/// Assigns a subtype to a captured text payload. Single-token shapes (URL, email,
+/// color, number, phone) are checked first, then structured formats, then code.
+pub fn classify_text(text: &str) -> Classification {
+    let head = truncate_to_char_boundary(text, CLASSIFY_MAX_BYTES);
+    let trimmed = head.trim();
+
+    if !trimmed.contains(char::is_whitespace) {
+        if is_url(trimmed) {
+            return Classification::plain(SUBTYPE_URL);
+        }
+        if EMAIL_RE.is_match(trimmed) {
+            return Classification::plain(SUBTYPE_EMAIL);
+        }
+        if HEX_COLOR_RE.is_match(trimmed) {
+            return Classification::plain(SUBTYPE_COLOR);
+        }
+    }
+    if FN_COLOR_RE.is_match(trimmed) {
+        return Classification::plain(SUBTYPE_COLOR);
+    }
+    if trimmed.chars().any(|c| c.is_ascii_digit()) && NUMBER_RE.is_match(trimmed) {
+        return Classification::plain(SUBTYPE_NUMBER);
+    }
+    if is_phone(trimmed) {
+        return Classification::plain(SUBTYPE_PHONE);
+    }
+    if text.len() <= CLASSIFY_MAX_BYTES && is_json(trimmed) {
+        return Classification::plain(SUBTYPE_JSON);
+    }
+
+    // Prose can mention "function" or "let"; real snippets carry some syntax.
+    if !trimmed.contains([';', '{', '}', '(', ')', '=', '<', '>', ':']) {
+        return Classification::plain(SUBTYPE_PROSE);
+    }
+
+    let language = guess_language(trimmed);
+    if language.is_some() || looks_like_code(trimmed) {
+        return Classification {
+            subtype: SUBTYPE_CODE,
+            language,
+        };
+    }
+
+    Classification::plain(SUBTYPE_PROSE)
+}
+
+fn is_url(text: &str) -> bool {
+    let lower = text.to_ascii_lowercase();
+    let rest = ["https://", "http://", "ftp://", "www."]
+        .iter()
+        .find_map(|scheme| lower.strip_prefix(scheme));
+    match rest {
+        Some(host) => host.starts_with("localhost") || (host.len() > 2 && host.contains('.')),
+        None => false,
+    }
+}
+
+fn is_phone(text: &str) -> bool {
+    if !PHONE_RE.is_match(text) || ISO_DATE_RE.is_match(text) {
+        return false;
+    }
+    let digits = text.chars().filter(char::is_ascii_digit).count();
+    (7..=15).contains(&digits)
+}
+
+fn is_json(text: &str) -> bool {
+    let starts_structured = (text.starts_with('{') && text.ends_with('}'))
+        || (text.starts_with('[') && text.ends_with(']'));
+    starts_structured && serde_json::from_str::<serde_json::Value>(text).is_ok()
+}
+
+fn guess_language(text: &str) -> Option<&'static str> {
+    let mut best: Option<(&'static str, usize)> = None;
+
+    for (language, markers) in LANGUAGE_MARKERS {
+        let hits: Vec<&&str> = markers.iter().filter(|m| text.contains(**m)).collect();
+        let strong = hits.iter().any(|m| STRONG_MARKERS.contains(m));
+        if hits.len() < 2 && !strong {
+            continue;
+        }
+        let score = hits.len() + if strong { 2 } else { 0 };
+        if best.is_none_or(|(_, top)| score > top) {
+            best = Some((language, score));
+        }
+    }
+
+    best.map(|(language, _)| language)
+}
+
+/// Fallback for snippets no marker table recognises: most non-blank lines end in
+/// a statement or block delimiter.
+fn looks_like_code(text: &str) -> bool {
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    if lines.len() < 2 {
+        return false;
+    }
+
+    let structured = lines
+        .iter()
+        .filter(|line| line.ends_with([';', '{', '}', ')', ']', ',']))
+        .count();
+    structured * 2 > lines.len()
+}
+
+fn truncate_to_char_boundary(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subtype(text: &str) -> &'static str {
+        classify_text(text).subtype
+    }
+
+    #[test]
+    fn classifies_single_token_shapes() {
+        assert_eq!(subtype("https://github.com/asterd/clip-it"), SUBTYPE_URL);
+        assert_eq!(subtype("www.example.org"), SUBTYPE_URL);
+        assert_eq!(subtype("alice@example.com"), SUBTYPE_EMAIL);
+        assert_eq!(subtype("mailto:bob@mail.example.co.uk"), SUBTYPE_EMAIL);
+        assert_eq!(subtype("#1e90ff"), SUBTYPE_COLOR);
+        assert_eq!(subtype("#fff"), SUBTYPE_COLOR);
+        assert_eq!(subtype("rgb(30, 144, 255)"), SUBTYPE_COLOR);
+        assert_eq!(subtype("hsla(210, 100%, 56%, 0.5)"), SUBTYPE_COLOR);
+        assert_eq!(subtype("42"), SUBTYPE_NUMBER);
+        assert_eq!(subtype("-3.14e10"), SUBTYPE_NUMBER);
+        assert_eq!(subtype("1,234,567.89"), SUBTYPE_NUMBER);
+        assert_eq!(subtype("+39 (02) 1234-5678"), SUBTYPE_PHONE);
+        assert_eq!(subtype("555-123-4567"), SUBTYPE_PHONE);
+    }
+
+    #[test]
+    fn classifies_json_only_when_it_parses() {
+        assert_eq!(subtype(r#"{"id": 1, "tags": ["a", "b"]}"#), SUBTYPE_JSON);
+        assert_eq!(subtype("[1, 2, 3]"), SUBTYPE_JSON);
+        assert_ne!(subtype("{not json}"), SUBTYPE_JSON);
+    }
+
+    #[test]
+    fn classifies_code_with_language_guess() {
+        let rust = classify_text("pub fn main() -> Result<()> {\n    let mut x = 1;\n}");
+        assert_eq!(rust.subtype, SUBTYPE_CODE);
+        assert_eq!(rust.language, Some("rust"));
+
+        let python = classify_text("def greet(self):\n    print(self.name)\n    return None");
+        assert_eq!(python.language, Some("python"));
+
+        let sql = classify_text("SELECT id, name FROM users WHERE active = 1");
+        assert_eq!(sql.language, Some("sql"));
+
+        let c = classify_text("#include <stdio.h>");
+        assert_eq!(c.language, Some("c"));
+    }
+
+    #[test]
+    fn plain_sentences_are_prose() {
+        assert_eq!(subtype("This is a normal sentence."), SUBTYPE_PROSE);
+        assert_eq!(
+            subtype("let me know which function you prefer"),
+            SUBTYPE_PROSE
+        );
+        assert_eq!(subtype("2024-01-05"), SUBTYPE_PROSE);
+        assert_eq!(
+            subtype("Meeting moved to Thursday.\nPlease bring the slides."),
+            SUBTYPE_PROSE
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! Size limits on captures. Text and images over the configured limits are
+//! skipped, truncated or compressed on the capture thread, before a payload
+//! that won't be kept whole is normalized, hashed or queued for the writer.
+
+use serde::Serialize;
+
+use super::normalize_text;
+use crate::error::{ClipItError, Result};
+use crate::images::{encode_png, stored_size};
+use crate::settings::{OversizePolicy, Settings};
+use crate::storage::{compress_text, truncate_with_marker};
+
+/// What a limit did to a capture; sent with `clipboard:capture_limited`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitAction {
+    Skipped,
+    Truncated,
+    Compressed,
+}
+
+/// A capture's text after its limit was applied, normalized.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LimitedText {
+    /// Everything, or a truncated copy with a marker.
+    pub text: String,
+    /// All of `text`, compressed, when the limit chose to keep it that way.
+    pub zstd: Option<Vec<u8>>,
+    /// Set when the text was altered to fit.
+    pub action: Option<LimitAction>,
+}
+
+/// A capture's image after its limit was applied.
+#[derive(Debug, PartialEq, Eq)]
+pub enum LimitedImage {
+    /// Stored as raw RGBA pixels.
+    Raw,
+    /// Stored PNG-encoded instead of as pixels.
+    Png(Vec<u8>),
+}
+
+/// Applies `max_text_bytes` to text as read from the clipboard; `None` means
+/// the text isn't stored. Oversized text is only normalized in full when
+/// `oversize_text` keeps all of it.
+pub fn limit_text(raw: String, settings: &Settings) -> Result<Option<LimitedText>> {
+    let limit = settings.max_text_bytes.max(0) as usize;
+    if raw.len() <= limit {
+        return Ok(Some(LimitedText {
+            text: normalize_text(&raw),
+            zstd: None,
+            action: None,
+        }));
+    }
+
+    let limited = match settings.oversize_text {
+        OversizePolicy::Skip => None,
+        OversizePolicy::Truncate => Some(LimitedText {
+            text: normalize_text(&truncate_with_marker(&raw, limit)),
+            zstd: None,
+            action: Some(LimitAction::Truncated),
+        }),
+        OversizePolicy::Compress => {
+            let text = normalize_text(&raw);
+            drop(raw);
+            let zstd = compress_text(&text)?;
+            (zstd.len() <= limit).then_some(LimitedText {
+                text,
+                zstd: Some(zstd),
+                action: Some(LimitAction::Compressed),
+            })
+        }
+    };
+    Ok(limited)
+}
+
+/// Whether an image of `width`x`height` is dropped by `max_image_pixels`
+/// before its pixels are copied: too big at the size it would be stored
+/// at, with `oversize_image` not allowing PNG instead.
+pub fn skips_image(width: i64, height: i64, settings: &Settings) -> bool {
+    let (width, height) = stored_size(width, height, settings);
+    width.saturating_mul(height) > settings.max_image_pixels
+        && settings.oversize_image != OversizePolicy::Compress
+}
+
+/// Applies `max_image_pixels`; `None` means the image isn't stored.
+pub fn limit_image(
+    rgba: &[u8],
+    width: i64,
+    height: i64,
+    settings: &Settings,
+) -> Result<Option<LimitedImage>> {
+    if width.saturating_mul(height) <= settings.max_image_pixels {
+        return Ok(Some(LimitedImage::Raw));
+    }
+
+    match settings.oversize_image {
+        OversizePolicy::Compress => {
+            let (Ok(width), Ok(height)) = (u32::try_from(width), u32::try_from(height)) else {
+                return Err(ClipItError::invalid_input(format!(
+                    "image size {width}x{height} is out of range"
+                )));
+            };
+            Ok(Some(LimitedImage::Png(encode_png(rgba, width, height)?)))
+        }
+        OversizePolicy::Skip | OversizePolicy::Truncate => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{limit_image, limit_text, skips_image, LimitAction, LimitedImage};
+    use crate::settings::{OversizePolicy, Settings};
+
+    fn settings(oversize_text: OversizePolicy) -> Settings {
+        Settings {
+            max_text_bytes: 16,
+            oversize_text,
+            max_image_pixels: 4,
+            oversize_image: OversizePolicy::Compress,
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn text_under_the_limit_is_only_normalized() {
+        let limited = limit_text("short\n".into(), &settings(OversizePolicy::Skip))
+            .expect("limit")
+            .expect("stored");
+        assert_eq!(limited.text, "short");
+        assert_eq!(limited.zstd, None);
+        assert_eq!(limited.action, None);
+    }
+
+    #[test]
+    fn oversize_text_follows_the_policy() {
+        let text = "ééééééééééé and more".to_string();
+
+        let skipped = limit_text(text.clone(), &settings(OversizePolicy::Skip)).expect("limit");
+        assert_eq!(skipped, None);
+
+        let truncated = limit_text(text.clone(), &settings(OversizePolicy::Truncate))
+            .expect("limit")
+            .expect("stored");
+        assert_eq!(truncated.action, Some(LimitAction::Truncated));
+        assert!(
+            truncated.text.starts_with("éééééééé\n"),
+            "{}",
+            truncated.text
+        );
+        assert!(truncated
+            .text
+            .ends_with(&format!("{} bytes in total]", text.len())));
+
+        let roomy = Settings {
+            max_text_bytes: 64,
+            ..settings(OversizePolicy::Compress)
+        };
+        let long = "abc".repeat(100);
+        let compressed = limit_text(long.clone(), &roomy)
+            .expect("limit")
+            .expect("stored");
+        assert_eq!(compressed.action, Some(LimitAction::Compressed));
+        assert_eq!(compressed.text, long);
+        let full = zstd::decode_all(compressed.zstd.expect("zstd").as_slice()).expect("decode");
+        assert_eq!(full, long.as_bytes());
+
+        // Compressed but still over the limit: not stored.
+        let noisy: String = (0..200u32)
+            .map(|i| char::from(b'!' + (i * 7919 % 90) as u8))
+            .collect();
+        let skipped = limit_text(noisy, &settings(OversizePolicy::Compress)).expect("limit");
+        assert_eq!(skipped, None);
+    }
+
+    #[test]
+    fn oversize_images_are_png_encoded_losslessly() {
+        let rgba: Vec<u8> = (0..3 * 2 * 4).map(|i| i as u8 * 10).collect();
+        let Some(LimitedImage::Png(png)) =
+            limit_image(&rgba, 3, 2, &settings(OversizePolicy::Skip)).expect("limit")
+        else {
+            panic!("expected a PNG");
+        };
+        let decoded = image::load_from_memory(&png).expect("decode").to_rgba8();
+        assert_eq!((decoded.width(), decoded.height()), (3, 2));
+        assert_eq!(decoded.into_raw(), rgba);
+
+        assert_eq!(
+            limit_image(&rgba[..8], 2, 1, &settings(OversizePolicy::Skip)).expect("limit"),
+            Some(LimitedImage::Raw)
+        );
+        let skip = Settings {
+            oversize_image: OversizePolicy::Skip,
+            ..settings(OversizePolicy::Skip)
+        };
+        assert_eq!(limit_image(&rgba, 3, 2, &skip).expect("limit"), None);
+    }
+
+    #[test]
+    fn images_are_skipped_by_the_size_they_would_be_stored_at() {
+        let skip = Settings {
+            max_image_pixels: 100 * 100,
+            oversize_image: OversizePolicy::Skip,
+            ..Settings::default()
+        };
+        assert!(skips_image(400, 300, &skip));
+        assert!(!skips_image(100, 100, &skip));
+
+        let downscaled = Settings {
+            image_max_dimension: 100,
+            ..skip.clone()
+        };
+        assert!(!skips_image(400, 300, &downscaled));
+        let deferred = Settings {
+            image_keep_original_hours: 1,
+            ..downscaled
+        };
+        assert!(skips_image(400, 300, &deferred));
+
+        let png = Settings {
+            oversize_image: OversizePolicy::Compress,
+            ..skip
+        };
+        assert!(!skips_image(400, 300, &png));
+    }
+}
@@ -9,9 +9,14 @@ use arboard::{Clipboard, ImageData};
 use sha2::{Digest, Sha256};
 use tauri::{AppHandle, Emitter};
 
-use crate::events::ClipboardItemAddedEvent;
+use crate::classify::classify_text;
+use crate::error::Result;
+use crate::events::{CaptureLimitedEvent, ClipboardItemAddedEvent, ITEM_EVENT_SCHEMA_VERSION};
+use crate::images::{apply_image_policy, perceptual_hash, stored_size, StoredImage};
+use crate::storage::{NewItem, StoredText};
 use crate::SharedState;
 
+mod limits;
 #[cfg(target_os = "linux")]
 mod linux;
 #[cfg(target_os = "macos")]
@@ -19,6 +24,32 @@ mod macos;
 #[cfg(target_os = "windows")]
 mod windows;
 
+pub use limits::LimitAction;
+use limits::{limit_image, limit_text, skips_image, LimitedImage};
+
+/// Captures waiting for the writer thread. When the database falls this far
+/// behind, reading the clipboard waits instead of piling up image buffers.
+const CAPTURE_QUEUE_LEN: usize = 16;
+
+/// A clipboard change read by the capture loop, on its way to the writer.
+struct Capture {
+    kind: &'static str,
+    text: Option<String>,
+    /// All of `text`, compressed by its size limit.
+    text_zstd: Option<Vec<u8>>,
+    image: Option<(Vec<u8>, i64, i64)>,
+    fingerprint: String,
+    captured_at: i64,
+    /// A size limit that altered the text, announced once it's stored.
+    limited: Option<CaptureLimitedEvent>,
+}
+
+/// Kind and size of the last capture a limit skipped, so polling the same
+/// clipboard again doesn't report it again.
+type LastSkipped = Option<(&'static str, u64)>;
+
+/// Reads the clipboard on change and queues what it finds for a writer
+/// thread, so a slow insert never delays noticing the next copy.
 pub fn start_clipboard_pipeline(app: AppHandle, state: Arc<SharedState>) {
     let (tx, rx) = mpsc::channel::<()>();
 
@@ -46,10 +77,30 @@ pub fn start_clipboard_pipeline(app: AppHandle, state: Arc<SharedState>) {
         thread::spawn(move || linux::run_polling_loop(tx_clone, state_clone));
     }
 
+    let (captures, queue) = mpsc::sync_channel::<Capture>(CAPTURE_QUEUE_LEN);
+    {
+        let app = app.clone();
+        let state = state.clone();
+        thread::spawn(move || {
+            while let Ok(capture) = queue.recv() {
+                if let Err(err) = store_capture(&app, &state, capture) {
+                    eprintln!("capture writer error: {err}");
+                }
+            }
+        });
+    }
+
     thread::spawn(move || {
+        let mut last_skipped = None;
         while rx.recv().is_ok() {
-            if let Err(err) = capture_once(&app, &state) {
-                eprintln!("capture loop error: {err}");
+            match capture_once(&app, &state, &mut last_skipped) {
+                Ok(Some(capture)) => {
+                    if captures.send(capture).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => eprintln!("capture loop error: {err}"),
             }
         }
     });
@@ -68,13 +119,37 @@ pub fn sha256_hex(input: &str) -> String {
     format!("{:x}", hasher.finalize())
 }
 
-pub fn set_clipboard_text(text: &str) -> anyhow::Result<()> {
+/// `sha256_hex` of `"{kind}:{text}"`, without copying the text.
+pub fn text_fingerprint(kind: &str, text: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(kind.as_bytes());
+    hasher.update(b":");
+    hasher.update(text.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Fingerprint of an RGBA image: its size and pixels, not how it was encoded.
+pub fn image_fingerprint(width: u64, height: u64, rgba: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"image:");
+    hasher.update(width.to_le_bytes());
+    hasher.update(height.to_le_bytes());
+    hasher.update(rgba);
+    format!("{:x}", hasher.finalize())
+}
+
+pub fn get_clipboard_text() -> Result<String> {
+    let mut clipboard = Clipboard::new()?;
+    Ok(clipboard.get_text()?)
+}
+
+pub fn set_clipboard_text(text: &str) -> Result<()> {
     let mut clipboard = Clipboard::new()?;
     clipboard.set_text(text.to_string())?;
     Ok(())
 }
 
-pub fn set_clipboard_image(rgba: Vec<u8>, width: usize, height: usize) -> anyhow::Result<()> {
+pub fn set_clipboard_image(rgba: Vec<u8>, width: usize, height: usize) -> Result<()> {
     let mut clipboard = Clipboard::new()?;
     clipboard.set_image(ImageData {
         width,
@@ -84,14 +159,14 @@ pub fn set_clipboard_image(rgba: Vec<u8>, width: usize, height: usize) -> anyhow
     Ok(())
 }
 
-fn capture_once(app: &AppHandle, state: &Arc<SharedState>) -> anyhow::Result<()> {
-    let capture_enabled = {
-        let settings = state.settings.read().expect("settings poisoned");
-        settings.capture_enabled
-    };
-
-    if !capture_enabled || state.paused.load(std::sync::atomic::Ordering::Relaxed) {
-        return Ok(());
+fn capture_once(
+    app: &AppHandle,
+    state: &SharedState,
+    last_skipped: &mut LastSkipped,
+) -> Result<Option<Capture>> {
+    let settings = state.settings.read().expect("settings poisoned").clone();
+    if !settings.capture_enabled || state.paused.load(std::sync::atomic::Ordering::Relaxed) {
+        return Ok(None);
     }
 
     let mut clipboard = Clipboard::new()?;
@@ -102,100 +177,240 @@ fn capture_once(app: &AppHandle, state: &Arc<SharedState>) -> anyhow::Result<()>
     #[cfg(not(target_os = "macos"))]
     let file_candidate: Option<String> = None;
 
-    let text_candidate = clipboard
-        .get_text()
-        .ok()
-        .map(|raw| normalize_text(&raw))
-        .filter(|t| !t.is_empty());
-
-    let (kind, text, image_rgba, image_width, image_height, fingerprint) =
-        if let Some(file_payload) = file_candidate
-            .or_else(|| text_candidate.clone().filter(|t| looks_like_file_payload(t)))
-        {
-            let fp = sha256_hex(&format!("file:{}", file_payload));
-            ("file".to_string(), Some(file_payload), None, None, None, fp)
-        } else if let Some(text_payload) = text_candidate {
-            let fp = sha256_hex(&format!("text:{}", text_payload));
-            ("text".to_string(), Some(text_payload), None, None, None, fp)
-        } else if let Ok(img) = clipboard.get_image() {
-            let width = img.width as i64;
-            let height = img.height as i64;
-            let bytes = img.bytes.into_owned();
-            let mut hasher = Sha256::new();
-            hasher.update(b"image:");
-            hasher.update((width as u64).to_le_bytes());
-            hasher.update((height as u64).to_le_bytes());
-            hasher.update(&bytes);
-            let fp = format!("{:x}", hasher.finalize());
-            let label = format!("image://{}x{}", width, height);
-            (
-                "image".to_string(),
-                Some(label),
-                Some(bytes),
-                Some(width),
-                Some(height),
-                fp,
-            )
-        } else {
-            return Ok(());
-        };
+    let text_candidate = match clipboard.get_text() {
+        Ok(raw) => {
+            let size = raw.len();
+            let Some(limited) = limit_text(raw, &settings)? else {
+                report_skipped(app, last_skipped, "text", size, settings.max_text_bytes);
+                return Ok(None);
+            };
+            Some((limited, size)).filter(|(limited, _)| !limited.text.is_empty())
+        }
+        Err(_) => None,
+    };
+
+    let mut text_zstd = None;
+    let mut limited = None;
+    let (kind, text, image, mut fingerprint) = if let Some(file_payload) = file_candidate {
+        let fp = sha256_hex(&format!("file:{}", file_payload));
+        ("file", Some(file_payload), None, fp)
+    } else if let Some((text_payload, size)) = text_candidate {
+        let kind = text_kind(&text_payload.text);
+        let fp = text_fingerprint(kind, &text_payload.text);
+        text_zstd = text_payload.zstd;
+        limited = text_payload
+            .action
+            .map(|action| limited_event("text", size, settings.max_text_bytes, action));
+        (kind, Some(text_payload.text), None, fp)
+    } else if let Ok(img) = clipboard.get_image() {
+        let width = img.width as i64;
+        let height = img.height as i64;
+        if skips_image(width, height, &settings) {
+            let (width, height) = stored_size(width, height, &settings);
+            let pixels = (width * height) as usize;
+            report_skipped(
+                app,
+                last_skipped,
+                "image",
+                pixels,
+                settings.max_image_pixels,
+            );
+            return Ok(None);
+        }
+        let bytes = img.bytes.into_owned();
+        let fp = image_fingerprint(width as u64, height as u64, &bytes);
+        let label = format!("image://{}x{}", width, height);
+        ("image", Some(label), Some((bytes, width, height)), fp)
+    } else {
+        return Ok(None);
+    };
+    *last_skipped = None;
 
     {
         let guard = state.last_written.lock().expect("last_written poisoned");
         if let Some(last) = &*guard {
-            if last.fingerprint == fingerprint && now - last.written_at_ms < 2000 {
-                return Ok(());
+            if last.fingerprint == fingerprint {
+                if now - last.written_at_ms < 2000 {
+                    return Ok(None);
+                }
+                if let Some(item_fingerprint) = &last.item_fingerprint {
+                    fingerprint = item_fingerprint.clone();
+                }
             }
         }
     }
 
-    let storage = state.storage.lock().expect("storage poisoned");
+    Ok(Some(Capture {
+        kind,
+        text,
+        text_zstd,
+        image,
+        fingerprint,
+        captured_at: now,
+        limited,
+    }))
+}
+
+fn limited_event(kind: &str, size: usize, limit: i64, action: LimitAction) -> CaptureLimitedEvent {
+    CaptureLimitedEvent {
+        schema_version: ITEM_EVENT_SCHEMA_VERSION,
+        kind: kind.to_string(),
+        size: size as u64,
+        limit: limit.max(0) as u64,
+        action,
+    }
+}
+
+fn report_skipped(
+    app: &AppHandle,
+    last_skipped: &mut LastSkipped,
+    kind: &'static str,
+    size: usize,
+    limit: i64,
+) {
+    if *last_skipped == Some((kind, size as u64)) {
+        return;
+    }
+    *last_skipped = Some((kind, size as u64));
+    let event = limited_event(kind, size, limit, LimitAction::Skipped);
+    let _ = app.emit("clipboard:capture_limited", event);
+}
 
-    if let Some(last_fp) = storage.last_fingerprint()? {
-        if last_fp == fingerprint {
+/// Runs on the writer thread: stores one capture unless it repeats the newest
+/// item, then announces it along with any size limit that altered it.
+fn store_capture(app: &AppHandle, state: &SharedState, capture: Capture) -> Result<()> {
+    let Capture {
+        kind,
+        text,
+        text_zstd,
+        image,
+        fingerprint,
+        captured_at,
+        mut limited,
+    } = capture;
+    let settings = state.settings.read().expect("settings poisoned").clone();
+
+    // Polling sees the same clipboard again and again; repeats go no further.
+    {
+        let storage = state.storage.lock().expect("storage poisoned");
+        if storage.last_fingerprint()?.as_deref() == Some(fingerprint.as_str()) {
             return Ok(());
         }
     }
 
-    let id = storage.insert_item(
-        &kind,
-        text.as_deref(),
-        &fingerprint,
-        image_rgba.as_deref(),
-        image_width,
-        image_height,
-    )?;
-    let max_items = {
-        let s = state.settings.read().expect("settings poisoned");
-        s.max_items
+    let text = match (text, text_zstd) {
+        (Some(text), Some(zstd)) => Some(StoredText::compressed(text, zstd, &settings)),
+        (Some(text), None) if kind == "text" => Some(StoredText::new(text, &settings)?),
+        (text, _) => text.map(StoredText::plain),
     };
-    storage.enforce_max_items(max_items)?;
-
-    let preview_text = match kind.as_str() {
-        "image" => "Image copied".to_string(),
-        "file" => text.clone().unwrap_or_default(),
-        _ => text
-            .clone()
-            .unwrap_or_default()
-            .replace('\n', " ")
-            .replace('\r', " ")
-            .chars()
-            .take(140)
-            .collect::<String>(),
+    // Hashed as captured, so a shrunk copy still matches the next capture.
+    let image_phash = image
+        .as_ref()
+        .and_then(|(rgba, width, height)| perceptual_hash(rgba, *width, *height));
+    let image = match image {
+        Some((rgba, width, height)) => {
+            // With `image_keep_original_hours`, the scheduler shrinks it later.
+            let shrunk = if settings.image_keep_original_hours == 0 {
+                apply_image_policy(&rgba, width, height, &settings)?
+            } else {
+                None
+            };
+            let mut stored = shrunk.unwrap_or_else(|| StoredImage::raw(rgba, width, height));
+            if let Some(rgba) = stored.rgba.take() {
+                let pixels = (stored.width.max(0) * stored.height.max(0)) as usize;
+                let event = |action| limited_event(kind, pixels, settings.max_image_pixels, action);
+                // `skips_image` already turned away what this would skip.
+                match limit_image(&rgba, stored.width, stored.height, &settings)? {
+                    Some(LimitedImage::Raw) => stored.rgba = Some(rgba),
+                    Some(LimitedImage::Png(png)) => {
+                        limited = Some(event(LimitAction::Compressed));
+                        stored.encoded = Some(png);
+                    }
+                    None => {
+                        let _ = app.emit("clipboard:capture_limited", event(LimitAction::Skipped));
+                        return Ok(());
+                    }
+                }
+            }
+            Some(stored)
+        }
+        None => None,
     };
 
+    let storage = state.storage.lock().expect("storage poisoned");
+    let plain_text = text.as_ref().map(|text| text.text.as_str());
+    let class = (kind == "text").then(|| classify_text(plain_text.unwrap_or_default()));
+    let subtype = class.map(|c| c.subtype);
+
+    let id = storage.insert_item(&NewItem {
+        created_at: Some(captured_at),
+        kind,
+        subtype,
+        language: class.and_then(|c| c.language),
+        text: plain_text,
+        text_zstd: text.as_ref().and_then(|text| text.zstd.as_deref()),
+        search_text: text.as_ref().and_then(|text| text.search_text.as_deref()),
+        fingerprint: &fingerprint,
+        image_rgba: image.as_ref().and_then(|image| image.rgba.as_deref()),
+        image_encoded: image.as_ref().and_then(|image| image.encoded.as_deref()),
+        image_quality: image.as_ref().and_then(|image| image.quality),
+        image_width: image.as_ref().map(|image| image.width),
+        image_height: image.as_ref().map(|image| image.height),
+        image_phash,
+    })?;
+    if let Some(hash) = image_phash.filter(|_| settings.collapse_similar_images) {
+        storage.collapse_similar_images(id, hash, settings.similar_image_distance as u32)?;
+    }
+    storage.enforce_max_items(settings.max_items)?;
+    drop(storage);
+
+    if let Some(event) = limited {
+        let _ = app.emit("clipboard:capture_limited", event);
+    }
     let payload = ClipboardItemAddedEvent {
+        schema_version: ITEM_EVENT_SCHEMA_VERSION,
         id,
-        preview_text,
-        created_at: now,
+        preview_text: event_preview_text(kind, plain_text.unwrap_or_default()),
+        subtype: subtype.map(str::to_string),
+        created_at: captured_at,
         pinned: false,
     };
     let _ = app.emit("clipboard:item_added", payload);
 
+    let stacked = {
+        let mut stack = state.paste_stack.lock().expect("paste stack poisoned");
+        stack.push(id).then(|| stack.state())
+    };
+    if let Some(snapshot) = stacked {
+        crate::refresh_paste_stack_indicator(app, &snapshot);
+    }
+
     Ok(())
 }
 
-fn looks_like_file_payload(text: &str) -> bool {
+pub fn event_preview_text(kind: &str, text: &str) -> String {
+    match kind {
+        "image" => "Image copied".to_string(),
+        "file" => text.to_string(),
+        _ => text
+            .replace(['\n', '\r'], " ")
+            .chars()
+            .take(140)
+            .collect::<String>(),
+    }
+}
+
+/// The kind a captured clipboard text is stored as: `file` when every line
+/// looks like a path, else `text`.
+pub fn text_kind(text: &str) -> &'static str {
+    if looks_like_file_payload(text) {
+        "file"
+    } else {
+        "text"
+    }
+}
+
+pub fn looks_like_file_payload(text: &str) -> bool {
     let lines: Vec<&str> = text
         .lines()
         .map(str::trim)
@@ -225,7 +440,7 @@ fn now_ms() -> i64 {
 
 #[cfg(test)]
 mod tests {
-    use super::{looks_like_file_payload, normalize_text};
+    use super::{looks_like_file_payload, normalize_text, sha256_hex, text_fingerprint, text_kind};
 
     #[test]
     fn normalize_text_removes_null_and_soft_trims() {
@@ -257,4 +472,19 @@ mod tests {
         let payload = "This is a normal sentence.";
         assert!(!looks_like_file_payload(payload));
     }
+
+    #[test]
+    fn joined_paths_read_back_as_files() {
+        assert_eq!(text_kind("/home/me/a.txt\n/home/me/b.txt"), "file");
+        assert_eq!(text_kind("first note\nsecond note"), "text");
+    }
+
+    #[test]
+    fn text_fingerprints_match_the_stored_ones() {
+        let text = "ünïcode clip";
+        assert_eq!(
+            text_fingerprint("text", text),
+            sha256_hex(&format!("text:{text}"))
+        );
+    }
 }
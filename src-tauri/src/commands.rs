@@ -1,93 +1,280 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::atomic::Ordering;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use serde::Serialize;
 use serde_json::Value;
 use tauri::{AppHandle, Emitter, Manager, State};
-use tauri_plugin_global_shortcut::{Code, Modifiers, Shortcut};
 
-use crate::clipboard::{normalize_text, set_clipboard_image, set_clipboard_text, sha256_hex};
-use crate::events::ClipboardPausedChangedEvent;
-use crate::settings::{PauseState, Settings};
-use crate::storage::{ItemPreview, SearchResponse};
+use crate::backup::BackupInfo;
+use crate::classify::classify_text;
+use crate::clipboard::{
+    event_preview_text, get_clipboard_text, image_fingerprint, normalize_text, set_clipboard_image,
+    set_clipboard_text, sha256_hex, text_kind,
+};
+use crate::error::ClipItError;
+use crate::events::{
+    BackupRestoredEvent, ClipboardItemAddedEvent, ClipboardPausedChangedEvent, ItemsImportedEvent,
+    SettingsChangedEvent, ITEM_EVENT_SCHEMA_VERSION,
+};
+use crate::export::{ExportFormat, ExportSummary, ImageMode};
+use crate::import::{ImportReport, ImportSource};
+use crate::paste_stack::{PasteStackState, StackOrder};
+use crate::settings::{settings_schema, AppliedSetting, PauseState, SettingSchema, Settings};
+use crate::snippets::{
+    render_template, uses_clipboard, validate_template, RenderContext, RenderedSnippet,
+};
+use crate::storage::{
+    compress_long_texts, Collection, CompressionReport, ItemPreview, ItemRevision, NewItem,
+    RecoveryReport, SearchParams, SearchResponse, SimilarImage, Snippet, Storage, StoredText, Tag,
+};
+use crate::transforms::{apply_transform, Transform, TransformOutput, TRANSFORMS};
 use crate::SharedState;
 
+const PASTE_HIDE_DELAY_MS: u64 = 60;
+
+#[tauri::command]
+pub fn get_settings(
+    state: State<'_, std::sync::Arc<SharedState>>,
+) -> Result<Settings, ClipItError> {
+    Ok(state.settings.read()?.clone())
+}
+
 #[tauri::command]
-pub fn get_settings(state: State<'_, std::sync::Arc<SharedState>>) -> Result<Settings, String> {
-    Ok(state.settings.read().map_err(err_to_string)?.clone())
+pub fn get_settings_schema() -> Vec<SettingSchema> {
+    settings_schema()
 }
 
+/// Validates and stores one setting, returning the value that was actually
+/// applied (clamped or canonicalised). Shortcut changes are registered before
+/// anything is saved, so a conflict leaves the previous settings in place.
+/// Keys that `config.toml` sets are updated there too, or the file would win
+/// on the next reload.
 #[tauri::command]
 pub fn set_setting(
     app: AppHandle,
     state: State<'_, std::sync::Arc<SharedState>>,
     key: String,
     value: Value,
-) -> Result<(), String> {
-    {
-        let mut settings = state.settings.write().map_err(err_to_string)?;
-        crate::storage::apply_setting_value(&mut settings, &key, value.clone());
-    }
+) -> Result<AppliedSetting, ClipItError> {
+    let (applied, settings) = {
+        let mut settings = state.settings.write()?;
+        let mut candidate = settings.clone();
+        let applied = candidate.apply(&key, &value)?;
+
+        if matches!(
+            key.as_str(),
+            "hotkey" | "recent_hotkey_prefix" | "pinned_hotkey_prefix"
+        ) {
+            crate::register_global_shortcuts(&app, &candidate)?;
+        }
+        *settings = candidate;
+        (applied, settings.clone())
+    };
 
     {
-        let storage = state.storage.lock().map_err(err_to_string)?;
-        storage.upsert_setting(&key, &value).map_err(err_to_string)?;
+        let storage = state.storage.lock()?;
+        storage.upsert_setting(&key, &applied.value)?;
         if key == "max_items" {
-            let settings = state.settings.read().map_err(err_to_string)?;
-            storage
-                .enforce_max_items(settings.max_items)
-                .map_err(err_to_string)?;
+            storage.enforce_max_items(settings.max_items)?;
         }
     }
-
-    if key == "hotkey" {
-        let settings = state.settings.read().map_err(err_to_string)?.clone();
-        crate::register_global_shortcut(&app, &settings.hotkey).map_err(err_to_string)?;
+    crate::config::write_config_value(&state.config_path, &key, &applied.value)
+        .map_err(|err| ClipItError::internal(format!("{err:#}")))?;
+    if key == "compressed_search" {
+        let state = state.inner().clone();
+        std::thread::spawn(move || crate::apply_compressed_search(&state));
     }
 
-    Ok(())
+    let _ = app.emit(
+        "settings:changed",
+        SettingsChangedEvent {
+            key,
+            value: applied.value.clone(),
+            settings,
+        },
+    );
+
+    Ok(applied)
+}
+
+/// Writes the current settings to `config.toml` and returns its path.
+#[tauri::command]
+pub fn export_settings_config(
+    state: State<'_, std::sync::Arc<SharedState>>,
+) -> Result<String, ClipItError> {
+    let settings = state.settings.read()?.clone();
+    crate::config::write_config(&state.config_path, &settings)
+        .map_err(|err| ClipItError::internal(format!("{err:#}")))?;
+    Ok(state.config_path.display().to_string())
+}
+
+/// Writes the history matching `params` (everything by default) to `path`, or
+/// to a timestamped file in the downloads folder when no path is given.
+#[tauri::command]
+pub fn export_history(
+    app: AppHandle,
+    state: State<'_, std::sync::Arc<SharedState>>,
+    format: ExportFormat,
+    params: Option<SearchParams>,
+    images: Option<ImageMode>,
+    path: Option<String>,
+) -> Result<ExportSummary, ClipItError> {
+    let path = match path.filter(|p| !p.trim().is_empty()) {
+        Some(path) => PathBuf::from(path),
+        None => crate::export::default_export_path(&app.path().download_dir()?, format),
+    };
+    let storage = state.readers.get()?;
+    crate::export::export_history(
+        &storage,
+        &params.unwrap_or_default(),
+        format,
+        images.unwrap_or_default(),
+        &path,
+    )
+}
+
+/// Imports another clipboard manager's history file. Entries already in the
+/// history are merged rather than duplicated. Imported items don't count
+/// towards `max_items`, so retention never drops them.
+#[tauri::command]
+pub fn import_history(
+    app: AppHandle,
+    state: State<'_, std::sync::Arc<SharedState>>,
+    source: ImportSource,
+    path: String,
+) -> Result<ImportReport, ClipItError> {
+    let settings = state.settings.read()?.clone();
+    let report = {
+        let storage = state.storage.lock()?;
+        crate::import::import_history(&storage, source, &PathBuf::from(path))?
+    };
+    compress_long_texts(&state.readers, &state.storage, &settings)?;
+    let _ = app.emit(
+        "clipboard:items_imported",
+        ItemsImportedEvent {
+            schema_version: ITEM_EVENT_SCHEMA_VERSION,
+            source,
+            imported: report.imported,
+        },
+    );
+    Ok(report)
+}
+
+/// Snapshots in the backups folder, newest first.
+#[tauri::command]
+pub fn list_backups(
+    state: State<'_, std::sync::Arc<SharedState>>,
+) -> Result<Vec<BackupInfo>, ClipItError> {
+    crate::backup::list_backups(&state.backup_dir)
+}
+
+/// Takes a snapshot now, outside the schedule, and rotates old ones out.
+#[tauri::command]
+pub fn create_backup(
+    state: State<'_, std::sync::Arc<SharedState>>,
+) -> Result<BackupInfo, ClipItError> {
+    let backup = crate::backup::create_backup(&state.db_path, &state.backup_dir)?;
+    let keep = state.settings.read()?.backup_keep;
+    crate::backup::rotate_backups(&state.backup_dir, keep as usize)?;
+    Ok(backup)
+}
+
+/// Replaces the history, settings and snippets with the snapshot `name` after
+/// checking it is intact. Returns the snapshot taken of the replaced database.
+#[tauri::command]
+pub fn restore_backup(
+    app: AppHandle,
+    state: State<'_, std::sync::Arc<SharedState>>,
+    name: String,
+) -> Result<BackupInfo, ClipItError> {
+    let safety_backup = {
+        let mut storage = state.storage.lock()?;
+        crate::backup::restore_backup(&mut storage, &state.db_path, &state.backup_dir, &name)?
+    };
+    crate::config::reload(&app, &state).map_err(|err| ClipItError::internal(format!("{err:#}")))?;
+    let _ = app.emit(
+        "backup:restored",
+        BackupRestoredEvent {
+            schema_version: ITEM_EVENT_SCHEMA_VERSION,
+            restored: name,
+            safety_backup: safety_backup.clone(),
+        },
+    );
+    Ok(safety_backup)
+}
+
+/// Compresses stored texts over `compress_text_above` bytes now rather than at
+/// the next start, and reports how much space that saved.
+#[tauri::command]
+pub fn compress_history(
+    state: State<'_, std::sync::Arc<SharedState>>,
+) -> Result<CompressionReport, ClipItError> {
+    let settings = state.settings.read()?.clone();
+    compress_long_texts(&state.readers, &state.storage, &settings)
+}
+
+/// The report from salvaging a damaged database at startup, once; later
+/// calls return `None`.
+#[tauri::command]
+pub fn take_recovery_report(
+    state: State<'_, std::sync::Arc<SharedState>>,
+) -> Result<Option<RecoveryReport>, ClipItError> {
+    Ok(state.recovery.lock()?.take())
 }
 
 #[tauri::command]
 pub fn search_items(
     state: State<'_, std::sync::Arc<SharedState>>,
-    query: String,
-    limit: u32,
-    offset: u32,
-    filter: Option<String>,
-) -> Result<SearchResponse, String> {
-    let storage = state.storage.lock().map_err(err_to_string)?;
-    storage
-        .search_items(&query, limit, offset, filter.as_deref().unwrap_or("all"))
-        .map_err(err_to_string)
+    params: SearchParams,
+) -> Result<SearchResponse, ClipItError> {
+    let storage = state.readers.get()?;
+    storage.search_items(&params)
 }
 
 #[tauri::command]
 pub fn get_item_preview(
     state: State<'_, std::sync::Arc<SharedState>>,
     item_id: i64,
-) -> Result<ItemPreview, String> {
-    let storage = state.storage.lock().map_err(err_to_string)?;
+) -> Result<ItemPreview, ClipItError> {
+    let storage = state.readers.get()?;
     storage
-        .get_item_preview(item_id)
-        .map_err(err_to_string)?
-        .ok_or_else(|| "item not found".to_string())
+        .get_item_preview(item_id)?
+        .ok_or(ClipItError::item_not_found(item_id))
+}
+
+/// Images that look like `item_id`, closest first. `max_distance` defaults
+/// to the `similar_image_distance` setting.
+#[tauri::command]
+pub fn find_similar_images(
+    state: State<'_, std::sync::Arc<SharedState>>,
+    item_id: i64,
+    max_distance: Option<u32>,
+) -> Result<Vec<SimilarImage>, ClipItError> {
+    let max_distance = match max_distance {
+        Some(distance) => distance,
+        None => state.settings.read()?.similar_image_distance as u32,
+    };
+    state
+        .readers
+        .get()?
+        .similar_images(item_id, max_distance.min(64), 50)
 }
 
 #[tauri::command]
 pub fn open_item_path(
     state: State<'_, std::sync::Arc<SharedState>>,
     item_id: i64,
-) -> Result<(), String> {
+) -> Result<(), ClipItError> {
     let payload = {
-        let storage = state.storage.lock().map_err(err_to_string)?;
-        storage
-            .get_item_clipboard_payload(item_id)
-            .map_err(err_to_string)?
+        let storage = state.readers.get()?;
+        storage.get_item_clipboard_payload(item_id)?
     }
-    .ok_or_else(|| "item not found".to_string())?;
+    .ok_or(ClipItError::item_not_found(item_id))?;
 
     if payload.kind != "file" {
-        return Err("item is not a file/folder path".to_string());
+        return Err(ClipItError::unsupported("item is not a file/folder path"));
     }
 
     let raw = payload.text.unwrap_or_default();
@@ -95,7 +282,10 @@ pub fn open_item_path(
         .lines()
         .map(str::trim)
         .find(|line| !line.is_empty())
-        .ok_or_else(|| "empty file path".to_string())?;
+        .ok_or(ClipItError::PayloadMissing {
+            item_id,
+            field: "file path",
+        })?;
     let path = normalize_path(first);
 
     #[cfg(target_os = "macos")]
@@ -103,21 +293,21 @@ pub fn open_item_path(
         std::process::Command::new("open")
             .arg(&path)
             .spawn()
-            .map_err(err_to_string)?;
+            .map_err(ClipItError::internal)?;
     }
     #[cfg(target_os = "windows")]
     {
         std::process::Command::new("explorer")
             .arg(&path)
             .spawn()
-            .map_err(err_to_string)?;
+            .map_err(ClipItError::internal)?;
     }
     #[cfg(target_os = "linux")]
     {
         std::process::Command::new("xdg-open")
             .arg(&path)
             .spawn()
-            .map_err(err_to_string)?;
+            .map_err(ClipItError::internal)?;
     }
 
     Ok(())
@@ -127,55 +317,303 @@ pub fn open_item_path(
 pub fn set_clipboard_item(
     state: State<'_, std::sync::Arc<SharedState>>,
     item_id: i64,
-) -> Result<(), String> {
+) -> Result<(), ClipItError> {
+    load_item_to_clipboard(&state, item_id)
+}
+
+/// Puts the item on the clipboard, hides the popup and pastes into the window
+/// that was focused before it opened. Returns `false` when there is no window to
+/// paste into (not on X11, or the popup was opened from our own window); the
+/// item is still on the clipboard in that case. While the paste stack is on, a
+/// paste consumes the item and then loads the next stack entry.
+#[tauri::command]
+pub fn paste_item(
+    app: AppHandle,
+    state: State<'_, std::sync::Arc<SharedState>>,
+    item_id: i64,
+) -> Result<bool, ClipItError> {
+    load_item_to_clipboard(&state, item_id)?;
+
+    if let Some(window) = app.get_webview_window("main") {
+        window.hide()?;
+    }
+
+    let Some(target) = state.paste_target.lock()?.take() else {
+        return Ok(false);
+    };
+    let overrides = state.settings.read()?.paste_overrides.clone();
+    let (stacked, removed) = {
+        let mut stack = state.paste_stack.lock()?;
+        let removed = stack.remove(item_id).then(|| stack.state());
+        (stack.is_active(), removed)
+    };
+    if let Some(snapshot) = removed {
+        crate::refresh_paste_stack_indicator(&app, &snapshot);
+    }
+
+    // Runs off the command thread so the popup can actually hide first.
+    let state = state.inner().clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(PASTE_HIDE_DELAY_MS));
+        if let Err(err) = crate::paste::paste_into(target, &overrides) {
+            eprintln!("direct paste failed: {err}");
+            return;
+        }
+        if stacked {
+            // Give the target a moment to read the clipboard before replacing it.
+            std::thread::sleep(std::time::Duration::from_millis(PASTE_HIDE_DELAY_MS));
+            if let Err(err) = advance_stack(&app, &state) {
+                eprintln!("failed to advance the paste stack: {err}");
+            }
+        }
+    });
+
+    Ok(true)
+}
+
+/// Copies the Nth most recent (or Nth pinned) item, for the slot hotkeys.
+pub fn copy_nth_item(state: &SharedState, n: u32, pinned_only: bool) -> Result<(), ClipItError> {
+    let item_id = {
+        let storage = state.readers.get()?;
+        storage.nth_recent_item_id(n, pinned_only)?
+    }
+    .ok_or(ClipItError::NotFound {
+        entity: "item",
+        id: None,
+    })?;
+    load_item_to_clipboard(state, item_id)
+}
+
+fn load_item_to_clipboard(state: &SharedState, item_id: i64) -> Result<(), ClipItError> {
     let payload = {
-        let storage = state.storage.lock().map_err(err_to_string)?;
-        storage
-            .get_item_clipboard_payload(item_id)
-            .map_err(err_to_string)?
+        let storage = state.readers.get()?;
+        storage.get_item_clipboard_payload(item_id)?
     }
-    .ok_or_else(|| "item not found".to_string())?;
+    .ok_or(ClipItError::item_not_found(item_id))?;
 
-    let fingerprint = match payload.kind.as_str() {
+    match payload.kind.as_str() {
         "image" => {
-            let rgba = payload
-                .image_rgba
-                .ok_or_else(|| "image payload missing".to_string())?;
-            let width = payload
-                .image_width
-                .ok_or_else(|| "image width missing".to_string())? as usize;
-            let height = payload
-                .image_height
-                .ok_or_else(|| "image height missing".to_string())? as usize;
-            set_clipboard_image(rgba.clone(), width, height).map_err(err_to_string)?;
-
-            let mut hasher = sha2::Sha256::new();
-            use sha2::Digest;
-            hasher.update(b"image:");
-            hasher.update((width as u64).to_le_bytes());
-            hasher.update((height as u64).to_le_bytes());
-            hasher.update(&rgba);
-            format!("{:x}", hasher.finalize())
+            let rgba = payload.image_rgba.ok_or(ClipItError::PayloadMissing {
+                item_id,
+                field: "image data",
+            })?;
+            let width = payload.image_width.ok_or(ClipItError::PayloadMissing {
+                item_id,
+                field: "image width",
+            })? as usize;
+            let height = payload.image_height.ok_or(ClipItError::PayloadMissing {
+                item_id,
+                field: "image height",
+            })? as usize;
+            if rgba.len() != width * height * 4 {
+                return Err(ClipItError::internal(format!(
+                    "item {item_id} image data doesn't match its {width}x{height} size"
+                )));
+            }
+            set_clipboard_image(rgba.clone(), width, height)?;
+
+            let written = image_fingerprint(width as u64, height as u64, &rgba);
+            let item_fingerprint = (written != payload.fingerprint).then_some(payload.fingerprint);
+            remember_written(state, written, item_fingerprint)
         }
         _ => {
             let text = payload.text.unwrap_or_default();
-            let normalized = normalize_text(&text);
-            if normalized.is_empty() {
-                return Ok(());
-            }
-            set_clipboard_text(&normalized).map_err(err_to_string)?;
-            sha256_hex(&format!("{}:{}", payload.kind, normalized))
+            write_text_to_clipboard(state, &text)
+        }
+    }
+}
+
+#[tauri::command]
+pub fn list_transforms() -> Vec<Transform> {
+    TRANSFORMS.to_vec()
+}
+
+/// Applies a quick-action transform to an item's text. `target` is `clipboard` to
+/// copy the result, or `new_item` to store it as a fresh history entry.
+#[tauri::command]
+pub fn transform_item(
+    app: AppHandle,
+    state: State<'_, std::sync::Arc<SharedState>>,
+    item_id: i64,
+    transform: String,
+    target: String,
+) -> Result<TransformOutput, ClipItError> {
+    let payload = {
+        let storage = state.readers.get()?;
+        storage.get_item_clipboard_payload(item_id)?
+    }
+    .ok_or(ClipItError::item_not_found(item_id))?;
+
+    if payload.kind == "image" {
+        return Err(ClipItError::unsupported(
+            "image items cannot be transformed",
+        ));
+    }
+
+    let text = apply_transform(&transform, &payload.text.unwrap_or_default())
+        .map_err(ClipItError::invalid_input)?;
+
+    let item_id = match target.as_str() {
+        "clipboard" => {
+            write_text_to_clipboard(&state, &text)?;
+            None
+        }
+        "new_item" => Some(save_text_item(&app, &state, &text)?),
+        other => {
+            return Err(ClipItError::invalid_input(format!(
+                "unknown transform target: {other}"
+            )))
         }
     };
 
-    let now = now_ms();
-    let mut guard = state.last_written.lock().map_err(err_to_string)?;
-    *guard = Some(crate::LastWritten {
-        fingerprint,
-        written_at_ms: now,
-    });
+    Ok(TransformOutput { text, item_id })
+}
 
-    Ok(())
+/// Combines several items, in the given order, into a new history item. File
+/// items merge into one multi-path file item; anything else becomes text joined
+/// by `separator` (`newline`, `space`, `comma`, or a literal string).
+#[tauri::command]
+pub fn merge_items(
+    app: AppHandle,
+    state: State<'_, std::sync::Arc<SharedState>>,
+    item_ids: Vec<i64>,
+    separator: String,
+    copy_to_clipboard: bool,
+    delete_sources: bool,
+) -> Result<MergeOutput, ClipItError> {
+    if item_ids.len() < 2 {
+        return Err(ClipItError::invalid_input(
+            "select at least two items to merge",
+        ));
+    }
+
+    let parts = {
+        let storage = state.readers.get()?;
+        item_ids
+            .iter()
+            .map(|&id| {
+                storage
+                    .get_item_clipboard_payload(id)?
+                    .ok_or(ClipItError::item_not_found(id))
+                    .map(|payload| (payload.kind, payload.text.unwrap_or_default()))
+            })
+            .collect::<Result<Vec<_>, ClipItError>>()?
+    };
+
+    let (kind, text) = merge_payloads(&parts, &merge_separator(&separator))?;
+    let replaced: &[i64] = if delete_sources { &item_ids } else { &[] };
+    let item_id = save_item_replacing(&app, &state, kind, &text, replaced)?;
+
+    if copy_to_clipboard {
+        write_text_to_clipboard(&state, &text)?;
+    }
+
+    Ok(MergeOutput {
+        item_id,
+        kind: kind.to_string(),
+        text,
+    })
+}
+
+#[tauri::command]
+pub fn update_item_text(
+    state: State<'_, std::sync::Arc<SharedState>>,
+    item_id: i64,
+    text: String,
+) -> Result<(), ClipItError> {
+    let settings = state.settings.read()?.clone();
+    let storage = state.storage.lock()?;
+    edit_item_text(&storage, &settings, item_id, &text)
+}
+
+#[tauri::command]
+pub fn list_item_revisions(
+    state: State<'_, std::sync::Arc<SharedState>>,
+    item_id: i64,
+) -> Result<Vec<ItemRevision>, ClipItError> {
+    let storage = state.readers.get()?;
+    storage.list_item_revisions(item_id)
+}
+
+/// Restores a revision's text. The text being replaced becomes a revision itself,
+/// so a revert can be undone the same way.
+#[tauri::command]
+pub fn revert_item_revision(
+    state: State<'_, std::sync::Arc<SharedState>>,
+    revision_id: i64,
+) -> Result<(), ClipItError> {
+    let settings = state.settings.read()?.clone();
+    let storage = state.storage.lock()?;
+    let revision = storage
+        .get_item_revision(revision_id)?
+        .ok_or(ClipItError::NotFound {
+            entity: "revision",
+            id: Some(revision_id),
+        })?;
+    edit_item_text(&storage, &settings, revision.item_id, &revision.text)
+}
+
+#[tauri::command]
+pub fn list_snippets(
+    state: State<'_, std::sync::Arc<SharedState>>,
+    query: Option<String>,
+) -> Result<Vec<Snippet>, ClipItError> {
+    let storage = state.readers.get()?;
+    storage.list_snippets(query.as_deref().unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn save_snippet(
+    state: State<'_, std::sync::Arc<SharedState>>,
+    snippet_id: Option<i64>,
+    name: String,
+    template: String,
+) -> Result<Snippet, ClipItError> {
+    validate_template(&template).map_err(ClipItError::invalid_input)?;
+    let storage = state.storage.lock()?;
+    storage.save_snippet(snippet_id, &name, &template)
+}
+
+#[tauri::command]
+pub fn delete_snippet(
+    state: State<'_, std::sync::Arc<SharedState>>,
+    snippet_id: i64,
+) -> Result<(), ClipItError> {
+    let storage = state.storage.lock()?;
+    storage.delete_snippet(snippet_id)
+}
+
+/// Expands a snippet's placeholders and puts the result on the clipboard.
+/// `inputs` supplies the `{input:Name}` values, keyed by name.
+#[tauri::command]
+pub fn render_snippet(
+    state: State<'_, std::sync::Arc<SharedState>>,
+    snippet_id: i64,
+    inputs: Option<HashMap<String, String>>,
+) -> Result<RenderedSnippet, ClipItError> {
+    let snippet = {
+        let storage = state.readers.get()?;
+        storage.get_snippet(snippet_id)?
+    }
+    .ok_or(ClipItError::NotFound {
+        entity: "snippet",
+        id: Some(snippet_id),
+    })?;
+
+    let clipboard = if uses_clipboard(&snippet.template) {
+        get_clipboard_text().ok()
+    } else {
+        None
+    };
+    let ctx = RenderContext {
+        now: chrono::Local::now(),
+        clipboard,
+        inputs: inputs.unwrap_or_default(),
+    };
+    let rendered = render_template(&snippet.template, &ctx).map_err(ClipItError::invalid_input)?;
+
+    write_text_to_clipboard(&state, &rendered.text)?;
+    Ok(rendered)
 }
 
 #[tauri::command]
@@ -183,9 +621,9 @@ pub fn favorite_item(
     state: State<'_, std::sync::Arc<SharedState>>,
     item_id: i64,
     favorite: bool,
-) -> Result<(), String> {
-    let storage = state.storage.lock().map_err(err_to_string)?;
-    storage.set_favorite(item_id, favorite).map_err(err_to_string)
+) -> Result<(), ClipItError> {
+    let storage = state.storage.lock()?;
+    storage.set_favorite(item_id, favorite)
 }
 
 #[tauri::command]
@@ -193,37 +631,148 @@ pub fn pin_item(
     state: State<'_, std::sync::Arc<SharedState>>,
     item_id: i64,
     pinned: bool,
-) -> Result<(), String> {
-    let storage = state.storage.lock().map_err(err_to_string)?;
-    storage.pin_item(item_id, pinned).map_err(err_to_string)
+) -> Result<(), ClipItError> {
+    let storage = state.storage.lock()?;
+    storage.pin_item(item_id, pinned)
+}
+
+#[tauri::command]
+pub fn list_tags(state: State<'_, std::sync::Arc<SharedState>>) -> Result<Vec<Tag>, ClipItError> {
+    let storage = state.readers.get()?;
+    storage.list_tags()
+}
+
+#[tauri::command]
+pub fn create_tag(
+    state: State<'_, std::sync::Arc<SharedState>>,
+    name: String,
+) -> Result<Tag, ClipItError> {
+    let storage = state.storage.lock()?;
+    storage.create_tag(&name)
+}
+
+#[tauri::command]
+pub fn rename_tag(
+    state: State<'_, std::sync::Arc<SharedState>>,
+    tag_id: i64,
+    name: String,
+) -> Result<(), ClipItError> {
+    let storage = state.storage.lock()?;
+    storage.rename_tag(tag_id, &name)
+}
+
+#[tauri::command]
+pub fn delete_tag(
+    state: State<'_, std::sync::Arc<SharedState>>,
+    tag_id: i64,
+) -> Result<(), ClipItError> {
+    let storage = state.storage.lock()?;
+    storage.delete_tag(tag_id)
+}
+
+#[tauri::command]
+pub fn tag_item(
+    state: State<'_, std::sync::Arc<SharedState>>,
+    item_id: i64,
+    tag_id: i64,
+    tagged: bool,
+) -> Result<(), ClipItError> {
+    let storage = state.storage.lock()?;
+    storage.set_item_tag(item_id, tag_id, tagged)
+}
+
+#[tauri::command]
+pub fn list_collections(
+    state: State<'_, std::sync::Arc<SharedState>>,
+) -> Result<Vec<Collection>, ClipItError> {
+    let storage = state.readers.get()?;
+    storage.list_collections()
+}
+
+#[tauri::command]
+pub fn create_collection(
+    state: State<'_, std::sync::Arc<SharedState>>,
+    name: String,
+) -> Result<Collection, ClipItError> {
+    let storage = state.storage.lock()?;
+    storage.create_collection(&name)
+}
+
+#[tauri::command]
+pub fn rename_collection(
+    state: State<'_, std::sync::Arc<SharedState>>,
+    collection_id: i64,
+    name: String,
+) -> Result<(), ClipItError> {
+    let storage = state.storage.lock()?;
+    storage.rename_collection(collection_id, &name)
+}
+
+#[tauri::command]
+pub fn delete_collection(
+    state: State<'_, std::sync::Arc<SharedState>>,
+    collection_id: i64,
+) -> Result<(), ClipItError> {
+    let storage = state.storage.lock()?;
+    storage.delete_collection(collection_id)
+}
+
+#[tauri::command]
+pub fn add_to_collection(
+    state: State<'_, std::sync::Arc<SharedState>>,
+    collection_id: i64,
+    item_id: i64,
+) -> Result<(), ClipItError> {
+    let storage = state.storage.lock()?;
+    storage.add_to_collection(collection_id, item_id)
+}
+
+#[tauri::command]
+pub fn remove_from_collection(
+    state: State<'_, std::sync::Arc<SharedState>>,
+    collection_id: i64,
+    item_id: i64,
+) -> Result<(), ClipItError> {
+    let storage = state.storage.lock()?;
+    storage.remove_from_collection(collection_id, item_id)
+}
+
+#[tauri::command]
+pub fn reorder_collection(
+    state: State<'_, std::sync::Arc<SharedState>>,
+    collection_id: i64,
+    item_ids: Vec<i64>,
+) -> Result<(), ClipItError> {
+    let storage = state.storage.lock()?;
+    storage.reorder_collection(collection_id, &item_ids)
 }
 
 #[tauri::command]
 pub fn delete_item(
     state: State<'_, std::sync::Arc<SharedState>>,
     item_id: i64,
-) -> Result<(), String> {
-    let storage = state.storage.lock().map_err(err_to_string)?;
-    storage.delete_item(item_id).map_err(err_to_string)
+) -> Result<(), ClipItError> {
+    let storage = state.storage.lock()?;
+    storage.delete_item(item_id)
 }
 
 #[tauri::command]
-pub fn clear_history(state: State<'_, std::sync::Arc<SharedState>>) -> Result<(), String> {
-    let storage = state.storage.lock().map_err(err_to_string)?;
-    storage.clear_history().map_err(err_to_string)
+pub fn clear_history(state: State<'_, std::sync::Arc<SharedState>>) -> Result<(), ClipItError> {
+    let storage = state.storage.lock()?;
+    storage.clear_history()
 }
 
 #[tauri::command]
-pub fn clear_all_history(state: State<'_, std::sync::Arc<SharedState>>) -> Result<(), String> {
-    let storage = state.storage.lock().map_err(err_to_string)?;
-    storage.clear_all_history().map_err(err_to_string)
+pub fn clear_all_history(state: State<'_, std::sync::Arc<SharedState>>) -> Result<(), ClipItError> {
+    let storage = state.storage.lock()?;
+    storage.clear_all_history()
 }
 
 #[tauri::command]
 pub fn toggle_pause_capture(
     app: AppHandle,
     state: State<'_, std::sync::Arc<SharedState>>,
-) -> Result<PauseState, String> {
+) -> Result<PauseState, ClipItError> {
     let next = !state.paused.load(Ordering::Relaxed);
     state.paused.store(next, Ordering::Relaxed);
 
@@ -236,34 +785,100 @@ pub fn toggle_pause_capture(
 }
 
 #[tauri::command]
-pub fn open_settings_window(app: AppHandle) -> Result<(), String> {
-    if let Some(window) = app.get_webview_window("settings") {
-        window.show().map_err(err_to_string)?;
-        window.set_focus().map_err(err_to_string)?;
-    }
-    Ok(())
+pub fn start_paste_stack(
+    app: AppHandle,
+    state: State<'_, std::sync::Arc<SharedState>>,
+    order: Option<StackOrder>,
+) -> Result<PasteStackState, ClipItError> {
+    let snapshot = {
+        let mut stack = state.paste_stack.lock()?;
+        stack.start(order.unwrap_or_default());
+        stack.state()
+    };
+    crate::refresh_paste_stack_indicator(&app, &snapshot);
+    Ok(snapshot)
 }
 
-pub fn parse_shortcut(shortcut: &str) -> Option<Shortcut> {
-    let s = shortcut.trim().to_lowercase();
-    let mut mods = Modifiers::empty();
-    let mut code: Option<Code> = None;
+#[tauri::command]
+pub fn stop_paste_stack(
+    app: AppHandle,
+    state: State<'_, std::sync::Arc<SharedState>>,
+) -> Result<PasteStackState, ClipItError> {
+    let snapshot = {
+        let mut stack = state.paste_stack.lock()?;
+        stack.stop();
+        stack.state()
+    };
+    crate::refresh_paste_stack_indicator(&app, &snapshot);
+    Ok(snapshot)
+}
+
+#[tauri::command]
+pub fn get_paste_stack(
+    state: State<'_, std::sync::Arc<SharedState>>,
+) -> Result<PasteStackState, ClipItError> {
+    Ok(state.paste_stack.lock()?.state())
+}
 
-    for part in s.split('+').map(|p| p.trim()) {
-        match part {
-            "ctrl" | "control" => mods |= Modifiers::CONTROL,
-            "shift" => mods |= Modifiers::SHIFT,
-            "alt" | "option" => mods |= Modifiers::ALT,
-            "cmd" | "command" | "super" => mods |= Modifiers::SUPER,
-            key => code = key_code_from_token(key).or(code),
+/// Ends collecting and loads the first entry onto the clipboard, ready for the
+/// first paste: while collecting, the clipboard holds the latest copy instead.
+#[tauri::command]
+pub fn finish_paste_stack(
+    app: AppHandle,
+    state: State<'_, std::sync::Arc<SharedState>>,
+) -> Result<PasteStackState, ClipItError> {
+    advance_stack(&app, &state)
+}
+
+/// Consumes the next stack entry by loading it onto the clipboard. Entries whose
+/// item was deleted or pruned in the meantime are skipped. Direct pastes advance
+/// on their own; this is for pastes the app can't see. Advancing while still
+/// collecting finishes collecting first, as `finish_paste_stack` does.
+#[tauri::command]
+pub fn advance_paste_stack(
+    app: AppHandle,
+    state: State<'_, std::sync::Arc<SharedState>>,
+) -> Result<PasteStackState, ClipItError> {
+    advance_stack(&app, &state)
+}
+
+fn advance_stack(app: &AppHandle, state: &SharedState) -> Result<PasteStackState, ClipItError> {
+    state.paste_stack.lock()?.finish_collecting();
+    loop {
+        let next = state.paste_stack.lock()?.pop_next();
+        let Some(item_id) = next else {
+            break;
+        };
+        match load_item_to_clipboard(state, item_id) {
+            Ok(()) => break,
+            Err(ClipItError::NotFound { .. }) => continue,
+            Err(err) => return Err(err),
         }
     }
 
-    code.map(|c| Shortcut::new(Some(mods), c))
+    let snapshot = state.paste_stack.lock()?.state();
+    crate::refresh_paste_stack_indicator(app, &snapshot);
+    Ok(snapshot)
+}
+
+#[tauri::command]
+pub fn open_settings_window(app: AppHandle) -> Result<(), ClipItError> {
+    if let Some(window) = app.get_webview_window("settings") {
+        window.show()?;
+        window.set_focus()?;
+    }
+    Ok(())
 }
 
 pub fn show_popup_window(app: &AppHandle) {
     if let Some(window) = app.get_webview_window("main") {
+        if !window.is_visible().unwrap_or(false) {
+            if let Some(state) = app.try_state::<std::sync::Arc<SharedState>>() {
+                if let Ok(mut target) = state.paste_target.lock() {
+                    *target = crate::paste::focused_foreign_window();
+                }
+            }
+        }
         let _ = window.set_always_on_top(true);
         let _ = window.show();
         let popup_size = match window.outer_size() {
@@ -292,6 +907,185 @@ pub fn show_popup_window(app: &AppHandle) {
     }
 }
 
+fn edit_item_text(
+    storage: &Storage,
+    settings: &Settings,
+    item_id: i64,
+    text: &str,
+) -> Result<(), ClipItError> {
+    let kind = storage
+        .get_item_clipboard_payload(item_id)?
+        .ok_or(ClipItError::item_not_found(item_id))?
+        .kind;
+    if kind == "image" {
+        return Err(ClipItError::unsupported("image items cannot be edited"));
+    }
+
+    let normalized = normalize_text(text);
+    if normalized.is_empty() {
+        return Err(ClipItError::invalid_input("item text cannot be empty"));
+    }
+    let class = (kind == "text").then(|| classify_text(&normalized));
+    let updated = storage.update_item_text(
+        item_id,
+        &normalized,
+        &sha256_hex(&format!("{kind}:{normalized}")),
+        class.map(|c| c.subtype),
+        class.and_then(|c| c.language),
+        settings,
+    )?;
+
+    if updated {
+        Ok(())
+    } else {
+        Err(ClipItError::item_not_found(item_id))
+    }
+}
+
+/// Writes `text` and remembers it under the kind capture will read it back as,
+/// whatever the item it came from: a transformed or merged text that looks
+/// like paths comes back as `file`.
+fn write_text_to_clipboard(state: &SharedState, text: &str) -> Result<(), ClipItError> {
+    let normalized = normalize_text(text);
+    if normalized.is_empty() {
+        return Ok(());
+    }
+    set_clipboard_text(&normalized)?;
+    let kind = text_kind(&normalized);
+    remember_written(state, sha256_hex(&format!("{kind}:{normalized}")), None)
+}
+
+/// Records what we just wrote so the capture loop doesn't re-import it.
+fn remember_written(
+    state: &SharedState,
+    fingerprint: String,
+    item_fingerprint: Option<String>,
+) -> Result<(), ClipItError> {
+    let mut guard = state.last_written.lock()?;
+    *guard = Some(crate::LastWritten {
+        fingerprint,
+        written_at_ms: now_ms(),
+        item_fingerprint,
+    });
+    Ok(())
+}
+
+/// Stores text produced by the app itself (transforms, merges) as a new history
+/// item, with the same classification and retention as captured text.
+fn save_text_item(app: &AppHandle, state: &SharedState, text: &str) -> Result<i64, ClipItError> {
+    save_item(app, state, "text", text)
+}
+
+/// Like `save_text_item`, for any text-backed kind (`text` or `file`). Only
+/// plain text is classified, matching the capture pipeline.
+fn save_item(
+    app: &AppHandle,
+    state: &SharedState,
+    kind: &str,
+    text: &str,
+) -> Result<i64, ClipItError> {
+    save_item_replacing(app, state, kind, text, &[])
+}
+
+/// `save_item` that also deletes the `replaced` items, in the same transaction
+/// so a failure keeps either all of them or none.
+fn save_item_replacing(
+    app: &AppHandle,
+    state: &SharedState,
+    kind: &str,
+    text: &str,
+    replaced: &[i64],
+) -> Result<i64, ClipItError> {
+    let normalized = normalize_text(text);
+    if normalized.is_empty() {
+        return Err(ClipItError::invalid_input("result is empty"));
+    }
+    let class = (kind == "text").then(|| classify_text(&normalized));
+    let subtype = class.map(|c| c.subtype);
+    let settings = state.settings.read()?.clone();
+    let fingerprint = sha256_hex(&format!("{kind}:{normalized}"));
+    let stored = StoredText::new(normalized.clone(), &settings)?;
+    let created_at = now_ms();
+
+    let id = {
+        let storage = state.storage.lock()?;
+        storage.batch(|| {
+            let id = storage.insert_item(&NewItem {
+                created_at: Some(created_at),
+                kind,
+                subtype,
+                language: class.and_then(|c| c.language),
+                text: Some(&stored.text),
+                text_zstd: stored.zstd.as_deref(),
+                search_text: stored.search_text.as_deref(),
+                fingerprint: &fingerprint,
+                ..Default::default()
+            })?;
+            for &replaced_id in replaced {
+                storage.delete_item(replaced_id)?;
+            }
+            storage.enforce_max_items(settings.max_items)?;
+            Ok(id)
+        })?
+    };
+
+    let _ = app.emit(
+        "clipboard:item_added",
+        ClipboardItemAddedEvent {
+            schema_version: ITEM_EVENT_SCHEMA_VERSION,
+            id,
+            preview_text: event_preview_text(kind, &normalized),
+            subtype: subtype.map(str::to_string),
+            created_at,
+            pinned: false,
+        },
+    );
+
+    Ok(id)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeOutput {
+    pub item_id: i64,
+    pub kind: String,
+    pub text: String,
+}
+
+fn merge_separator(separator: &str) -> String {
+    match separator {
+        "newline" => "\n".to_string(),
+        "space" => " ".to_string(),
+        "comma" => ", ".to_string(),
+        custom => custom.to_string(),
+    }
+}
+
+/// Joins `(kind, text)` parts. File parts are always newline-separated paths,
+/// with duplicates dropped, so the result is still a valid file payload.
+fn merge_payloads(
+    parts: &[(String, String)],
+    separator: &str,
+) -> Result<(&'static str, String), ClipItError> {
+    if parts.iter().any(|(kind, _)| kind == "image") {
+        return Err(ClipItError::unsupported("image items cannot be merged"));
+    }
+
+    if parts.iter().all(|(kind, _)| kind == "file") {
+        let mut paths: Vec<&str> = Vec::new();
+        for line in parts.iter().flat_map(|(_, text)| text.lines()) {
+            let line = line.trim();
+            if !line.is_empty() && !paths.contains(&line) {
+                paths.push(line);
+            }
+        }
+        return Ok(("file", paths.join("\n")));
+    }
+
+    let texts: Vec<&str> = parts.iter().map(|(_, text)| text.as_str()).collect();
+    Ok(("text", texts.join(separator)))
+}
+
 fn now_ms() -> i64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -299,42 +1093,6 @@ fn now_ms() -> i64 {
         .as_millis() as i64
 }
 
-fn err_to_string<E: std::fmt::Display>(e: E) -> String {
-    e.to_string()
-}
-
-fn key_code_from_token(token: &str) -> Option<Code> {
-    match token {
-        "a" => Some(Code::KeyA),
-        "b" => Some(Code::KeyB),
-        "c" => Some(Code::KeyC),
-        "d" => Some(Code::KeyD),
-        "e" => Some(Code::KeyE),
-        "f" => Some(Code::KeyF),
-        "g" => Some(Code::KeyG),
-        "h" => Some(Code::KeyH),
-        "i" => Some(Code::KeyI),
-        "j" => Some(Code::KeyJ),
-        "k" => Some(Code::KeyK),
-        "l" => Some(Code::KeyL),
-        "m" => Some(Code::KeyM),
-        "n" => Some(Code::KeyN),
-        "o" => Some(Code::KeyO),
-        "p" => Some(Code::KeyP),
-        "q" => Some(Code::KeyQ),
-        "r" => Some(Code::KeyR),
-        "s" => Some(Code::KeyS),
-        "t" => Some(Code::KeyT),
-        "u" => Some(Code::KeyU),
-        "v" => Some(Code::KeyV),
-        "w" => Some(Code::KeyW),
-        "x" => Some(Code::KeyX),
-        "y" => Some(Code::KeyY),
-        "z" => Some(Code::KeyZ),
-        _ => None,
-    }
-}
-
 fn normalize_path(input: &str) -> String {
     if let Some(rest) = input.strip_prefix("file://") {
         return rest.replace("%20", " ");
@@ -344,7 +1102,14 @@ fn normalize_path(input: &str) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::normalize_path;
+    use super::{merge_payloads, merge_separator, normalize_path};
+
+    fn parts(items: &[(&str, &str)]) -> Vec<(String, String)> {
+        items
+            .iter()
+            .map(|(kind, text)| (kind.to_string(), text.to_string()))
+            .collect()
+    }
 
     #[test]
     fn normalize_path_decodes_file_url() {
@@ -357,4 +1122,27 @@ mod tests {
         let out = normalize_path("/tmp/file.txt");
         assert_eq!(out, "/tmp/file.txt");
     }
+
+    #[test]
+    fn merge_joins_text_with_separator() {
+        let items = parts(&[("text", "alpha"), ("file", "/tmp/a"), ("text", "gamma")]);
+        assert_eq!(
+            merge_payloads(&items, &merge_separator("comma")),
+            Ok(("text", "alpha, /tmp/a, gamma".to_string()))
+        );
+        assert_eq!(
+            merge_payloads(&items, &merge_separator(" | ")),
+            Ok(("text", "alpha | /tmp/a | gamma".to_string()))
+        );
+    }
+
+    #[test]
+    fn merge_combines_file_paths() {
+        let items = parts(&[("file", "/tmp/a\n/tmp/b"), ("file", "/tmp/b\n/tmp/c")]);
+        assert_eq!(
+            merge_payloads(&items, " "),
+            Ok(("file", "/tmp/a\n/tmp/b\n/tmp/c".to_string()))
+        );
+        assert!(merge_payloads(&parts(&[("text", "x"), ("image", "")]), "\n").is_err());
+    }
 }
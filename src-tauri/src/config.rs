@@ -0,0 +1,319 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use notify::{RecursiveMode, Watcher};
+use serde_json::Value;
+use tauri::{AppHandle, Emitter};
+
+use crate::events::SettingsChangedEvent;
+use crate::settings::{SettingValueError, Settings, SETTING_SPECS};
+use crate::SharedState;
+
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Editors often write a file in several steps; wait for them to settle.
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Reads `config.toml` into `(key, value)` pairs. A missing file is empty.
+pub fn read_config(path: &Path) -> Result<Vec<(String, Value)>> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => {
+            parse_config(&text).with_context(|| format!("failed to parse {}", path.display()))
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+pub fn parse_config(text: &str) -> Result<Vec<(String, Value)>> {
+    let table: toml::Table = toml::from_str(text)?;
+    table
+        .into_iter()
+        .map(|(key, value)| Ok((key, serde_json::to_value(value)?)))
+        .collect()
+}
+
+/// Applies file values over `settings`. Bad entries are skipped and returned so
+/// one typo doesn't discard the rest of the file.
+pub fn layer_config(
+    settings: &mut Settings,
+    entries: &[(String, Value)],
+) -> Vec<SettingValueError> {
+    entries
+        .iter()
+        .filter_map(|(key, value)| settings.apply(key, value).err())
+        .collect()
+}
+
+/// Current settings as a commented `config.toml`, in schema order.
+pub fn render_config(settings: &Settings) -> Result<String> {
+    let values = serde_json::to_value(settings)?;
+    let mut out =
+        String::from("# Clip It settings. Values here override the ones saved from the app.\n");
+
+    for spec in SETTING_SPECS {
+        let Some(value) = values.get(spec.key) else {
+            continue;
+        };
+        let value = toml::Value::try_from(value)
+            .with_context(|| format!("setting {} has no TOML form", spec.key))?;
+        out.push_str(&format!("\n# {}\n", spec.description));
+        if let (Some(min), Some(max)) = (spec.min, spec.max) {
+            out.push_str(&format!("# Range: {min}..{max}\n"));
+        }
+        out.push_str(&format!("{} = {}\n", spec.key, value));
+    }
+
+    Ok(out)
+}
+
+/// Writes `render_config` to `path`, replacing it atomically.
+pub fn write_config(path: &Path, settings: &Settings) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("toml.tmp");
+    std::fs::write(&tmp, render_config(settings)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Writes `value` for `key` into `config.toml` when the file already sets
+/// that key, so a change made in the app isn't undone by the file on the next
+/// reload. Comments, formatting and the other keys are kept. Returns whether
+/// the file was rewritten.
+pub fn write_config_value(path: &Path, key: &str, value: &Value) -> Result<bool> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err).with_context(|| format!("failed to read {}", path.display())),
+    };
+    let mut doc: toml_edit::DocumentMut = text
+        .parse()
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+    let Some(item) = doc.get_mut(key) else {
+        return Ok(false);
+    };
+
+    let mut replacement: toml_edit::Value = toml::Value::try_from(value)
+        .with_context(|| format!("setting {key} has no TOML form"))?
+        .to_string()
+        .parse()?;
+    if let Some(existing) = item.as_value() {
+        *replacement.decor_mut() = existing.decor().clone();
+    }
+    *item = toml_edit::Item::Value(replacement);
+
+    let tmp = path.with_extension("toml.tmp");
+    std::fs::write(&tmp, doc.to_string())?;
+    std::fs::rename(&tmp, path)?;
+    Ok(true)
+}
+
+/// Rebuilds settings from the database plus `config.toml` and applies whatever
+/// changed: shortcuts are re-registered, and every window is told about each
+/// changed key. The polling loops read the interval on every tick.
+pub fn reload(app: &AppHandle, state: &SharedState) -> Result<()> {
+    let mut next = {
+        let storage = state
+            .storage
+            .lock()
+            .map_err(|_| anyhow::anyhow!("storage poisoned"))?;
+        storage.load_settings()?
+    };
+    for err in layer_config(&mut next, &read_config(&state.config_path)?) {
+        eprintln!("{CONFIG_FILE_NAME}: {err}");
+    }
+
+    let previous = state
+        .settings
+        .read()
+        .map_err(|_| anyhow::anyhow!("settings poisoned"))?
+        .clone();
+
+    let hotkeys_changed = next.hotkey != previous.hotkey
+        || next.recent_hotkey_prefix != previous.recent_hotkey_prefix
+        || next.pinned_hotkey_prefix != previous.pinned_hotkey_prefix;
+    if hotkeys_changed {
+        if let Err(err) = crate::register_global_shortcuts(app, &next) {
+            eprintln!("{CONFIG_FILE_NAME}: keeping previous shortcuts: {err}");
+            next.hotkey = previous.hotkey.clone();
+            next.recent_hotkey_prefix = previous.recent_hotkey_prefix.clone();
+            next.pinned_hotkey_prefix = previous.pinned_hotkey_prefix.clone();
+        }
+    }
+
+    if next.max_items != previous.max_items {
+        let storage = state
+            .storage
+            .lock()
+            .map_err(|_| anyhow::anyhow!("storage poisoned"))?;
+        storage.enforce_max_items(next.max_items)?;
+    }
+
+    *state
+        .settings
+        .write()
+        .map_err(|_| anyhow::anyhow!("settings poisoned"))? = next.clone();
+    if next.compressed_search != previous.compressed_search {
+        crate::apply_compressed_search(state);
+    }
+
+    let before = serde_json::to_value(&previous)?;
+    let after = serde_json::to_value(&next)?;
+    for spec in SETTING_SPECS {
+        if before.get(spec.key) != after.get(spec.key) {
+            let _ = app.emit(
+                "settings:changed",
+                SettingsChangedEvent {
+                    key: spec.key.to_string(),
+                    value: after.get(spec.key).cloned().unwrap_or(Value::Null),
+                    settings: next.clone(),
+                },
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Watches the config directory (not the file, which editors replace) and
+/// reloads when `config.toml` changes.
+pub fn start_watcher(app: AppHandle, state: Arc<SharedState>) {
+    let path: PathBuf = state.config_path.clone();
+    let Some(dir) = path.parent().map(Path::to_path_buf) else {
+        return;
+    };
+
+    thread::spawn(move || {
+        if let Err(err) = std::fs::create_dir_all(&dir) {
+            eprintln!("config watcher disabled: {err}");
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel::<notify::Result<notify::Event>>();
+        let mut watcher = match notify::recommended_watcher(tx) {
+            Ok(watcher) => watcher,
+            Err(err) => {
+                eprintln!("config watcher disabled: {err}");
+                return;
+            }
+        };
+        if let Err(err) = watcher.watch(&dir, RecursiveMode::NonRecursive) {
+            eprintln!("config watcher disabled: {err}");
+            return;
+        }
+
+        let touches_config = |event: &notify::Result<notify::Event>| {
+            event
+                .as_ref()
+                .is_ok_and(|e| e.paths.iter().any(|p| p == &path))
+        };
+
+        while let Ok(event) = rx.recv() {
+            if !touches_config(&event) {
+                continue;
+            }
+            // Drain the burst of events from a single save.
+            while rx.recv_timeout(RELOAD_DEBOUNCE).is_ok() {}
+            if let Err(err) = reload(&app, &state) {
+                eprintln!("failed to reload {CONFIG_FILE_NAME}: {err}");
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{layer_config, parse_config, render_config, write_config_value};
+    use crate::settings::Settings;
+
+    #[test]
+    fn file_values_layer_over_settings() {
+        let entries = parse_config(
+            r#"
+hotkey = "alt+space"
+max_items = 2
+bogus = true
+window_opacity = "high"
+
+[paste_overrides]
+Kitty = "ctrl+shift+v"
+"#,
+        )
+        .expect("parse");
+
+        let mut settings = Settings::default();
+        let errors = layer_config(&mut settings, &entries);
+
+        assert_eq!(settings.hotkey, "Alt+Space");
+        assert_eq!(settings.max_items, 10);
+        assert_eq!(settings.paste_overrides["kitty"], "ctrl+shift+v");
+        assert_eq!(settings.window_opacity, Settings::default().window_opacity);
+        assert_eq!(errors.len(), 2);
+        assert!(parse_config("hotkey = ").is_err());
+    }
+
+    #[test]
+    fn rendered_config_round_trips() {
+        let mut settings = Settings::default();
+        settings.apply("max_items", &json!(250)).expect("valid");
+        settings
+            .apply("recent_hotkey_prefix", &json!("Ctrl+Alt"))
+            .expect("valid");
+        settings
+            .apply("paste_overrides", &json!({"xterm": "shift+insert"}))
+            .expect("valid");
+
+        let text = render_config(&settings).expect("render");
+        assert!(text.contains("# Range: 10..5000\nmax_items = 250\n"));
+
+        let mut loaded = Settings::default();
+        let errors = layer_config(&mut loaded, &parse_config(&text).expect("parse"));
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(
+            serde_json::to_value(&loaded).expect("json"),
+            serde_json::to_value(&settings).expect("json")
+        );
+    }
+
+    #[test]
+    fn app_changes_are_written_to_keys_the_file_sets() {
+        let dir = crate::test_support::temp_dir("config-write");
+        let path = dir.join("config.toml");
+        assert!(!write_config_value(&path, "max_items", &json!(20)).expect("missing file"));
+
+        std::fs::write(
+            &path,
+            "# mine\nmax_items = 10 # keep small\n\n[paste_overrides]\nkitty = \"ctrl+v\"\n",
+        )
+        .expect("write");
+        assert!(write_config_value(&path, "max_items", &json!(250)).expect("write value"));
+        assert!(!write_config_value(&path, "hotkey", &json!("Alt+V")).expect("absent key"));
+        assert!(
+            write_config_value(&path, "paste_overrides", &json!({"xterm": "shift+insert"}))
+                .expect("write table")
+        );
+
+        let text = std::fs::read_to_string(&path).expect("read");
+        assert!(
+            text.starts_with("# mine\nmax_items = 250 # keep small\n"),
+            "{text}"
+        );
+        let entries = parse_config(&text).expect("parse");
+        let mut settings = Settings::default();
+        assert!(layer_config(&mut settings, &entries).is_empty());
+        assert_eq!(settings.max_items, 250);
+        assert_eq!(settings.hotkey, Settings::default().hotkey);
+        assert_eq!(settings.paste_overrides.len(), 1);
+        assert_eq!(settings.paste_overrides["xterm"], "shift+insert");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
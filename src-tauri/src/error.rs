@@ -0,0 +1,378 @@
+use std::fmt;
+use std::sync::PoisonError;
+
+use rusqlite::ErrorCode;
+use serde::{Serialize, Serializer};
+
+use crate::settings::SettingValueError;
+use crate::shortcut::ShortcutError;
+
+pub type Result<T, E = ClipItError> = std::result::Result<T, E>;
+
+/// Error returned by every command. It reaches the UI as
+/// `{ "code": "not_found", "entity": "item", "id": 4, "message": "..." }`:
+/// `code` and the context fields are stable, `message` is for display only.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(remote = "Self", tag = "code", rename_all = "snake_case")]
+pub enum ClipItError {
+    /// An item, snippet, tag or collection that doesn't exist (or was deleted).
+    NotFound {
+        entity: &'static str,
+        id: Option<i64>,
+    },
+    /// A name that must be unique is already taken.
+    AlreadyExists {
+        entity: &'static str,
+        name: String,
+    },
+    /// A stored item lacks data it needs to be used, e.g. an image without pixels.
+    PayloadMissing {
+        item_id: i64,
+        field: &'static str,
+    },
+    /// Arguments the command can't act on: empty text, a bad template or regex.
+    InvalidInput {
+        reason: String,
+    },
+    /// The action doesn't apply to this item kind or platform.
+    Unsupported {
+        reason: String,
+    },
+    InvalidSetting {
+        key: String,
+        reason: String,
+    },
+    InvalidShortcut {
+        shortcut: String,
+        reason: String,
+    },
+    /// The OS refused to register a shortcut, usually because it's taken.
+    ShortcutConflict {
+        shortcut: String,
+        reason: String,
+    },
+    /// Another connection holds the database lock; retrying may succeed.
+    StorageLocked,
+    SearchTimeout {
+        timeout_ms: u64,
+    },
+    StorageFailure {
+        reason: String,
+    },
+    ClipboardUnavailable {
+        reason: String,
+    },
+    Internal {
+        reason: String,
+    },
+}
+
+impl ClipItError {
+    pub fn item_not_found(item_id: i64) -> Self {
+        Self::NotFound {
+            entity: "item",
+            id: Some(item_id),
+        }
+    }
+
+    pub fn invalid_input(reason: impl fmt::Display) -> Self {
+        Self::InvalidInput {
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn unsupported(reason: impl fmt::Display) -> Self {
+        Self::Unsupported {
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn storage(reason: impl fmt::Display) -> Self {
+        Self::StorageFailure {
+            reason: reason.to_string(),
+        }
+    }
+
+    pub fn internal(reason: impl fmt::Display) -> Self {
+        Self::Internal {
+            reason: reason.to_string(),
+        }
+    }
+
+    /// The stable code sent to the UI.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound { .. } => "not_found",
+            Self::AlreadyExists { .. } => "already_exists",
+            Self::PayloadMissing { .. } => "payload_missing",
+            Self::InvalidInput { .. } => "invalid_input",
+            Self::Unsupported { .. } => "unsupported",
+            Self::InvalidSetting { .. } => "invalid_setting",
+            Self::InvalidShortcut { .. } => "invalid_shortcut",
+            Self::ShortcutConflict { .. } => "shortcut_conflict",
+            Self::StorageLocked => "storage_locked",
+            Self::SearchTimeout { .. } => "search_timeout",
+            Self::StorageFailure { .. } => "storage_failure",
+            Self::ClipboardUnavailable { .. } => "clipboard_unavailable",
+            Self::Internal { .. } => "internal",
+        }
+    }
+}
+
+impl fmt::Display for ClipItError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound { entity, .. } => write!(f, "{entity} not found"),
+            Self::AlreadyExists { entity, name } => write!(f, "{entity} \"{name}\" already exists"),
+            Self::PayloadMissing { field, .. } => write!(f, "item is missing its {field}"),
+            Self::InvalidInput { reason } | Self::Unsupported { reason } => f.write_str(reason),
+            Self::InvalidSetting { key, reason } => {
+                write!(f, "invalid setting \"{key}\": {reason}")
+            }
+            Self::InvalidShortcut { shortcut, reason } => {
+                write!(f, "invalid shortcut \"{shortcut}\": {reason}")
+            }
+            Self::ShortcutConflict { shortcut, reason } => {
+                write!(f, "shortcut \"{shortcut}\" is unavailable: {reason}")
+            }
+            Self::StorageLocked => f.write_str("database is locked, try again"),
+            Self::SearchTimeout { timeout_ms } => {
+                write!(f, "search timed out after {timeout_ms}ms")
+            }
+            Self::StorageFailure { reason } => write!(f, "storage error: {reason}"),
+            Self::ClipboardUnavailable { reason } => write!(f, "clipboard unavailable: {reason}"),
+            Self::Internal { reason } => f.write_str(reason),
+        }
+    }
+}
+
+impl std::error::Error for ClipItError {}
+
+/// Serializes the derived, `code`-tagged form plus the display `message`.
+impl Serialize for ClipItError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Wire<'a> {
+            #[serde(flatten, serialize_with = "detail")]
+            detail: &'a ClipItError,
+            message: String,
+        }
+
+        fn detail<S: Serializer>(
+            err: &&ClipItError,
+            serializer: S,
+        ) -> std::result::Result<S::Ok, S::Error> {
+            ClipItError::serialize(err, serializer)
+        }
+
+        Wire {
+            detail: self,
+            message: self.to_string(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl From<rusqlite::Error> for ClipItError {
+    fn from(err: rusqlite::Error) -> Self {
+        match err.sqlite_error_code() {
+            Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked) => Self::StorageLocked,
+            _ => Self::storage(err),
+        }
+    }
+}
+
+impl From<arboard::Error> for ClipItError {
+    fn from(err: arboard::Error) -> Self {
+        Self::ClipboardUnavailable {
+            reason: err.to_string(),
+        }
+    }
+}
+
+impl From<SettingValueError> for ClipItError {
+    fn from(err: SettingValueError) -> Self {
+        let (key, reason) = match err {
+            SettingValueError::UnknownKey { key } => (key, "unknown setting".to_string()),
+            SettingValueError::WrongType { key, expected } => {
+                (key, format!("expects a {expected:?} value"))
+            }
+            SettingValueError::InvalidValue { key, reason } => (key, reason),
+        };
+        Self::InvalidSetting { key, reason }
+    }
+}
+
+impl From<ShortcutError> for ClipItError {
+    fn from(err: ShortcutError) -> Self {
+        match err {
+            ShortcutError::Invalid { shortcut, reason } => {
+                Self::InvalidShortcut { shortcut, reason }
+            }
+            ShortcutError::Conflict { shortcut, message } => Self::ShortcutConflict {
+                shortcut,
+                reason: message,
+            },
+            ShortcutError::Internal { message } => Self::internal(message),
+        }
+    }
+}
+
+impl From<std::io::Error> for ClipItError {
+    fn from(err: std::io::Error) -> Self {
+        Self::internal(err)
+    }
+}
+
+impl From<tauri::Error> for ClipItError {
+    fn from(err: tauri::Error) -> Self {
+        Self::internal(err)
+    }
+}
+
+impl<T> From<PoisonError<T>> for ClipItError {
+    fn from(_: PoisonError<T>) -> Self {
+        Self::internal("app state is poisoned")
+    }
+}
+
+/// `anyhow::Context` for storage calls: prefixes the reason of a storage
+/// failure and leaves every other code untouched.
+pub trait Context<T> {
+    fn context(self, what: &str) -> Result<T>;
+}
+
+impl<T, E: Into<ClipItError>> Context<T> for std::result::Result<T, E> {
+    fn context(self, what: &str) -> Result<T> {
+        self.map_err(|err| match err.into() {
+            ClipItError::StorageFailure { reason } => {
+                ClipItError::storage(format!("{what}: {reason}"))
+            }
+            other => other,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::ffi;
+    use serde_json::json;
+
+    use super::{ClipItError, Context};
+    use crate::settings::SettingValueError;
+    use crate::shortcut::ShortcutError;
+
+    fn sqlite_error(code: i32) -> rusqlite::Error {
+        rusqlite::Error::SqliteFailure(ffi::Error::new(code), None)
+    }
+
+    #[test]
+    fn serializes_code_context_and_message() {
+        assert_eq!(
+            serde_json::to_value(ClipItError::item_not_found(7)).expect("json"),
+            json!({"code": "not_found", "entity": "item", "id": 7, "message": "item not found"})
+        );
+        assert_eq!(
+            serde_json::to_value(ClipItError::StorageLocked).expect("json"),
+            json!({"code": "storage_locked", "message": "database is locked, try again"})
+        );
+        assert_eq!(
+            serde_json::to_value(ClipItError::PayloadMissing {
+                item_id: 3,
+                field: "image width"
+            })
+            .expect("json"),
+            json!({
+                "code": "payload_missing",
+                "item_id": 3,
+                "field": "image width",
+                "message": "item is missing its image width"
+            })
+        );
+    }
+
+    #[test]
+    fn every_variant_reports_its_serialized_code() {
+        let errors = [
+            ClipItError::item_not_found(1),
+            ClipItError::AlreadyExists {
+                entity: "tag",
+                name: "work".to_string(),
+            },
+            ClipItError::PayloadMissing {
+                item_id: 1,
+                field: "image data",
+            },
+            ClipItError::invalid_input("empty"),
+            ClipItError::unsupported("images"),
+            ClipItError::InvalidSetting {
+                key: "max_items".to_string(),
+                reason: "too big".to_string(),
+            },
+            ClipItError::InvalidShortcut {
+                shortcut: "Ctrl".to_string(),
+                reason: "no key".to_string(),
+            },
+            ClipItError::ShortcutConflict {
+                shortcut: "Ctrl+V".to_string(),
+                reason: "taken".to_string(),
+            },
+            ClipItError::StorageLocked,
+            ClipItError::SearchTimeout { timeout_ms: 250 },
+            ClipItError::storage("disk I/O error"),
+            ClipItError::ClipboardUnavailable {
+                reason: "no display".to_string(),
+            },
+            ClipItError::internal("oops"),
+        ];
+
+        for err in errors {
+            let value = serde_json::to_value(&err).expect("json");
+            assert_eq!(value["code"], err.code());
+            assert_eq!(value["message"], err.to_string());
+        }
+    }
+
+    #[test]
+    fn sqlite_lock_errors_are_retryable() {
+        assert_eq!(
+            ClipItError::from(sqlite_error(ffi::SQLITE_BUSY)),
+            ClipItError::StorageLocked
+        );
+        assert_eq!(
+            ClipItError::from(sqlite_error(ffi::SQLITE_LOCKED)),
+            ClipItError::StorageLocked
+        );
+        assert_eq!(
+            ClipItError::from(sqlite_error(ffi::SQLITE_IOERR)).code(),
+            "storage_failure"
+        );
+
+        let locked: Result<(), _> = Err(sqlite_error(ffi::SQLITE_BUSY));
+        assert_eq!(locked.context("saving"), Err(ClipItError::StorageLocked));
+        let failed: Result<(), _> = Err(ClipItError::storage("disk full"));
+        assert_eq!(
+            failed.context("saving"),
+            Err(ClipItError::storage("saving: disk full"))
+        );
+    }
+
+    #[test]
+    fn setting_and_shortcut_errors_map_to_codes() {
+        let unknown = ClipItError::from(SettingValueError::UnknownKey {
+            key: "bogus".to_string(),
+        });
+        assert_eq!(unknown.code(), "invalid_setting");
+
+        let conflict = ClipItError::from(ShortcutError::Conflict {
+            shortcut: "Ctrl+V".to_string(),
+            message: "taken".to_string(),
+        });
+        assert_eq!(conflict.code(), "shortcut_conflict");
+
+        let poisoned = ClipItError::from(ShortcutError::Internal {
+            message: "hotkey state is poisoned".to_string(),
+        });
+        assert_eq!(poisoned.code(), "internal");
+    }
+}
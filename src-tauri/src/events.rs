@@ -1,10 +1,23 @@
 use serde::Serialize;
+use serde_json::Value;
+
+use crate::backup::BackupInfo;
+use crate::clipboard::LimitAction;
+use crate::import::ImportSource;
+use crate::settings::Settings;
+use crate::storage::SearchItem;
+
+/// Version of the `clipboard:item_*` payloads, sent as `schemaVersion`. Bump it
+/// when a field is removed or changes meaning; adding fields doesn't need it.
+pub const ITEM_EVENT_SCHEMA_VERSION: u32 = 1;
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ClipboardItemAddedEvent {
+    pub schema_version: u32,
     pub id: i64,
     pub preview_text: String,
+    pub subtype: Option<String>,
     pub created_at: i64,
     pub pinned: bool,
 }
@@ -13,3 +26,129 @@ pub struct ClipboardItemAddedEvent {
 pub struct ClipboardPausedChangedEvent {
     pub paused: bool,
 }
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PasteStackChangedEvent {
+    pub active: bool,
+    pub depth: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SettingsChangedEvent {
+    pub key: String,
+    pub value: Value,
+    pub settings: Settings,
+}
+
+/// What an `ItemUpdatedEvent` changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemChange {
+    Favorite,
+    Pinned,
+    Text,
+    Tags,
+}
+
+/// The item as a list row after the change, so windows can patch it in place.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemUpdatedEvent {
+    pub schema_version: u32,
+    pub change: ItemChange,
+    pub item: SearchItem,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemDeletedEvent {
+    pub schema_version: u32,
+    pub id: i64,
+}
+
+/// `all` is false for "clear history", which keeps pinned, favorite, tagged and
+/// collected items; `ids` lists what was actually removed either way.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryClearedEvent {
+    pub schema_version: u32,
+    pub all: bool,
+    pub ids: Vec<i64>,
+}
+
+/// Items dropped by the `max_items` retention limit.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemsPrunedEvent {
+    pub schema_version: u32,
+    pub max_items: i64,
+    pub ids: Vec<i64>,
+}
+
+/// Older near-duplicates of a new image, removed by `collapse_similar_images`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImagesCollapsedEvent {
+    pub schema_version: u32,
+    /// The new image they were collapsed into.
+    pub kept: i64,
+    pub ids: Vec<i64>,
+}
+
+/// Sent once after an import instead of one `clipboard:item_added` per item.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemsImportedEvent {
+    pub schema_version: u32,
+    pub source: ImportSource,
+    pub imported: u32,
+}
+
+/// Sent after a backup replaced the live database, so windows reload
+/// everything they have cached.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupRestoredEvent {
+    pub schema_version: u32,
+    pub restored: String,
+    /// Snapshot of the database as it was just before the restore.
+    pub safety_backup: BackupInfo,
+}
+
+/// Sent when a capture was over its size limit, so the user knows why it's
+/// missing or cut short.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureLimitedEvent {
+    pub schema_version: u32,
+    pub kind: String,
+    /// Text bytes or image pixels, as captured.
+    pub size: u64,
+    /// `max_text_bytes` or `max_image_pixels` at the time.
+    pub limit: u64,
+    pub action: LimitAction,
+}
+
+/// Raised by `Storage` mutations, whichever window or background task caused
+/// them; see `Storage::set_event_sink`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum StorageEvent {
+    ItemUpdated(ItemUpdatedEvent),
+    ItemDeleted(ItemDeletedEvent),
+    HistoryCleared(HistoryClearedEvent),
+    ItemsPruned(ItemsPrunedEvent),
+    ImagesCollapsed(ImagesCollapsedEvent),
+}
+
+impl StorageEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ItemUpdated(_) => "clipboard:item_updated",
+            Self::ItemDeleted(_) => "clipboard:item_deleted",
+            Self::HistoryCleared(_) => "clipboard:history_cleared",
+            Self::ItemsPruned(_) => "clipboard:items_pruned",
+            Self::ImagesCollapsed(_) => "clipboard:images_collapsed",
+        }
+    }
+}
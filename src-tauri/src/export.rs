@@ -0,0 +1,536 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ClipItError, Result};
+use crate::images::encode_png;
+use crate::storage::{ExportItem, SearchParams, Storage};
+
+/// `format` field of a JSON archive; importers check it before anything else.
+pub const ARCHIVE_FORMAT: &str = "clip-it-history";
+/// Bumped when the archive layout changes incompatibly.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Json,
+    Csv,
+    Markdown,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Csv => "csv",
+            Self::Markdown => "md",
+        }
+    }
+}
+
+/// How JSON and Markdown exports carry image pixels. CSV leaves images out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageMode {
+    /// PNG files in a `<name>_images` folder next to the export.
+    #[default]
+    Files,
+    /// PNG data inlined as base64.
+    Base64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSummary {
+    pub path: String,
+    pub format: ExportFormat,
+    pub exported: u32,
+    /// Items the format can't hold, i.e. images in a CSV export.
+    pub skipped: u32,
+}
+
+/// One item in a JSON archive.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveItem {
+    pub id: i64,
+    pub created_at: i64,
+    pub kind: String,
+    pub subtype: Option<String>,
+    pub language: Option<String>,
+    pub text: Option<String>,
+    pub favorite: bool,
+    pub pinned: bool,
+    pub tags: Vec<String>,
+    pub image: Option<ArchiveImage>,
+}
+
+/// Image pixels as PNG, either in a file (relative to the archive) or inline.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveImage {
+    pub width: i64,
+    pub height: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub png_base64: Option<String>,
+}
+
+/// Writes every item matching `params` to `path`. The file is written next to
+/// its destination and renamed into place, so a failed export never leaves a
+/// truncated file behind.
+pub fn export_history(
+    storage: &Storage,
+    params: &SearchParams,
+    format: ExportFormat,
+    images: ImageMode,
+    path: &Path,
+) -> Result<ExportSummary> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension(format!("{}.tmp", format.extension()));
+    let out = BufWriter::new(File::create(&tmp)?);
+
+    let written = match format {
+        ExportFormat::Json => stream(
+            storage,
+            params,
+            JsonWriter::new(out, ImageSink::new(path, images))?,
+        ),
+        ExportFormat::Csv => stream(storage, params, CsvWriter::new(out)?),
+        ExportFormat::Markdown => stream(
+            storage,
+            params,
+            MarkdownWriter::new(out, ImageSink::new(path, images))?,
+        ),
+    };
+    let (exported, skipped) = match written {
+        Ok(counts) => counts,
+        Err(err) => {
+            let _ = fs::remove_file(&tmp);
+            return Err(err);
+        }
+    };
+    fs::rename(&tmp, path)?;
+
+    Ok(ExportSummary {
+        path: path.display().to_string(),
+        format,
+        exported,
+        skipped,
+    })
+}
+
+/// Default export location: `clipit-history-<timestamp>.<ext>` in `dir`.
+pub fn default_export_path(dir: &Path, format: ExportFormat) -> PathBuf {
+    dir.join(format!(
+        "clipit-history-{}.{}",
+        Local::now().format("%Y%m%d-%H%M%S"),
+        format.extension()
+    ))
+}
+
+trait ExportWriter {
+    /// Writes one item; `false` means the format skipped it.
+    fn item(&mut self, item: &ExportItem) -> Result<bool>;
+    fn finish(self) -> Result<()>;
+}
+
+fn stream(
+    storage: &Storage,
+    params: &SearchParams,
+    mut writer: impl ExportWriter,
+) -> Result<(u32, u32)> {
+    let (mut exported, mut skipped) = (0, 0);
+    storage.for_each_export_item(params, |item| {
+        if writer.item(&item)? {
+            exported += 1;
+        } else {
+            skipped += 1;
+        }
+        Ok(())
+    })?;
+    writer.finish()?;
+    Ok((exported, skipped))
+}
+
+/// Where image pixels go for the JSON and Markdown writers.
+struct ImageSink {
+    mode: ImageMode,
+    /// Folder for `ImageMode::Files`, and its name as referenced from the export.
+    dir: PathBuf,
+    dir_name: String,
+}
+
+impl ImageSink {
+    fn new(export_path: &Path, mode: ImageMode) -> Self {
+        let stem = export_path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "clipit-history".to_string());
+        let dir_name = format!("{stem}_images");
+        Self {
+            mode,
+            dir: export_path.with_file_name(&dir_name),
+            dir_name,
+        }
+    }
+
+    fn store(&self, item: &ExportItem) -> Result<Option<ArchiveImage>> {
+        let (Some(rgba), Some(width), Some(height)) =
+            (&item.image_rgba, item.image_width, item.image_height)
+        else {
+            return Ok(None);
+        };
+        let (Ok(w), Ok(h)) = (u32::try_from(width), u32::try_from(height)) else {
+            return Err(ClipItError::internal(format!(
+                "image size {width}x{height} is out of range"
+            )));
+        };
+        let png = encode_png(rgba, w, h)?;
+
+        let (file, png_base64) = match self.mode {
+            ImageMode::Files => {
+                fs::create_dir_all(&self.dir)?;
+                let name = format!("{}.png", item.id);
+                fs::write(self.dir.join(&name), png)?;
+                (Some(format!("{}/{name}", self.dir_name)), None)
+            }
+            ImageMode::Base64 => (
+                None,
+                Some(base64::engine::general_purpose::STANDARD.encode(png)),
+            ),
+        };
+        Ok(Some(ArchiveImage {
+            width,
+            height,
+            file,
+            png_base64,
+        }))
+    }
+}
+
+/// `{"format": ..., "version": 1, "exportedAt": ..., "items": [...]}`, written
+/// item by item rather than built as one value.
+struct JsonWriter<W: Write> {
+    out: W,
+    images: ImageSink,
+    first: bool,
+}
+
+impl<W: Write> JsonWriter<W> {
+    fn new(mut out: W, images: ImageSink) -> Result<Self> {
+        write!(
+            out,
+            "{{\"format\":\"{ARCHIVE_FORMAT}\",\"version\":{ARCHIVE_VERSION},\"exportedAt\":{},\"items\":[",
+            Local::now().timestamp_millis()
+        )?;
+        Ok(Self {
+            out,
+            images,
+            first: true,
+        })
+    }
+}
+
+impl<W: Write> ExportWriter for JsonWriter<W> {
+    fn item(&mut self, item: &ExportItem) -> Result<bool> {
+        let archived = ArchiveItem {
+            id: item.id,
+            created_at: item.created_at,
+            kind: item.kind.clone(),
+            subtype: item.subtype.clone(),
+            language: item.language.clone(),
+            text: item.text.clone(),
+            favorite: item.favorite,
+            pinned: item.pinned,
+            tags: item.tags.clone(),
+            image: self.images.store(item)?,
+        };
+        if !self.first {
+            self.out.write_all(b",")?;
+        }
+        self.first = false;
+        self.out.write_all(b"\n")?;
+        serde_json::to_writer(&mut self.out, &archived).map_err(ClipItError::internal)?;
+        Ok(true)
+    }
+
+    fn finish(mut self) -> Result<()> {
+        self.out.write_all(b"\n]}\n")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+struct CsvWriter<W: Write> {
+    out: csv::Writer<W>,
+}
+
+impl<W: Write> CsvWriter<W> {
+    const HEADER: [&'static str; 8] = [
+        "id",
+        "created_at",
+        "kind",
+        "subtype",
+        "favorite",
+        "pinned",
+        "tags",
+        "text",
+    ];
+
+    fn new(out: W) -> Result<Self> {
+        let mut out = csv::Writer::from_writer(out);
+        out.write_record(Self::HEADER)
+            .map_err(ClipItError::internal)?;
+        Ok(Self { out })
+    }
+}
+
+impl<W: Write> ExportWriter for CsvWriter<W> {
+    fn item(&mut self, item: &ExportItem) -> Result<bool> {
+        if item.kind == "image" {
+            return Ok(false);
+        }
+        self.out
+            .write_record([
+                item.id.to_string(),
+                format_timestamp(item.created_at),
+                item.kind.clone(),
+                item.subtype.clone().unwrap_or_default(),
+                item.favorite.to_string(),
+                item.pinned.to_string(),
+                item.tags.join("; "),
+                item.text.clone().unwrap_or_default(),
+            ])
+            .map_err(ClipItError::internal)?;
+        Ok(true)
+    }
+
+    fn finish(mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+struct MarkdownWriter<W: Write> {
+    out: W,
+    images: ImageSink,
+}
+
+impl<W: Write> MarkdownWriter<W> {
+    fn new(mut out: W, images: ImageSink) -> Result<Self> {
+        writeln!(
+            out,
+            "# Clip It history\n\nExported {}.",
+            Local::now().format("%Y-%m-%d %H:%M")
+        )?;
+        Ok(Self { out, images })
+    }
+}
+
+impl<W: Write> ExportWriter for MarkdownWriter<W> {
+    fn item(&mut self, item: &ExportItem) -> Result<bool> {
+        let kind = match &item.subtype {
+            Some(subtype) => format!("{} ({subtype})", item.kind),
+            None => item.kind.clone(),
+        };
+        writeln!(
+            self.out,
+            "\n## {} · {kind}\n",
+            format_timestamp(item.created_at)
+        )?;
+
+        let mut flags = Vec::new();
+        if item.pinned {
+            flags.push("pinned".to_string());
+        }
+        if item.favorite {
+            flags.push("favorite".to_string());
+        }
+        if !item.tags.is_empty() {
+            flags.push(format!("tags: {}", item.tags.join(", ")));
+        }
+        if !flags.is_empty() {
+            writeln!(self.out, "*{}*\n", flags.join(" · "))?;
+        }
+
+        if let Some(image) = self.images.store(item)? {
+            let src = match (image.file, image.png_base64) {
+                (Some(file), _) => file,
+                (None, Some(data)) => format!("data:image/png;base64,{data}"),
+                (None, None) => String::new(),
+            };
+            writeln!(self.out, "![image {}x{}]({src})", image.width, image.height)?;
+        } else {
+            let text = item.text.as_deref().unwrap_or_default();
+            let fence = code_fence(text);
+            let language = item.language.as_deref().unwrap_or_default();
+            writeln!(self.out, "{fence}{language}\n{text}\n{fence}")?;
+        }
+        Ok(true)
+    }
+
+    fn finish(mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// A backtick fence longer than any backtick run inside `text`.
+fn code_fence(text: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or_default();
+    "`".repeat(longest.max(2) + 1)
+}
+
+fn format_timestamp(ms: i64) -> String {
+    DateTime::from_timestamp_millis(ms)
+        .map(|t| {
+            t.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::{
+        code_fence, export_history, ArchiveItem, ExportFormat, ImageMode, ARCHIVE_VERSION,
+    };
+    use crate::storage::{NewItem, SearchParams, Storage};
+    use crate::test_support::temp_dir;
+
+    fn sample_storage(dir: &std::path::Path) -> Storage {
+        let storage = Storage::open(&dir.join("history.db")).expect("open db");
+        let note = storage
+            .insert_item(&NewItem {
+                kind: "text",
+                text: Some("say \"hi\", then\n```run```"),
+                fingerprint: "fp-note",
+                ..Default::default()
+            })
+            .expect("insert note");
+        storage
+            .insert_item(&NewItem {
+                kind: "image",
+                text: Some("image://1x1"),
+                fingerprint: "fp-image",
+                image_rgba: Some(&[255, 0, 0, 255]),
+                image_width: Some(1),
+                image_height: Some(1),
+                ..Default::default()
+            })
+            .expect("insert image");
+        storage.set_favorite(note, true).expect("favorite");
+        let tag = storage.create_tag("work").expect("tag");
+        storage.set_item_tag(note, tag.id, true).expect("tag note");
+        storage
+    }
+
+    #[test]
+    fn json_archive_keeps_flags_tags_and_images() {
+        let dir = temp_dir("json");
+        let storage = sample_storage(&dir);
+        let path = dir.join("out.json");
+
+        let summary = export_history(
+            &storage,
+            &SearchParams::default(),
+            ExportFormat::Json,
+            ImageMode::Files,
+            &path,
+        )
+        .expect("export");
+        assert_eq!((summary.exported, summary.skipped), (2, 0));
+
+        let archive: Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).expect("read")).expect("json");
+        assert_eq!(archive["version"], ARCHIVE_VERSION);
+        let items: Vec<ArchiveItem> =
+            serde_json::from_value(archive["items"].clone()).expect("items");
+        assert!(items[0].favorite);
+        assert_eq!(items[0].tags, vec!["work".to_string()]);
+        let image = items[1].image.as_ref().expect("image");
+        let file = dir.join(image.file.as_ref().expect("image file"));
+        assert!(std::fs::read(file).expect("png").starts_with(b"\x89PNG"));
+
+        let inline = dir.join("inline.json");
+        export_history(
+            &storage,
+            &SearchParams {
+                filter: Some("favorites".to_string()),
+                ..Default::default()
+            },
+            ExportFormat::Json,
+            ImageMode::Base64,
+            &inline,
+        )
+        .expect("filtered export");
+        let archive: Value =
+            serde_json::from_str(&std::fs::read_to_string(&inline).expect("read")).expect("json");
+        assert_eq!(archive["items"].as_array().map(Vec::len), Some(1));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn csv_skips_images_and_markdown_fences_text() {
+        let dir = temp_dir("csv-md");
+        let storage = sample_storage(&dir);
+
+        let csv_path = dir.join("out.csv");
+        let summary = export_history(
+            &storage,
+            &SearchParams::default(),
+            ExportFormat::Csv,
+            ImageMode::Files,
+            &csv_path,
+        )
+        .expect("csv export");
+        assert_eq!((summary.exported, summary.skipped), (1, 1));
+        let mut reader = csv::Reader::from_path(&csv_path).expect("csv");
+        let rows: Vec<csv::StringRecord> = reader.records().map(|r| r.expect("row")).collect();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(&rows[0][7], "say \"hi\", then\n```run```");
+        assert_eq!(&rows[0][6], "work");
+
+        let md_path = dir.join("out.md");
+        export_history(
+            &storage,
+            &SearchParams {
+                query: "then".to_string(),
+                ..Default::default()
+            },
+            ExportFormat::Markdown,
+            ImageMode::Files,
+            &md_path,
+        )
+        .expect("markdown export");
+        let markdown = std::fs::read_to_string(&md_path).expect("read");
+        assert!(markdown.contains("*favorite · tags: work*"));
+        assert!(markdown.contains("````\nsay \"hi\""));
+        assert!(!markdown.contains("![image"));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn fences_outgrow_backtick_runs() {
+        assert_eq!(code_fence("plain"), "```");
+        assert_eq!(code_fence("a ```` b"), "`````");
+    }
+}
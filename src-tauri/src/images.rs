@@ -0,0 +1,630 @@
+//! The stored-image policy: downscale to `image_max_dimension` and re-encode
+//! as `image_encoding`, at capture or, with `image_keep_original_hours`, later
+//! on a schedule. Fingerprints always describe the image as captured, so a
+//! shrunk item still deduplicates against a fresh copy of the original.
+//! Perceptual hashes find images that look alike without matching exactly.
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use chrono::Local;
+use image::codecs::png::PngEncoder;
+use image::imageops::{self, FilterType};
+use image::{ExtendedColorType, ImageBuffer, ImageEncoder, Rgba, RgbaImage};
+
+use crate::error::{ClipItError, Result};
+use crate::settings::{ImageEncoding, Settings};
+use crate::SharedState;
+
+/// How often the scheduler looks for images whose original has been kept long enough.
+const SCHEDULE_TICK: Duration = Duration::from_secs(5 * 60);
+
+/// Images decoded and re-encoded (or hashed) per reader query.
+const SHRINK_BATCH: usize = 8;
+
+/// Side of the grey thumbnail `perceptual_hash` compares: one column more
+/// than rows, so each of the 8 rows yields 8 neighbour comparisons.
+const HASH_WIDTH: u32 = 9;
+const HASH_HEIGHT: u32 = 8;
+
+/// An image as it goes into the database: raw pixels or encoded bytes, and
+/// the size either of them decodes to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredImage {
+    pub rgba: Option<Vec<u8>>,
+    pub encoded: Option<Vec<u8>>,
+    /// The `image_quality` a WebP `encoded` was written at.
+    pub quality: Option<i64>,
+    pub width: i64,
+    pub height: i64,
+}
+
+impl StoredImage {
+    pub fn raw(rgba: Vec<u8>, width: i64, height: i64) -> Self {
+        Self {
+            rgba: Some(rgba),
+            encoded: None,
+            quality: None,
+            width,
+            height,
+        }
+    }
+}
+
+/// Whether the settings ask for anything but full-size raw pixels.
+pub fn policy_enabled(settings: &Settings) -> bool {
+    settings.image_max_dimension > 0 || settings.image_encoding != ImageEncoding::Raw
+}
+
+/// Applies the image policy to RGBA pixels; `None` when it leaves them as they are.
+pub fn apply_image_policy(
+    rgba: &[u8],
+    width: i64,
+    height: i64,
+    settings: &Settings,
+) -> Result<Option<StoredImage>> {
+    let (Ok(w), Ok(h)) = (u32::try_from(width), u32::try_from(height)) else {
+        return Err(ClipItError::invalid_input(format!(
+            "image size {width}x{height} is out of range"
+        )));
+    };
+    let target = scaled_size(w, h, settings.image_max_dimension);
+    if target.is_none() && settings.image_encoding == ImageEncoding::Raw {
+        return Ok(None);
+    }
+
+    let (pixels, w, h) = match target {
+        Some((nw, nh)) => {
+            let source = RgbaImage::from_raw(w, h, rgba.to_vec()).ok_or_else(|| {
+                ClipItError::invalid_input(format!(
+                    "image data doesn't match its {width}x{height} size"
+                ))
+            })?;
+            let resized = imageops::resize(&source, nw, nh, FilterType::Lanczos3);
+            (resized.into_raw(), nw, nh)
+        }
+        None => (rgba.to_vec(), w, h),
+    };
+
+    let encoded = match settings.image_encoding {
+        ImageEncoding::Raw => None,
+        ImageEncoding::Png => Some(encode_png(&pixels, w, h)?),
+        ImageEncoding::Webp => Some(encode_webp(&pixels, w, h, settings.image_quality)?),
+    };
+    Ok(Some(StoredImage {
+        rgba: encoded.is_none().then_some(pixels),
+        encoded,
+        quality: (settings.image_encoding == ImageEncoding::Webp).then_some(settings.image_quality),
+        width: w.into(),
+        height: h.into(),
+    }))
+}
+
+/// The size a capture of `width`x`height` is stored at: downscaled at once
+/// unless `image_keep_original_hours` defers the policy.
+pub fn stored_size(width: i64, height: i64, settings: &Settings) -> (i64, i64) {
+    let (Ok(w), Ok(h)) = (u32::try_from(width), u32::try_from(height)) else {
+        return (width, height);
+    };
+    if settings.image_keep_original_hours > 0 {
+        return (width, height);
+    }
+    scaled_size(w, h, settings.image_max_dimension)
+        .map_or((width, height), |(w, h)| (i64::from(w), i64::from(h)))
+}
+
+/// The size an image is downscaled to so its longest side is `max_dimension`,
+/// keeping its aspect ratio; `None` when it already fits.
+fn scaled_size(width: u32, height: u32, max_dimension: i64) -> Option<(u32, u32)> {
+    let longest = width.max(height);
+    if max_dimension <= 0 || i64::from(longest) <= max_dimension {
+        return None;
+    }
+    let scale = |side: u32| {
+        let scaled =
+            (u64::from(side) * max_dimension as u64 + u64::from(longest) / 2) / u64::from(longest);
+        (scaled as u32).max(1)
+    };
+    Some((scale(width), scale(height)))
+}
+
+pub fn encode_png(rgba: &[u8], width: u32, height: u32) -> Result<Vec<u8>> {
+    if rgba.len() as u64 != u64::from(width) * u64::from(height) * 4 {
+        return Err(ClipItError::invalid_input(format!(
+            "image data doesn't match its {width}x{height} size"
+        )));
+    }
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(rgba, width, height, ExtendedColorType::Rgba8)
+        .map_err(|err| ClipItError::internal(format!("failed to encode image: {err}")))?;
+    Ok(png)
+}
+
+/// Lossless at quality 100, lossy below.
+fn encode_webp(rgba: &[u8], width: u32, height: u32, quality: i64) -> Result<Vec<u8>> {
+    let lossless = quality >= 100;
+    webp::Encoder::from_rgba(rgba, width, height)
+        .encode_simple(lossless, quality.clamp(1, 100) as f32)
+        .map(|webp| webp.to_vec())
+        .map_err(|err| ClipItError::internal(format!("failed to encode image: {err:?}")))
+}
+
+/// 64-bit difference hash (dHash) of RGBA pixels: the image shrunk to a 9×8
+/// grey thumbnail, one bit per horizontal neighbour pair that gets brighter.
+/// Rescaling, re-encoding and small edits flip only a few bits, so near
+/// duplicates are a short Hamming distance apart. `None` when the pixels
+/// don't match the size.
+pub fn perceptual_hash(rgba: &[u8], width: i64, height: i64) -> Option<i64> {
+    let (width, height) = (u32::try_from(width).ok()?, u32::try_from(height).ok()?);
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let source = ImageBuffer::<Rgba<u8>, &[u8]>::from_raw(width, height, rgba)?;
+    let thumbnail = imageops::resize(&source, HASH_WIDTH, HASH_HEIGHT, FilterType::Triangle);
+    let grey = |x: u32, y: u32| {
+        let [r, g, b, _] = thumbnail.get_pixel(x, y).0;
+        299 * u32::from(r) + 587 * u32::from(g) + 114 * u32::from(b)
+    };
+
+    let mut hash = 0_u64;
+    for y in 0..HASH_HEIGHT {
+        for x in 0..HASH_WIDTH - 1 {
+            hash = (hash << 1) | u64::from(grey(x, y) < grey(x + 1, y));
+        }
+    }
+    // Stored as SQLite's signed 64-bit INTEGER; only the bits matter.
+    Some(hash as i64)
+}
+
+/// Bits that differ between two perceptual hashes.
+pub fn hash_distance(a: i64, b: i64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Applies the image policy to images captured more than
+/// `image_keep_original_hours` ago. Settings are read on every tick, so
+/// tightening the policy also shrinks images captured before the change:
+/// a smaller `image_max_dimension`, PNG or WebP over raw pixels, WebP over
+/// PNG, or a lower `image_quality`. Changes that wouldn't store an image
+/// smaller, such as back to raw or PNG, leave stored images alone.
+/// Also hashes images stored before perceptual hashes were computed.
+pub fn start_scheduler(state: Arc<SharedState>) {
+    thread::spawn(move || loop {
+        if let Err(err) = hash_stored_images(&state) {
+            eprintln!("hashing stored images failed: {err}");
+        }
+        if let Err(err) = shrink_stored_images(&state) {
+            eprintln!("shrinking stored images failed: {err}");
+        }
+        thread::sleep(SCHEDULE_TICK);
+    });
+}
+
+/// Computes the perceptual hash of every image item that lacks one.
+pub fn hash_stored_images(state: &SharedState) -> Result<usize> {
+    let mut hashed = 0;
+    let mut after_id = 0;
+    loop {
+        let batch = state
+            .readers
+            .get()?
+            .images_without_hash(after_id, SHRINK_BATCH)?;
+        let Some(last) = batch.last() else {
+            return Ok(hashed);
+        };
+        after_id = last.id;
+
+        for image in batch {
+            let rgba = match image.rgba {
+                Ok(rgba) => rgba,
+                Err(err) => {
+                    eprintln!("can't hash image {}: {err}", image.id);
+                    continue;
+                }
+            };
+            if let Some(hash) = perceptual_hash(&rgba, image.width, image.height) {
+                state.storage.lock()?.set_image_hash(image.id, hash)?;
+                hashed += 1;
+            }
+        }
+    }
+}
+
+/// Shrinks every stored image the policy applies to and returns how many.
+/// Images are decoded and encoded on a reader; the writer only takes the updates.
+pub fn shrink_stored_images(state: &SharedState) -> Result<usize> {
+    let settings = state.settings.read()?.clone();
+    if !policy_enabled(&settings) {
+        return Ok(0);
+    }
+    let captured_before =
+        Local::now().timestamp_millis() - settings.image_keep_original_hours * 60 * 60 * 1000;
+
+    let mut shrunk = 0;
+    let mut after_id = 0;
+    loop {
+        let batch = state.readers.get()?.images_to_shrink(
+            captured_before,
+            settings.image_max_dimension,
+            settings.image_encoding,
+            settings.image_quality,
+            after_id,
+            SHRINK_BATCH,
+        )?;
+        let Some(last) = batch.last() else {
+            return Ok(shrunk);
+        };
+        after_id = last.id;
+
+        for image in batch {
+            // A bad item is skipped rather than stopping every later tick here.
+            let policy = image
+                .rgba
+                .and_then(|rgba| apply_image_policy(&rgba, image.width, image.height, &settings));
+            let stored = match policy {
+                Ok(Some(stored)) => stored,
+                Ok(None) => continue,
+                Err(err) => {
+                    eprintln!("can't shrink image {}: {err}", image.id);
+                    continue;
+                }
+            };
+            let replaced = state.storage.lock()?.replace_item_image(
+                image.id,
+                stored.rgba.as_deref(),
+                stored.encoded.as_deref(),
+                stored.quality,
+                stored.width,
+                stored.height,
+            )?;
+            if replaced {
+                shrunk += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_image_policy, hash_distance, perceptual_hash, scaled_size};
+    use crate::clipboard::image_fingerprint;
+    use crate::settings::{ImageEncoding, Settings, SETTING_SPECS};
+    use crate::storage::{NewItem, Storage};
+    use crate::test_support::temp_db_path;
+
+    fn gradient(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                [(x * 255 / width) as u8, (y * 255 / height) as u8, 128, 255]
+            })
+            .collect()
+    }
+
+    /// Blocks of varying brightness, so neighbouring columns differ both ways.
+    fn blocks(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i * 8 / width % 8, i / width * 8 / height);
+                let level = ((x * 5 + y * 3) * 37 % 256) as u8;
+                [level, level, level, 255]
+            })
+            .collect()
+    }
+
+    fn decode(bytes: &[u8]) -> image::RgbaImage {
+        image::load_from_memory(bytes).expect("decode").to_rgba8()
+    }
+
+    #[test]
+    fn downscaling_keeps_the_aspect_ratio() {
+        assert_eq!(scaled_size(3840, 2160, 1920), Some((1920, 1080)));
+        assert_eq!(scaled_size(1000, 3, 100), Some((100, 1)));
+        assert_eq!(scaled_size(800, 600, 800), None);
+        assert_eq!(scaled_size(800, 600, 0), None);
+    }
+
+    #[test]
+    fn policy_resizes_and_encodes_decodable_images() {
+        let rgba = gradient(64, 32);
+        let untouched = apply_image_policy(&rgba, 64, 32, &Settings::default()).expect("policy");
+        assert_eq!(untouched, None);
+
+        let raw = Settings {
+            image_max_dimension: 16,
+            ..Settings::default()
+        };
+        let stored = apply_image_policy(&rgba, 64, 32, &raw)
+            .expect("policy")
+            .expect("resized");
+        assert_eq!((stored.width, stored.height), (16, 8));
+        assert_eq!(stored.rgba.expect("raw").len(), 16 * 8 * 4);
+
+        let png = Settings {
+            image_encoding: ImageEncoding::Png,
+            ..Settings::default()
+        };
+        let stored = apply_image_policy(&rgba, 64, 32, &png)
+            .expect("policy")
+            .expect("encoded");
+        assert_eq!(stored.rgba, None);
+        assert_eq!(decode(&stored.encoded.expect("png")).into_raw(), rgba);
+
+        let lossless = Settings {
+            image_encoding: ImageEncoding::Webp,
+            image_quality: 100,
+            ..Settings::default()
+        };
+        let stored = apply_image_policy(&rgba, 64, 32, &lossless)
+            .expect("policy")
+            .expect("encoded");
+        assert_eq!(decode(&stored.encoded.expect("webp")).into_raw(), rgba);
+
+        let lossy = Settings {
+            image_max_dimension: 32,
+            image_encoding: ImageEncoding::Webp,
+            image_quality: 60,
+            ..Settings::default()
+        };
+        let stored = apply_image_policy(&rgba, 64, 32, &lossy)
+            .expect("policy")
+            .expect("encoded");
+        let decoded = decode(&stored.encoded.expect("webp"));
+        assert_eq!((decoded.width(), decoded.height()), (32, 16));
+        assert_eq!((stored.width, stored.height), (32, 16));
+    }
+
+    #[test]
+    fn changing_the_encoding_re_encodes_what_it_would_shrink() {
+        let db_path = temp_db_path("re-encode");
+        let storage = Storage::open(&db_path).expect("open db");
+        let rgba = gradient(64, 32);
+        let id = storage
+            .insert_item(&NewItem {
+                kind: "image",
+                text: Some("image://64x32"),
+                fingerprint: "gradient",
+                image_rgba: Some(&rgba),
+                image_width: Some(64),
+                image_height: Some(32),
+                ..Default::default()
+            })
+            .expect("insert");
+        let pending = |encoding, quality| {
+            storage
+                .images_to_shrink(i64::MAX, 0, encoding, quality, 0, 10)
+                .expect("pending")
+                .len()
+        };
+        let store = |encoding, quality| {
+            let settings = Settings {
+                image_encoding: encoding,
+                image_quality: quality,
+                ..Settings::default()
+            };
+            let stored = apply_image_policy(&rgba, 64, 32, &settings)
+                .expect("policy")
+                .expect("encoded");
+            storage
+                .replace_item_image(
+                    id,
+                    None,
+                    stored.encoded.as_deref(),
+                    stored.quality,
+                    stored.width,
+                    stored.height,
+                )
+                .expect("replace");
+        };
+
+        assert_eq!(pending(ImageEncoding::Raw, 90), 0);
+        assert_eq!(pending(ImageEncoding::Png, 90), 1);
+        store(ImageEncoding::Png, 90);
+        assert_eq!(pending(ImageEncoding::Png, 90), 0);
+        assert_eq!(pending(ImageEncoding::Webp, 90), 1);
+        store(ImageEncoding::Webp, 80);
+        assert_eq!(pending(ImageEncoding::Webp, 80), 0);
+        // Neither a higher quality nor going back to PNG stores it smaller.
+        assert_eq!(pending(ImageEncoding::Webp, 90), 0);
+        assert_eq!(pending(ImageEncoding::Png, 90), 0);
+        assert_eq!(pending(ImageEncoding::Webp, 60), 1);
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn shrunk_items_round_trip_and_keep_their_fingerprint() {
+        let db_path = temp_db_path("shrink");
+        let storage = Storage::open(&db_path).expect("open db");
+        let broken = storage
+            .insert_item(&NewItem {
+                kind: "image",
+                text: Some("image://64x32"),
+                fingerprint: "broken",
+                image_encoded: Some(b"not an image"),
+                image_width: Some(64),
+                image_height: Some(32),
+                ..Default::default()
+            })
+            .expect("insert");
+        let rgba = gradient(64, 32);
+        let fingerprint = image_fingerprint(64, 32, &rgba);
+        let id = storage
+            .insert_item(&NewItem {
+                kind: "image",
+                text: Some("image://64x32"),
+                fingerprint: &fingerprint,
+                image_rgba: Some(&rgba),
+                image_width: Some(64),
+                image_height: Some(32),
+                ..Default::default()
+            })
+            .expect("insert");
+
+        let settings = Settings {
+            image_max_dimension: 20,
+            image_encoding: ImageEncoding::Webp,
+            image_quality: 80,
+            ..Settings::default()
+        };
+        let pending = || {
+            storage
+                .images_to_shrink(i64::MAX, 20, ImageEncoding::Webp, 80, 0, 10)
+                .expect("pending")
+        };
+        // The unreadable item comes back as an error of its own.
+        let batch = pending();
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch[0].id, broken);
+        assert!(batch[0].rgba.is_err());
+        assert_eq!(batch[1].rgba.as_ref().expect("pixels"), &rgba);
+        let stored = apply_image_policy(&rgba, 64, 32, &settings)
+            .expect("policy")
+            .expect("shrunk");
+        assert!(storage
+            .replace_item_image(
+                id,
+                stored.rgba.as_deref(),
+                stored.encoded.as_deref(),
+                stored.quality,
+                stored.width,
+                stored.height
+            )
+            .expect("replace"));
+        assert_eq!(pending().len(), 1);
+        storage.delete_item(broken).expect("delete");
+
+        // What `set_clipboard_image` receives must match the stored size.
+        let payload = storage
+            .get_item_clipboard_payload(id)
+            .expect("payload")
+            .expect("item");
+        let (width, height) = (payload.image_width, payload.image_height);
+        assert_eq!((width, height), (Some(20), Some(10)));
+        assert_eq!(payload.image_rgba.expect("pixels").len(), 20 * 10 * 4);
+        assert_eq!(payload.text.as_deref(), Some("image://20x10"));
+        // What a restore remembers, so re-capturing it dedupes against the item.
+        assert_eq!(payload.fingerprint, fingerprint);
+        assert_eq!(
+            storage.last_fingerprint().expect("fingerprint"),
+            Some(fingerprint)
+        );
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn perceptual_hash_survives_resizing_but_not_a_different_image() {
+        let rgba = blocks(64, 48);
+        let hash = perceptual_hash(&rgba, 64, 48).expect("hash");
+        let resized = image::imageops::resize(
+            &image::RgbaImage::from_raw(64, 48, rgba.clone()).expect("image"),
+            61,
+            45,
+            image::imageops::FilterType::Triangle,
+        );
+        let resized_hash = perceptual_hash(resized.as_raw(), 61, 45).expect("hash");
+        assert!(hash_distance(hash, resized_hash) <= 5);
+
+        let other = perceptual_hash(&gradient(64, 48), 64, 48).expect("hash");
+        assert!(hash_distance(hash, other) > 10);
+        assert_eq!(perceptual_hash(&rgba[..16], 64, 48), None);
+    }
+
+    #[test]
+    fn similar_images_are_found_and_collapsed() {
+        let db_path = temp_db_path("similar");
+        let storage = Storage::open(&db_path).expect("open db");
+        let insert = |fingerprint: &str, phash: i64| {
+            storage
+                .insert_item(&NewItem {
+                    kind: "image",
+                    text: Some("image://1x1"),
+                    fingerprint,
+                    image_rgba: Some(&[0, 0, 0, 255]),
+                    image_width: Some(1),
+                    image_height: Some(1),
+                    image_phash: Some(phash),
+                    ..Default::default()
+                })
+                .expect("insert")
+        };
+        let far = insert("far", 0x0f0f);
+        let near = insert("near", 0b111);
+        let pinned = insert("pinned", 0b1);
+        storage.pin_item(pinned, true).expect("pin");
+        let query = insert("query", 0);
+
+        let found = storage.similar_images(query, 5, 10).expect("similar");
+        let found: Vec<(i64, u32)> = found.iter().map(|s| (s.item.id, s.distance)).collect();
+        assert_eq!(found, vec![(pinned, 1), (near, 3)]);
+        assert!(storage
+            .similar_images(query, 0, 10)
+            .expect("exact")
+            .is_empty());
+
+        assert_eq!(
+            storage
+                .collapse_similar_images(query, 0, 5)
+                .expect("collapse"),
+            vec![near]
+        );
+        let left: Vec<i64> = storage
+            .similar_images(query, 64, 10)
+            .expect("similar")
+            .iter()
+            .map(|s| s.item.id)
+            .collect();
+        assert_eq!(left, vec![pinned, far]);
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn unrelated_images_survive_the_largest_similar_distance() {
+        let max_distance = SETTING_SPECS
+            .iter()
+            .find(|spec| spec.key == "similar_image_distance")
+            .and_then(|spec| spec.max)
+            .expect("ranged setting") as u32;
+        let db_path = temp_db_path("similar-max");
+        let storage = Storage::open(&db_path).expect("open db");
+        let stripes: Vec<u8> = (0..64 * 48)
+            .flat_map(|i| {
+                let v = if (i / 4) % 2 == 0 { 255 } else { 0 };
+                [v, v, v, 255]
+            })
+            .collect();
+        let mut ids = Vec::new();
+        for (fingerprint, rgba) in [
+            ("gradient", gradient(64, 48)),
+            ("stripes", stripes),
+            ("blocks", blocks(64, 48)),
+        ] {
+            let phash = perceptual_hash(&rgba, 64, 48).expect("hash");
+            let id = storage
+                .insert_item(&NewItem {
+                    kind: "image",
+                    text: Some("image://64x48"),
+                    fingerprint,
+                    image_rgba: Some(&rgba),
+                    image_width: Some(64),
+                    image_height: Some(48),
+                    image_phash: Some(phash),
+                    ..Default::default()
+                })
+                .expect("insert");
+            ids.push((id, phash));
+        }
+
+        let (query, hash) = ids[2];
+        let collapsed = storage
+            .collapse_similar_images(query, hash, max_distance)
+            .expect("collapse");
+        assert!(collapsed.is_empty(), "{collapsed:?}");
+
+        let _ = std::fs::remove_file(db_path);
+    }
+}
@@ -0,0 +1,174 @@
+//! Our own JSON export (see `export.rs`), so history can move between machines.
+
+use std::path::{Component, Path, PathBuf};
+
+use base64::Engine;
+use serde::Deserialize;
+
+use super::{malformed, Content, Entry, Importer};
+use crate::error::Result;
+use crate::export::{ArchiveItem, ARCHIVE_FORMAT, ARCHIVE_VERSION};
+
+const WHAT: &str = "Clip It archive";
+
+#[derive(Deserialize)]
+struct Archive {
+    format: String,
+    version: u32,
+    items: Vec<ArchiveItem>,
+}
+
+pub fn read(path: &Path, into: &mut Importer<'_>) -> Result<()> {
+    let file = std::fs::File::open(path)?;
+    let archive: Archive = serde_json::from_reader(std::io::BufReader::new(file))
+        .map_err(|err| malformed(WHAT, path, err))?;
+    if archive.format != ARCHIVE_FORMAT {
+        return Err(malformed(
+            WHAT,
+            path,
+            format!("unknown format \"{}\"", archive.format),
+        ));
+    }
+    if archive.version > ARCHIVE_VERSION {
+        return Err(malformed(
+            WHAT,
+            path,
+            format!(
+                "version {} is newer than this app supports",
+                archive.version
+            ),
+        ));
+    }
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    for item in archive.items {
+        let content = if item.kind == "image" {
+            let png = item
+                .image
+                .and_then(|image| match (image.file, image.png_base64) {
+                    (Some(file), _) => {
+                        image_path(dir, &file).and_then(|path| std::fs::read(path).ok())
+                    }
+                    (None, Some(data)) => {
+                        base64::engine::general_purpose::STANDARD.decode(data).ok()
+                    }
+                    (None, None) => None,
+                });
+            match png {
+                Some(png) => Content::Image(png),
+                None => {
+                    into.skip();
+                    continue;
+                }
+            }
+        } else {
+            let text = item.text.unwrap_or_default();
+            if item.kind == "file" {
+                Content::Files(text)
+            } else {
+                Content::Text(text)
+            }
+        };
+
+        into.add(Entry {
+            created_at: item.created_at,
+            content,
+            favorite: item.favorite,
+            pinned: item.pinned,
+            tags: item.tags,
+        })?;
+    }
+    Ok(())
+}
+
+/// Where an item's image file lives, or `None` when `file` could reach outside
+/// the archive's directory (`..`, an absolute path or a drive prefix).
+fn image_path(dir: &Path, file: &str) -> Option<PathBuf> {
+    let relative = Path::new(file);
+    let mut components = relative.components().peekable();
+    let inside = components.peek().is_some()
+        && components.all(|component| matches!(component, Component::Normal(_)));
+    inside.then(|| dir.join(relative))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::{fixture, import, temp_dir};
+    use super::super::ImportSource;
+
+    #[test]
+    fn restores_flags_tags_timestamps_and_images() {
+        let dir = temp_dir("archive");
+        let (storage, report, items) =
+            import(&dir, ImportSource::ClipIt, &fixture("clipit-archive.json"));
+
+        assert_eq!((report.imported, report.skipped), (4, 1));
+        assert_eq!(items[0].created_at, 1_700_000_000_000);
+        assert!(items[0].favorite);
+        assert_eq!(items[0].tags, ["work", "notes"]);
+        assert_eq!(items[1].kind, "file");
+        assert!(items[1].pinned);
+        assert_eq!(
+            (items[2].image_width, items[2].image_height),
+            (Some(2), Some(1))
+        );
+        assert_eq!(
+            items[2].image_rgba.as_deref(),
+            Some(&[0, 128, 255, 255, 0, 128, 255, 255][..])
+        );
+        assert_eq!(items[3].text.as_deref(), Some("image://1x2"));
+        assert!(items[3].favorite);
+        assert_eq!(storage.list_tags().expect("tags").len(), 2);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn ignores_image_files_outside_the_archive_directory() {
+        let dir = temp_dir("archive-paths");
+        let outside = dir.join("outside.png");
+        std::fs::copy(fixture("clipit-archive_images/3.png"), &outside).expect("copy png");
+        let archive = dir.join("archive").join("history.json");
+        std::fs::create_dir_all(archive.parent().expect("parent")).expect("mkdir");
+        let absolute = outside.display().to_string().replace('\\', "/");
+        std::fs::write(
+            &archive,
+            format!(
+                r#"{{"format":"clip-it-history","version":1,"items":[
+                    {{"id":1,"createdAt":1,"kind":"image","favorite":false,"pinned":false,"tags":[],"image":{{"width":2,"height":1,"file":"../outside.png"}}}},
+                    {{"id":2,"createdAt":2,"kind":"image","favorite":false,"pinned":false,"tags":[],"image":{{"width":2,"height":1,"file":"{absolute}"}}}},
+                    {{"id":3,"createdAt":3,"kind":"image","favorite":false,"pinned":false,"tags":[],"image":{{"width":2,"height":1,"file":""}}}}
+                ]}}"#
+            ),
+        )
+        .expect("write");
+
+        let (_, report, items) = import(&dir, ImportSource::ClipIt, &archive);
+        assert_eq!((report.imported, report.skipped), (0, 3));
+        assert!(items.is_empty());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rejects_foreign_and_newer_archives() {
+        let dir = temp_dir("archive-version");
+        let newer = dir.join("newer.json");
+        std::fs::write(
+            &newer,
+            r#"{"format":"clip-it-history","version":99,"items":[]}"#,
+        )
+        .expect("write");
+        let foreign = dir.join("foreign.json");
+        std::fs::write(&foreign, r#"{"format":"other","version":1,"items":[]}"#).expect("write");
+
+        let storage = crate::storage::Storage::open(&dir.join("history.db")).expect("open db");
+        for path in [newer, foreign] {
+            let err = super::super::import_history(&storage, ImportSource::ClipIt, &path)
+                .expect_err("rejected");
+            assert_eq!(err.code(), "invalid_input");
+        }
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,197 @@
+//! cliphist keeps every entry in a bolt (bbolt) database: bucket `b`, keyed
+//! by big-endian `u64` ids in copy order, each value the raw clipboard bytes.
+//! There are no timestamps or favorites. Only the parts of the bolt format
+//! needed to walk one bucket are read here.
+
+use std::path::Path;
+
+use super::{listed_at, malformed, Content, Entry, Importer};
+use crate::error::Result;
+
+const WHAT: &str = "cliphist database";
+const BUCKET: &[u8] = b"b";
+
+const MAGIC: u32 = 0xED0C_DAED;
+const PAGE_HEADER_SIZE: usize = 16;
+const ELEMENT_SIZE: usize = 16;
+const BRANCH_PAGE: u16 = 0x01;
+const LEAF_PAGE: u16 = 0x02;
+const BUCKET_LEAF: u32 = 0x01;
+
+pub fn read(path: &Path, into: &mut Importer<'_>) -> Result<()> {
+    let bytes = std::fs::read(path)?;
+    let values = bucket_values(&bytes, BUCKET).map_err(|reason| malformed(WHAT, path, reason))?;
+
+    let newest = listed_at(path);
+    let count = values.len() as i64;
+    for (index, value) in values.into_iter().enumerate() {
+        let created_at = newest - (count - 1 - index as i64);
+        let content = match String::from_utf8(value) {
+            Ok(text) => Content::Text(text),
+            Err(err) if image::guess_format(err.as_bytes()).is_ok() => {
+                Content::Image(err.into_bytes())
+            }
+            Err(_) => {
+                into.skip();
+                continue;
+            }
+        };
+        into.add(Entry::new(created_at, content))?;
+    }
+    Ok(())
+}
+
+/// Values of the top-level bucket `name`, in key order.
+fn bucket_values(bytes: &[u8], name: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let db = Bolt::open(bytes)?;
+    let mut values = Vec::new();
+    let mut found = None;
+    db.walk(db.root, &mut |key, value, flags| {
+        if key == name && flags & BUCKET_LEAF != 0 {
+            found = Some(value.to_vec());
+        }
+        Ok(())
+    })?;
+    let Some(bucket) = found else {
+        return Ok(values);
+    };
+
+    // A bucket value is `{root: u64, sequence: u64}`; root 0 means the
+    // bucket's single leaf page follows inline.
+    let root = le_u64(&bucket, 0)?;
+    let mut collect = |_: &[u8], value: &[u8], flags: u32| {
+        if flags & BUCKET_LEAF == 0 {
+            values.push(value.to_vec());
+        }
+        Ok(())
+    };
+    if root == 0 {
+        let inline = bucket
+            .get(ELEMENT_SIZE..)
+            .ok_or("truncated inline bucket")?;
+        Bolt::walk_page(inline, &mut collect)?;
+    } else {
+        db.walk(root, &mut collect)?;
+    }
+    Ok(values)
+}
+
+/// Called with each leaf element's key, value and flags.
+type Visit<'v> = dyn FnMut(&[u8], &[u8], u32) -> Result<(), String> + 'v;
+
+struct Bolt<'a> {
+    bytes: &'a [u8],
+    page_size: usize,
+    root: u64,
+}
+
+impl<'a> Bolt<'a> {
+    /// Picks the newer of the two meta pages.
+    fn open(bytes: &'a [u8]) -> Result<Self, String> {
+        let first = Self::meta(bytes, PAGE_HEADER_SIZE)?;
+        let page_size = first.0;
+        let second = Self::meta(bytes, page_size + PAGE_HEADER_SIZE).ok();
+        let (page_size, root, _) = match second {
+            Some(second) if second.2 > first.2 => second,
+            _ => first,
+        };
+        Ok(Self {
+            bytes,
+            page_size,
+            root,
+        })
+    }
+
+    /// `(page size, root bucket page, txid)` from the meta at `offset`.
+    fn meta(bytes: &[u8], offset: usize) -> Result<(usize, u64, u64), String> {
+        if le_u32(bytes, offset)? != MAGIC {
+            return Err("bad magic".to_string());
+        }
+        let page_size = le_u32(bytes, offset + 8)? as usize;
+        if page_size < PAGE_HEADER_SIZE * 2 {
+            return Err(format!("bad page size {page_size}"));
+        }
+        Ok((
+            page_size,
+            le_u64(bytes, offset + 16)?,
+            le_u64(bytes, offset + 48)?,
+        ))
+    }
+
+    fn page(&self, id: u64) -> Result<&'a [u8], String> {
+        let start = (id as usize)
+            .checked_mul(self.page_size)
+            .filter(|start| start + PAGE_HEADER_SIZE <= self.bytes.len())
+            .ok_or_else(|| format!("page {id} is past the end of the file"))?;
+        let overflow = le_u32(self.bytes, start + 12)? as usize;
+        let end = (start + (overflow + 1) * self.page_size).min(self.bytes.len());
+        Ok(&self.bytes[start..end])
+    }
+
+    fn walk(&self, id: u64, visit: &mut Visit<'_>) -> Result<(), String> {
+        let page = self.page(id)?;
+        if le_u16(page, 8)? == BRANCH_PAGE {
+            for index in 0..le_u16(page, 10)? as usize {
+                let child = le_u64(page, PAGE_HEADER_SIZE + index * ELEMENT_SIZE + 8)?;
+                self.walk(child, visit)?;
+            }
+            Ok(())
+        } else {
+            Self::walk_page(page, visit)
+        }
+    }
+
+    fn walk_page(page: &[u8], visit: &mut Visit<'_>) -> Result<(), String> {
+        if le_u16(page, 8)? != LEAF_PAGE {
+            return Err("expected a leaf page".to_string());
+        }
+        for index in 0..le_u16(page, 10)? as usize {
+            let element = PAGE_HEADER_SIZE + index * ELEMENT_SIZE;
+            let flags = le_u32(page, element)?;
+            let key_start = element + le_u32(page, element + 4)? as usize;
+            let key_end = key_start + le_u32(page, element + 8)? as usize;
+            let value_end = key_end + le_u32(page, element + 12)? as usize;
+            let key = page.get(key_start..key_end).ok_or("truncated key")?;
+            let value = page.get(key_end..value_end).ok_or("truncated value")?;
+            visit(key, value, flags)?;
+        }
+        Ok(())
+    }
+}
+
+fn le_u16(bytes: &[u8], at: usize) -> Result<u16, String> {
+    let b = bytes.get(at..at + 2).ok_or("unexpected end of page")?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> Result<u32, String> {
+    let b = bytes.get(at..at + 4).ok_or("unexpected end of page")?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn le_u64(bytes: &[u8], at: usize) -> Result<u64, String> {
+    let b = bytes.get(at..at + 8).ok_or("unexpected end of page")?;
+    let mut buf = [0; 8];
+    buf.copy_from_slice(b);
+    Ok(u64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::{fixture, import, temp_dir};
+    use super::super::ImportSource;
+
+    #[test]
+    fn walks_the_bucket_in_id_order() {
+        let dir = temp_dir("cliphist");
+        let (_storage, report, items) =
+            import(&dir, ImportSource::Cliphist, &fixture("cliphist.db"));
+
+        assert_eq!((report.imported, report.skipped), (3, 1));
+        assert_eq!(items[0].text.as_deref(), Some("first clip"));
+        assert_eq!(items[1].kind, "image");
+        assert_eq!(items[2].text.as_deref(), Some("last clip – ünïcode"));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,45 @@
+//! Clipman (Wayland) keeps its history in `clipman.json`: a JSON array of
+//! strings, oldest first, with no timestamps.
+
+use std::path::Path;
+
+use super::{listed_at, malformed, Content, Entry, Importer};
+use crate::error::Result;
+
+pub fn read(path: &Path, into: &mut Importer<'_>) -> Result<()> {
+    let file = std::fs::File::open(path)?;
+    let history: Vec<String> = serde_json::from_reader(std::io::BufReader::new(file))
+        .map_err(|err| malformed("Clipman history", path, err))?;
+
+    let newest = listed_at(path);
+    let count = history.len() as i64;
+    for (index, text) in history.into_iter().enumerate() {
+        let age = count - 1 - index as i64;
+        into.add(Entry::new(newest - age, Content::Text(text)))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::{fixture, import, temp_dir};
+    use super::super::ImportSource;
+
+    #[test]
+    fn imports_oldest_first_and_skips_blank_entries() {
+        let dir = temp_dir("clipman");
+        let (_storage, report, items) =
+            import(&dir, ImportSource::Clipman, &fixture("clipman.json"));
+
+        assert_eq!((report.imported, report.skipped), (3, 1));
+        let texts: Vec<_> = items
+            .iter()
+            .map(|i| i.text.as_deref().unwrap_or_default())
+            .collect();
+        assert_eq!(texts, ["git status", "https://example.com/docs", "#ff8800"]);
+        assert_eq!(items[1].subtype.as_deref(), Some("url"));
+        assert!(items[0].created_at < items[2].created_at);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
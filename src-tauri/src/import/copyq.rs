@@ -0,0 +1,230 @@
+//! CopyQ tab files, `copyq_tab_<base64 name>.dat`: a big-endian `QDataStream`
+//! holding an optional `QString` header (`"CopyQ v2"`/`"CopyQ v3"`), a `u32`
+//! item count and the items top row first. An item is either
+//!
+//! - v2: `i32 -2`, `i32` format count, then per format a `QString` MIME type
+//!   with its common prefix shortened to a digit, a `bool` compression flag
+//!   and the `QByteArray` data;
+//! - v1: `i32` format count, then per format a `QString` MIME type and the
+//!   `qCompress`ed `QByteArray` data.
+//!
+//! Tabs carry no timestamps or favorites; tags come from CopyQ's tags plugin.
+
+use std::io::Read;
+use std::path::Path;
+
+use flate2::read::ZlibDecoder;
+
+use super::{listed_at, malformed, Content, Entry, Importer};
+use crate::error::Result;
+
+const WHAT: &str = "CopyQ tab file";
+const MIME_TEXT: &str = "text/plain";
+const MIME_URIS: &str = "text/uri-list";
+const MIME_PNG: &str = "image/png";
+const MIME_TAGS: &str = "application/x-copyq-tags";
+
+/// What the digit in front of a v2 MIME type stands for.
+const MIME_PREFIXES: [&str; 3] = ["application/x-copyq-", "text/", ""];
+
+pub fn read(path: &Path, into: &mut Importer<'_>) -> Result<()> {
+    let bytes = std::fs::read(path)?;
+    let items = parse_tab(&bytes).map_err(|reason| malformed(WHAT, path, reason))?;
+
+    let newest = listed_at(path);
+    for (position, formats) in items.into_iter().enumerate() {
+        let find = |mime: &str| {
+            formats
+                .iter()
+                .find(|(name, _)| name == mime)
+                .map(|(_, data)| data.as_slice())
+        };
+        let content = if let Some(png) = find(MIME_PNG) {
+            Content::Image(png.to_vec())
+        } else if let Some(uris) = find(MIME_URIS) {
+            Content::Files(String::from_utf8_lossy(uris).into_owned())
+        } else if let Some(text) = find(MIME_TEXT) {
+            Content::Text(String::from_utf8_lossy(text).into_owned())
+        } else {
+            into.skip();
+            continue;
+        };
+
+        let mut entry = Entry::new(newest - position as i64, content);
+        entry.tags = find(MIME_TAGS)
+            .map(|tags| {
+                String::from_utf8_lossy(tags)
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        into.add(entry)?;
+    }
+    Ok(())
+}
+
+type Formats = Vec<(String, Vec<u8>)>;
+
+fn parse_tab(bytes: &[u8]) -> Result<Vec<Formats>, String> {
+    let mut stream = DataStream { bytes, pos: 0 };
+    let header = {
+        let mut peek = DataStream { bytes, pos: 0 };
+        peek.string()
+            .ok()
+            .flatten()
+            .map(|header| (header, peek.pos))
+    };
+    if let Some((header, end)) = header {
+        if header.starts_with("CopyQ v") {
+            stream.pos = end;
+        }
+    }
+
+    let count = stream.u32()?;
+    let mut items = Vec::new();
+    for _ in 0..count {
+        items.push(parse_item(&mut stream)?);
+    }
+    Ok(items)
+}
+
+fn parse_item(stream: &mut DataStream<'_>) -> Result<Formats, String> {
+    let marker = stream.i32()?;
+    let mut formats = Vec::new();
+    if marker == -2 {
+        let count = stream.i32()?;
+        for _ in 0..count {
+            let mime = expand_mime(&stream.string()?.unwrap_or_default());
+            let compressed = stream.u8()? != 0;
+            let data = stream.byte_array()?;
+            let data = if compressed { uncompress(&data)? } else { data };
+            formats.push((mime, data));
+        }
+    } else if marker >= 0 {
+        for _ in 0..marker {
+            let mime = stream.string()?.unwrap_or_default();
+            let data = uncompress(&stream.byte_array()?)?;
+            formats.push((mime, data));
+        }
+    } else {
+        return Err(format!("unknown item version {marker}"));
+    }
+    Ok(formats)
+}
+
+fn expand_mime(stored: &str) -> String {
+    let mut chars = stored.chars();
+    match chars.next().and_then(|c| c.to_digit(10)) {
+        Some(digit) if (digit as usize) < MIME_PREFIXES.len() => {
+            format!("{}{}", MIME_PREFIXES[digit as usize], chars.as_str())
+        }
+        _ => stored.to_string(),
+    }
+}
+
+/// Qt's `qUncompress`: a big-endian `u32` length, then a zlib stream.
+fn uncompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.is_empty() {
+        return Ok(Vec::new());
+    }
+    let body = data.get(4..).ok_or("truncated compressed data")?;
+    let mut out = Vec::new();
+    ZlibDecoder::new(body)
+        .read_to_end(&mut out)
+        .map_err(|err| format!("bad compressed data: {err}"))?;
+    Ok(out)
+}
+
+struct DataStream<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl DataStream<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], String> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| format!("unexpected end of file at byte {}", self.pos))?;
+        let taken = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(self.u32()? as i32)
+    }
+
+    /// `QByteArray`: a `u32` length (`0xFFFFFFFF` for null) and the bytes.
+    fn byte_array(&mut self) -> Result<Vec<u8>, String> {
+        match self.u32()? {
+            u32::MAX => Ok(Vec::new()),
+            len => Ok(self.take(len as usize)?.to_vec()),
+        }
+    }
+
+    /// `QString`: a `u32` byte length (`0xFFFFFFFF` for null) and UTF-16BE.
+    fn string(&mut self) -> Result<Option<String>, String> {
+        let len = self.u32()?;
+        if len == u32::MAX {
+            return Ok(None);
+        }
+        if len % 2 != 0 {
+            return Err(format!("odd string length {len}"));
+        }
+        let units: Vec<u16> = self
+            .take(len as usize)?
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16(&units)
+            .map(Some)
+            .map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::{fixture, import, temp_dir};
+    use super::super::ImportSource;
+    use super::parse_tab;
+
+    #[test]
+    fn reads_v1_and_v2_items_with_tags_and_images() {
+        let dir = temp_dir("copyq");
+        let (_storage, report, items) = import(
+            &dir,
+            ImportSource::CopyQ,
+            &fixture("copyq_tab_Y2xpcGJvYXJk.dat"),
+        );
+
+        assert_eq!((report.imported, report.skipped), (3, 1));
+        assert_eq!(items[0].text.as_deref(), Some("older entry"));
+        assert_eq!(items[1].kind, "image");
+        assert_eq!(items[1].image_rgba.as_deref(), Some(&[255, 0, 0, 255][..]));
+        assert_eq!(items[2].text.as_deref(), Some("copyq newest ✓"));
+        assert_eq!(items[2].tags, ["work", "ideas"]);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn truncated_tabs_are_rejected() {
+        let bytes = std::fs::read(fixture("copyq_tab_Y2xpcGJvYXJk.dat")).expect("fixture");
+        assert_eq!(parse_tab(&bytes).expect("parse").len(), 4);
+        assert!(parse_tab(&bytes[..bytes.len() - 3]).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! Ditto's `Ditto.db`: clips and groups share the `Main` table (`bIsGroup`),
+//! and each clip's clipboard formats are rows in `Data`. `lDate` is Unix
+//! seconds. "Never auto delete" clips become favorites, sticky clips pinned
+//! items, and the group a clip sits in becomes a tag.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use rusqlite::{params, Connection, OpenFlags};
+
+use super::{malformed, Content, Entry, Importer};
+use crate::error::Result;
+
+const WHAT: &str = "Ditto database";
+
+/// `stickyClipOrder` of a clip that isn't sticky.
+const NOT_STICKY: f64 = -2_147_483_648.0;
+
+pub fn read(path: &Path, into: &mut Importer<'_>) -> Result<()> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|err| malformed(WHAT, path, err))?;
+    let bad = |err: rusqlite::Error| malformed(WHAT, path, err);
+
+    let groups: HashMap<i64, String> = conn
+        .prepare("SELECT lID, mText FROM Main WHERE bIsGroup = 1")
+        .and_then(|mut stmt| {
+            stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect()
+        })
+        .map_err(bad)?;
+
+    // Older databases predate sticky clips.
+    let has_sticky = conn
+        .prepare("SELECT stickyClipOrder FROM Main LIMIT 0")
+        .is_ok();
+    let sticky = if has_sticky {
+        "stickyClipOrder"
+    } else {
+        "NULL"
+    };
+    let mut clips = conn
+        .prepare(&format!(
+            "SELECT lID, lDate, COALESCE(lDontAutoDelete, 0) != 0, lParentID, {sticky}
+             FROM Main
+             WHERE COALESCE(bIsGroup, 0) = 0
+             ORDER BY lID"
+        ))
+        .map_err(bad)?;
+    let mut formats = conn
+        .prepare(
+            "SELECT strClipBoardFormat, ooData FROM Data
+             WHERE lParentID = ?1 AND ooData IS NOT NULL",
+        )
+        .map_err(bad)?;
+
+    let rows = clips
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, bool>(2)?,
+                row.get::<_, Option<i64>>(3)?,
+                row.get::<_, Option<f64>>(4)?,
+            ))
+        })
+        .map_err(bad)?;
+    for row in rows {
+        let (clip_id, date, never_delete, group, sticky_order) = row.map_err(bad)?;
+        let data: HashMap<String, Vec<u8>> = formats
+            .query_map(params![clip_id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(bad)?
+            .collect::<rusqlite::Result<_>>()
+            .map_err(bad)?;
+
+        let Some(content) = content(&data) else {
+            into.skip();
+            continue;
+        };
+        into.add(Entry {
+            created_at: date * 1000,
+            content,
+            favorite: never_delete,
+            pinned: sticky_order.is_some_and(|order| order != NOT_STICKY),
+            tags: group
+                .and_then(|group| groups.get(&group))
+                .cloned()
+                .into_iter()
+                .collect(),
+        })?;
+    }
+    Ok(())
+}
+
+/// PNG, then dropped files, then Unicode or ANSI text. `CF_DIB` bitmaps and
+/// rich formats alone aren't stored.
+fn content(data: &HashMap<String, Vec<u8>>) -> Option<Content> {
+    if let Some(png) = data.get("PNG") {
+        return Some(Content::Image(png.clone()));
+    }
+    if let Some(files) = data.get("CF_HDROP").and_then(|drop| drop_files(drop)) {
+        return Some(Content::Files(files.join("\n")));
+    }
+    if let Some(text) = data.get("CF_UNICODETEXT") {
+        let text = utf16_le(text);
+        let end = text.find('\0').unwrap_or(text.len());
+        return Some(Content::Text(text[..end].to_string()));
+    }
+    data.get("CF_TEXT").map(|text| {
+        let end = text.iter().position(|b| *b == 0).unwrap_or(text.len());
+        Content::Text(String::from_utf8_lossy(&text[..end]).into_owned())
+    })
+}
+
+/// A `DROPFILES` header followed by a double-NUL-terminated path list, UTF-16
+/// when `fWide` is set.
+fn drop_files(drop: &[u8]) -> Option<Vec<String>> {
+    let word = |at: usize| {
+        drop.get(at..at + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    let offset = word(0)? as usize;
+    let wide = word(16)? != 0;
+    let list = drop.get(offset..)?;
+    let text = if wide {
+        utf16_le(list)
+    } else {
+        String::from_utf8_lossy(list).into_owned()
+    };
+    let paths: Vec<String> = text
+        .split('\0')
+        .take_while(|path| !path.is_empty())
+        .map(str::to_string)
+        .collect();
+    (!paths.is_empty()).then_some(paths)
+}
+
+/// Windows text: UTF-16LE, NULs included.
+fn utf16_le(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::super::test_support::{fixture, import, temp_dir};
+    use super::super::ImportSource;
+
+    #[test]
+    fn maps_groups_to_tags_and_flags_to_favorites_and_pins() {
+        let dir = temp_dir("ditto");
+        let db = dir.join("Ditto.db");
+        let script = std::fs::read_to_string(fixture("ditto.sql")).expect("fixture");
+        Connection::open(&db)
+            .and_then(|conn| conn.execute_batch(&script))
+            .expect("build fixture db");
+
+        let (_storage, report, items) = import(&dir, ImportSource::Ditto, &db);
+
+        assert_eq!((report.imported, report.skipped), (4, 1));
+        assert_eq!(
+            items[0].text.as_deref(),
+            Some("Pancakes: flour, eggs, milk")
+        );
+        assert_eq!(items[0].created_at, 1_600_000_100_000);
+        assert!(items[0].favorite);
+        assert_eq!(items[0].tags, ["Recipes"]);
+        assert_eq!(items[1].kind, "image");
+        assert!(items[1].pinned);
+        assert_eq!(items[2].kind, "file");
+        assert_eq!(
+            items[2].text.as_deref(),
+            Some("C:\\Users\\me\\report.docx\nC:\\Users\\me\\data.xlsx")
+        );
+        assert_eq!(items[3].text.as_deref(), Some("only ansi text"));
+        assert!(!items[3].favorite && !items[3].pinned);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,22 @@
+-- Ditto.db as written by Ditto 3.x; lDate is Unix seconds.
+CREATE TABLE Main (lID INTEGER PRIMARY KEY AUTOINCREMENT, lDate INTEGER, mText TEXT, lShortCut INTEGER, lDontAutoDelete INTEGER, CRC INTEGER, bIsGroup INTEGER, lParentID INTEGER, QuickPasteText TEXT, clipOrder REAL, clipGroupOrder REAL, globalShortCut INT, lastPasteDate INTEGER, stickyClipOrder REAL DEFAULT(-2147483648), stickyClipGroupOrder REAL DEFAULT(-2147483648));
+CREATE TABLE Data (lID INTEGER PRIMARY KEY AUTOINCREMENT, lParentID INTEGER, strClipBoardFormat TEXT, ooData BLOB);
+
+INSERT INTO Main VALUES (1, 1600000000, 'Recipes', 0, 0, 0, 1, -1, '', 1, 1, 0, 0, -2147483648, -2147483648);
+
+INSERT INTO Main VALUES (2, 1600000100, 'Pancakes: flour, eggs, milk', 0, 1600000100, 111, 0, 1, '', 2, 2, 0, 0, -2147483648, -2147483648);
+INSERT INTO Data VALUES (1, 2, 'CF_UNICODETEXT', X'500061006E00630061006B00650073003A00200066006C006F00750072002C00200065006700670073002C0020006D0069006C006B000000');
+INSERT INTO Data VALUES (2, 2, 'CF_TEXT', CAST('Pancakes: flour, eggs, milk' AS BLOB));
+
+INSERT INTO Main VALUES (3, 1600000200, 'CF_DIB', 0, 0, 222, 0, -1, '', 3, 3, 0, 0, 1, -2147483648);
+INSERT INTO Data VALUES (3, 3, 'CF_DIB', X'28000000');
+INSERT INTO Data VALUES (4, 3, 'PNG', X'89504E470D0A1A0A0000000D49484452000000010000000108060000001F15C4890000000D49444154789C63F8CFC0F01F00050001FF89993D1D0000000049454E44AE426082');
+
+INSERT INTO Main VALUES (4, 1600000300, 'C:\Users\me\report.docx', 0, 0, 333, 0, -1, '', 4, 4, 0, 0, -2147483648, -2147483648);
+INSERT INTO Data VALUES (5, 4, 'CF_HDROP', X'140000000000000000000000000000000100000043003A005C00550073006500720073005C006D0065005C007200650070006F00720074002E0064006F0063007800000043003A005C00550073006500720073005C006D0065005C0064006100740061002E0078006C007300780000000000');
+
+INSERT INTO Main VALUES (5, 1600000400, 'only ansi text', 0, 0, 444, 0, -1, '', 5, 5, 0, 0, -2147483648, -2147483648);
+INSERT INTO Data VALUES (6, 5, 'CF_TEXT', CAST('only ansi text' AS BLOB));
+
+INSERT INTO Main VALUES (6, 1600000500, 'CF_DIB', 0, 0, 555, 0, -1, '', 6, 6, 0, 0, -2147483648, -2147483648);
+INSERT INTO Data VALUES (7, 6, 'CF_DIB', X'28000000');
@@ -0,0 +1,18 @@
+-- Core Data store as written by Maccy; dates are seconds since 2001-01-01.
+CREATE TABLE ZHISTORYITEM (Z_PK INTEGER PRIMARY KEY, Z_ENT INTEGER, Z_OPT INTEGER, ZNUMBEROFCOPIES INTEGER, ZFIRSTCOPIEDAT TIMESTAMP, ZLASTCOPIEDAT TIMESTAMP, ZAPPLICATION VARCHAR, ZPIN VARCHAR, ZTITLE VARCHAR);
+CREATE TABLE ZHISTORYITEMCONTENT (Z_PK INTEGER PRIMARY KEY, Z_ENT INTEGER, Z_OPT INTEGER, ZITEM INTEGER, ZTYPE VARCHAR, ZVALUE BLOB);
+
+INSERT INTO ZHISTORYITEM VALUES (1, 1, 1, 3, 700000000.0, 700000100.5, 'com.apple.Terminal', 'b', 'brew upgrade');
+INSERT INTO ZHISTORYITEMCONTENT VALUES (1, 2, 1, 1, 'public.utf8-plain-text', CAST('brew upgrade' AS BLOB));
+INSERT INTO ZHISTORYITEMCONTENT VALUES (2, 2, 1, 1, 'public.html', CAST('<b>brew upgrade</b>' AS BLOB));
+
+INSERT INTO ZHISTORYITEM VALUES (2, 1, 1, 1, 700000200.0, 700000200.0, 'com.apple.Preview', NULL, 'Image');
+INSERT INTO ZHISTORYITEMCONTENT VALUES (3, 2, 1, 2, 'public.png', X'89504E470D0A1A0A0000000D49484452000000010000000108060000001F15C4890000000D49444154789C63F8CFC0F01F00050001FF89993D1D0000000049454E44AE426082');
+INSERT INTO ZHISTORYITEMCONTENT VALUES (4, 2, 1, 2, 'public.tiff', X'49492A00');
+
+INSERT INTO ZHISTORYITEM VALUES (3, 1, 1, 1, 700000300.0, 700000300.0, 'com.apple.finder', NULL, 'a.txt');
+INSERT INTO ZHISTORYITEMCONTENT VALUES (5, 2, 1, 3, 'public.file-url', CAST('file:///Users/me/a.txt' AS BLOB));
+INSERT INTO ZHISTORYITEMCONTENT VALUES (6, 2, 1, 3, 'public.file-url', CAST('file:///Users/me/b.txt' AS BLOB));
+
+INSERT INTO ZHISTORYITEM VALUES (4, 1, 1, 1, 700000400.0, 700000400.0, 'com.apple.Preview', NULL, 'Image');
+INSERT INTO ZHISTORYITEMCONTENT VALUES (7, 2, 1, 4, 'public.tiff', X'49492A00');
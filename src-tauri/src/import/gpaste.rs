@@ -0,0 +1,166 @@
+//! GPaste's `history.xml`, newest first:
+//! `<history version="2.0"><item kind="Text" uuid="..."><value><![CDATA[...]]></value></item>...`.
+//! Older files put the text straight inside `<item>`. Images are PNG files
+//! referenced by path, with a `date` in Unix seconds; nothing else is dated.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use super::{listed_at, malformed, Content, Entry, Importer};
+use crate::error::Result;
+
+const WHAT: &str = "GPaste history";
+
+#[derive(Default)]
+struct Item {
+    kind: String,
+    date: Option<i64>,
+    value: Option<String>,
+    text: String,
+}
+
+pub fn read(path: &Path, into: &mut Importer<'_>) -> Result<()> {
+    let mut reader = Reader::from_reader(BufReader::new(File::open(path)?));
+    let newest = listed_at(path);
+    let mut buf = Vec::new();
+    let mut item: Option<Item> = None;
+    let mut in_value = false;
+    let mut position = 0;
+
+    loop {
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|err| malformed(WHAT, path, err))?;
+        match event {
+            Event::Start(start) if start.local_name().as_ref() == b"item" => {
+                item = Some(start_item(&start).map_err(|err| malformed(WHAT, path, err))?);
+            }
+            Event::Start(start) if start.local_name().as_ref() == b"value" => {
+                if let Some(item) = item.as_mut() {
+                    item.value.get_or_insert_with(String::new);
+                    in_value = true;
+                }
+            }
+            Event::End(end) if end.local_name().as_ref() == b"value" => in_value = false,
+            Event::End(end) if end.local_name().as_ref() == b"item" => {
+                if let Some(item) = item.take() {
+                    add_item(path, item, newest - position, into)?;
+                    position += 1;
+                }
+            }
+            Event::Text(text) => {
+                let text = text
+                    .xml_content()
+                    .map_err(|err| malformed(WHAT, path, err))?;
+                push_text(item.as_mut(), in_value, &text);
+            }
+            Event::CData(data) => {
+                let text = data.decode().map_err(|err| malformed(WHAT, path, err))?;
+                push_text(item.as_mut(), in_value, &text);
+            }
+            Event::GeneralRef(reference) => {
+                let name = reference
+                    .decode()
+                    .map_err(|err| malformed(WHAT, path, err))?;
+                let escaped = format!("&{name};");
+                let text = quick_xml::escape::unescape(&escaped)
+                    .map_err(|err| malformed(WHAT, path, err))?;
+                push_text(item.as_mut(), in_value, &text);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(())
+}
+
+fn start_item(start: &BytesStart<'_>) -> quick_xml::Result<Item> {
+    let mut item = Item::default();
+    for attr in start.attributes() {
+        let attr = attr?;
+        match attr.key.as_ref() {
+            b"kind" => item.kind = attr.unescape_value()?.into_owned(),
+            b"date" => item.date = attr.unescape_value()?.trim().parse().ok(),
+            _ => {}
+        }
+    }
+    Ok(item)
+}
+
+fn push_text(item: Option<&mut Item>, in_value: bool, text: &str) {
+    let Some(item) = item else {
+        return;
+    };
+    match (&mut item.value, in_value) {
+        (Some(value), true) => value.push_str(text),
+        _ => item.text.push_str(text),
+    }
+}
+
+fn add_item(path: &Path, item: Item, listed: i64, into: &mut Importer<'_>) -> Result<()> {
+    let created_at = item.date.map_or(listed, |secs| secs * 1000);
+    let value = item.value.unwrap_or_else(|| item.text.trim().to_string());
+
+    let content = match item.kind.as_str() {
+        "Text" => Content::Text(value),
+        "Uris" => Content::Files(value),
+        "Image" => match std::fs::read(image_path(path, value.trim())) {
+            Ok(png) => Content::Image(png),
+            Err(_) => {
+                into.skip();
+                return Ok(());
+            }
+        },
+        // Passwords stay in GPaste; anything else is a kind we don't know.
+        _ => {
+            into.skip();
+            return Ok(());
+        }
+    };
+    into.add(Entry::new(created_at, content))
+}
+
+/// Image paths are absolute, but the history may have been copied from another
+/// machine; fall back to the `images` folder next to `history.xml`.
+fn image_path(history: &Path, stored: &str) -> PathBuf {
+    let stored = PathBuf::from(stored);
+    if stored.exists() {
+        return stored;
+    }
+    match (history.parent(), stored.file_name()) {
+        (Some(dir), Some(name)) => dir.join("images").join(name),
+        _ => stored,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_support::{fixture, import, temp_dir};
+    use super::super::ImportSource;
+
+    #[test]
+    fn imports_text_uris_and_images_but_not_passwords() {
+        let dir = temp_dir("gpaste");
+        let (_storage, report, items) =
+            import(&dir, ImportSource::GPaste, &fixture("gpaste/history.xml"));
+
+        assert_eq!((report.imported, report.skipped), (4, 1));
+        assert_eq!(items[0].kind, "image");
+        assert_eq!(items[0].created_at, 1_650_000_000_000);
+        assert_eq!(items[0].image_rgba.as_deref(), Some(&[255, 0, 0, 255][..]));
+        assert_eq!(items[1].text.as_deref(), Some("tab\tseparated & escaped"));
+        assert_eq!(items[2].kind, "file");
+        assert_eq!(
+            items[2].text.as_deref(),
+            Some("file:///home/someone/notes.txt\nfile:///home/someone/todo.txt")
+        );
+        assert_eq!(items[3].text.as_deref(), Some("Fish & chips <3"));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
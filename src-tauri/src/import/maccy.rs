@@ -0,0 +1,126 @@
+//! Maccy's Core Data store, `Storage.sqlite`: one `ZHISTORYITEM` row per entry
+//! and its pasteboard types in `ZHISTORYITEMCONTENT`. Dates are seconds since
+//! 2001-01-01; a pinned entry has a `ZPIN` key, which becomes `pinned`.
+
+use std::path::Path;
+
+use rusqlite::{params, Connection, OpenFlags};
+
+use super::{malformed, Content, Entry, Importer};
+use crate::error::Result;
+
+const WHAT: &str = "Maccy database";
+
+/// Seconds between the Unix epoch and Core Data's reference date.
+const CORE_DATA_EPOCH: f64 = 978_307_200.0;
+
+const TYPE_TEXT: &str = "public.utf8-plain-text";
+const TYPE_FILE_URL: &str = "public.file-url";
+const TYPE_PNG: &str = "public.png";
+
+pub fn read(path: &Path, into: &mut Importer<'_>) -> Result<()> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|err| malformed(WHAT, path, err))?;
+    let bad = |err: rusqlite::Error| malformed(WHAT, path, err);
+
+    let mut items = conn
+        .prepare(
+            "SELECT Z_PK, COALESCE(ZLASTCOPIEDAT, ZFIRSTCOPIEDAT, 0), ZPIN IS NOT NULL
+             FROM ZHISTORYITEM
+             ORDER BY Z_PK",
+        )
+        .map_err(bad)?;
+    let mut contents = conn
+        .prepare(
+            "SELECT ZTYPE, ZVALUE FROM ZHISTORYITEMCONTENT
+             WHERE ZITEM = ?1 AND ZVALUE IS NOT NULL
+             ORDER BY Z_PK",
+        )
+        .map_err(bad)?;
+
+    let rows = items
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, f64>(1)?,
+                row.get::<_, bool>(2)?,
+            ))
+        })
+        .map_err(bad)?;
+    for row in rows {
+        let (item_id, copied_at, pinned) = row.map_err(bad)?;
+        let types = contents
+            .query_map(params![item_id], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .map_err(bad)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(bad)?;
+
+        let Some(content) = content(&types) else {
+            into.skip();
+            continue;
+        };
+        let mut entry = Entry::new(((copied_at + CORE_DATA_EPOCH) * 1000.0) as i64, content);
+        entry.pinned = pinned;
+        into.add(entry)?;
+    }
+    Ok(())
+}
+
+/// The richest type we can store: an image, then files, then plain text.
+fn content(types: &[(String, Vec<u8>)]) -> Option<Content> {
+    let values = |wanted: &'static str| {
+        types
+            .iter()
+            .filter(move |(kind, _)| kind == wanted)
+            .map(|(_, value)| value)
+    };
+
+    if let Some(png) = values(TYPE_PNG).next() {
+        return Some(Content::Image(png.clone()));
+    }
+    let files: Vec<_> = values(TYPE_FILE_URL)
+        .map(|url| String::from_utf8_lossy(url).into_owned())
+        .collect();
+    if !files.is_empty() {
+        return Some(Content::Files(files.join("\n")));
+    }
+    values(TYPE_TEXT)
+        .next()
+        .map(|text| Content::Text(String::from_utf8_lossy(text).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::super::test_support::{fixture, import, temp_dir};
+    use super::super::ImportSource;
+
+    #[test]
+    fn keeps_copy_dates_pins_and_the_richest_type() {
+        let dir = temp_dir("maccy");
+        let db = dir.join("Storage.sqlite");
+        let script = std::fs::read_to_string(fixture("maccy.sql")).expect("fixture");
+        Connection::open(&db)
+            .and_then(|conn| conn.execute_batch(&script))
+            .expect("build fixture db");
+
+        let (_storage, report, items) = import(&dir, ImportSource::Maccy, &db);
+
+        assert_eq!((report.imported, report.skipped), (3, 1));
+        assert_eq!(items[0].text.as_deref(), Some("brew upgrade"));
+        assert_eq!(items[0].created_at, 1_678_307_300_500);
+        assert!(items[0].pinned);
+        assert_eq!(items[1].kind, "image");
+        assert!(!items[1].pinned);
+        assert_eq!(items[2].kind, "file");
+        assert_eq!(
+            items[2].text.as_deref(),
+            Some("file:///Users/me/a.txt\nfile:///Users/me/b.txt")
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
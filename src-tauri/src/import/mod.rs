@@ -0,0 +1,312 @@
+//! Importers for other clipboard managers' histories. Each source module reads
+//! its own format into `Entry` values; `Importer` maps them onto `items`.
+
+mod archive;
+mod cliphist;
+mod clipman;
+mod copyq;
+mod ditto;
+mod gpaste;
+mod maccy;
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::classify::classify_text;
+use crate::clipboard::{image_fingerprint, looks_like_file_payload, normalize_text, sha256_hex};
+use crate::error::{ClipItError, Result};
+use crate::storage::{ImportItem, NewItem, Storage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportSource {
+    /// Our own JSON export.
+    ClipIt,
+    /// A CopyQ tab file, `copyq_tab_*.dat`.
+    CopyQ,
+    /// GPaste's `history.xml`.
+    GPaste,
+    /// Clipman's `clipman.json`.
+    Clipman,
+    /// cliphist's bolt database, `~/.cache/cliphist/db`.
+    Cliphist,
+    /// Maccy's `Storage.sqlite`.
+    Maccy,
+    /// Ditto's `Ditto.db`.
+    Ditto,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub source: ImportSource,
+    pub imported: u32,
+    /// Entries whose fingerprint was already in the history.
+    pub duplicates: u32,
+    /// Entries with nothing we can store: empty text, passwords, unreadable images.
+    pub skipped: u32,
+}
+
+/// One entry read from a foreign history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Unix milliseconds.
+    pub created_at: i64,
+    pub content: Content,
+    pub favorite: bool,
+    pub pinned: bool,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Content {
+    Text(String),
+    /// Newline-separated paths or `file://` URLs.
+    Files(String),
+    /// Encoded image data; PNG is the only format decoded.
+    Image(Vec<u8>),
+}
+
+impl Entry {
+    fn new(created_at: i64, content: Content) -> Self {
+        Self {
+            created_at,
+            content,
+            favorite: false,
+            pinned: false,
+            tags: Vec::new(),
+        }
+    }
+}
+
+/// Reads the history at `path` into `storage` in one transaction, so a file
+/// that turns out to be malformed halfway through imports nothing.
+pub fn import_history(
+    storage: &Storage,
+    source: ImportSource,
+    path: &Path,
+) -> Result<ImportReport> {
+    if !path.exists() {
+        return Err(ClipItError::invalid_input(format!(
+            "{} does not exist",
+            path.display()
+        )));
+    }
+
+    storage.batch(|| {
+        let mut importer = Importer::new(storage, source);
+        match source {
+            ImportSource::ClipIt => archive::read(path, &mut importer)?,
+            ImportSource::CopyQ => copyq::read(path, &mut importer)?,
+            ImportSource::GPaste => gpaste::read(path, &mut importer)?,
+            ImportSource::Clipman => clipman::read(path, &mut importer)?,
+            ImportSource::Cliphist => cliphist::read(path, &mut importer)?,
+            ImportSource::Maccy => maccy::read(path, &mut importer)?,
+            ImportSource::Ditto => ditto::read(path, &mut importer)?,
+        }
+        Ok(importer.report)
+    })
+}
+
+pub struct Importer<'a> {
+    storage: &'a Storage,
+    report: ImportReport,
+}
+
+impl<'a> Importer<'a> {
+    fn new(storage: &'a Storage, source: ImportSource) -> Self {
+        Self {
+            storage,
+            report: ImportReport {
+                source,
+                imported: 0,
+                duplicates: 0,
+                skipped: 0,
+            },
+        }
+    }
+
+    /// Stores `entry` the way a capture of the same content would have been
+    /// stored, so its fingerprint matches one the history may already have.
+    pub fn add(&mut self, entry: Entry) -> Result<()> {
+        let (kind, text, image) = match entry.content {
+            Content::Text(raw) => {
+                let text = normalize_text(&raw);
+                let kind = if looks_like_file_payload(&text) {
+                    "file"
+                } else {
+                    "text"
+                };
+                (kind, text, None)
+            }
+            Content::Files(raw) => ("file", normalize_text(&raw), None),
+            Content::Image(bytes) => match image::load_from_memory(&bytes) {
+                Ok(decoded) => {
+                    let rgba = decoded.to_rgba8();
+                    let (width, height) = rgba.dimensions();
+                    (
+                        "image",
+                        format!("image://{width}x{height}"),
+                        Some((rgba.into_raw(), i64::from(width), i64::from(height))),
+                    )
+                }
+                Err(_) => {
+                    self.skip();
+                    return Ok(());
+                }
+            },
+        };
+        if image.is_none() && text.is_empty() {
+            self.skip();
+            return Ok(());
+        }
+
+        let fingerprint = match &image {
+            Some((rgba, width, height)) => image_fingerprint(*width as u64, *height as u64, rgba),
+            None => sha256_hex(&format!("{kind}:{text}")),
+        };
+        let class = (kind == "text").then(|| classify_text(&text));
+
+        let inserted = self.storage.import_item(&ImportItem {
+            item: NewItem {
+                kind,
+                subtype: class.map(|c| c.subtype),
+                language: class.and_then(|c| c.language),
+                text: Some(&text),
+                fingerprint: &fingerprint,
+                image_rgba: image.as_ref().map(|(rgba, _, _)| rgba.as_slice()),
+                image_width: image.as_ref().map(|(_, width, _)| *width),
+                image_height: image.as_ref().map(|(_, _, height)| *height),
+                ..Default::default()
+            },
+            created_at: entry.created_at,
+            favorite: entry.favorite,
+            pinned: entry.pinned,
+            tags: &entry.tags,
+        })?;
+        match inserted {
+            Some(_) => self.report.imported += 1,
+            None => self.report.duplicates += 1,
+        }
+        Ok(())
+    }
+
+    pub fn skip(&mut self) {
+        self.report.skipped += 1;
+    }
+}
+
+/// The file's modification time in Unix ms, as the timestamp of the newest
+/// entry of a source that stores none. Callers subtract one millisecond per
+/// position for the entries listed after it, which keeps their order.
+fn listed_at(path: &Path) -> i64 {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .unwrap_or_else(|_| SystemTime::now())
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+fn malformed(what: &str, path: &Path, reason: impl std::fmt::Display) -> ClipItError {
+    ClipItError::invalid_input(format!("{} is not a {what}: {reason}", path.display()))
+}
+
+#[cfg(test)]
+mod test_support {
+    use std::path::{Path, PathBuf};
+
+    use super::{import_history, ImportReport, ImportSource};
+    use crate::storage::{ExportItem, SearchParams, Storage};
+    pub use crate::test_support::temp_dir;
+
+    pub fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/import/fixtures")
+            .join(name)
+    }
+
+    /// Imports `path` into a fresh database and returns the report and every
+    /// stored item, oldest first.
+    pub fn import(
+        dir: &Path,
+        source: ImportSource,
+        path: &Path,
+    ) -> (Storage, ImportReport, Vec<ExportItem>) {
+        let storage = Storage::open(&dir.join("history.db")).expect("open db");
+        let report = import_history(&storage, source, path).expect("import");
+        let items = stored(&storage);
+        (storage, report, items)
+    }
+
+    pub fn stored(storage: &Storage) -> Vec<ExportItem> {
+        let mut items = Vec::new();
+        storage
+            .for_each_export_item(&SearchParams::default(), |item| {
+                items.push(item);
+                Ok(())
+            })
+            .expect("read items");
+        items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_support::{fixture, import, stored, temp_dir};
+    use super::{import_history, ImportSource};
+
+    #[test]
+    fn reimporting_merges_duplicates_by_fingerprint() {
+        let dir = temp_dir("dedup");
+        let path = fixture("clipman.json");
+        let (storage, first, items) = import(&dir, ImportSource::Clipman, &path);
+        assert_eq!(first.duplicates, 0);
+
+        let again = import_history(&storage, ImportSource::Clipman, &path).expect("reimport");
+        assert_eq!((again.imported, again.duplicates), (0, first.imported));
+        assert_eq!(stored(&storage).len(), items.len());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn retention_keeps_imported_items() {
+        let dir = temp_dir("retention");
+        let (storage, report, items) =
+            import(&dir, ImportSource::Clipman, &fixture("clipman.json"));
+        assert!(report.imported > 0);
+
+        let captures: Vec<i64> = (0..3)
+            .map(|i| crate::test_support::insert_text(&storage, &format!("capture {i}")))
+            .collect();
+        storage.enforce_max_items(2).expect("retention");
+
+        let kept = stored(&storage);
+        assert!(items
+            .iter()
+            .all(|item| kept.iter().any(|k| k.id == item.id)));
+        let kept_captures = kept.iter().filter(|k| captures.contains(&k.id)).count();
+        assert_eq!(kept_captures, 2);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn malformed_files_import_nothing() {
+        let dir = temp_dir("malformed");
+        let broken = dir.join("broken.json");
+        std::fs::write(&broken, "[\"first\", 2").expect("write");
+
+        let (storage, _, _) = import(&dir, ImportSource::Clipman, &fixture("clipman.json"));
+        let before = stored(&storage).len();
+        let err = import_history(&storage, ImportSource::Clipman, &broken).expect_err("broken");
+        assert_eq!(err.code(), "invalid_input");
+        assert_eq!(stored(&storage).len(), before);
+        assert!(import_history(&storage, ImportSource::Ditto, &dir.join("missing.db")).is_err());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,11 +1,26 @@
 #![allow(unexpected_cfgs)]
 
+mod backup;
+mod classify;
 mod clipboard;
 mod commands;
+mod config;
+mod error;
 mod events;
+mod export;
+mod images;
+mod import;
+mod paste;
+mod paste_stack;
 mod settings;
+mod shortcut;
+mod snippets;
 mod storage;
+#[cfg(test)]
+mod test_support;
+mod transforms;
 
+use std::path::PathBuf;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex, RwLock};
@@ -17,31 +32,52 @@ use tauri::menu::MenuEvent;
 use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
 use tauri::{Emitter, Manager};
 use tauri_plugin_global_shortcut::GlobalShortcutExt;
-use tauri_plugin_global_shortcut::ShortcutState;
+use tauri_plugin_global_shortcut::{Shortcut, ShortcutState};
 
-use crate::events::ClipboardPausedChangedEvent;
-use crate::settings::Settings;
-use crate::storage::Storage;
+use crate::events::{ClipboardPausedChangedEvent, PasteStackChangedEvent};
+use crate::paste_stack::{PasteStack, PasteStackState};
+use crate::settings::{hotkey_bindings, HotkeyAction, Settings};
+use crate::shortcut::{format_shortcut, parse_shortcut, ShortcutError};
+use crate::storage::{ReadPool, RecoveryReport, Storage, READ_POOL_SIZE};
 
 pub struct LastWritten {
     pub fingerprint: String,
     pub written_at_ms: i64,
+    /// The restored item's own fingerprint when what was written hashes
+    /// differently, as a downscaled or lossy image does. Later captures of
+    /// those pixels take it on, so they dedupe against the item.
+    pub item_fingerprint: Option<String>,
 }
 
 pub struct SharedState {
+    /// The writer connection; every mutation goes through it.
     pub storage: Mutex<Storage>,
+    /// Read-only connections, so queries never wait on a write in progress.
+    pub readers: ReadPool,
     pub settings: RwLock<Settings>,
     pub paused: AtomicBool,
     pub last_written: Mutex<Option<LastWritten>>,
+    pub paste_stack: Mutex<PasteStack>,
+    /// X11 window that had focus when the popup opened; target of `paste_item`.
+    pub paste_target: Mutex<Option<u32>>,
+    /// Global shortcuts currently registered by us and what each one does.
+    pub hotkeys: Mutex<Vec<(Shortcut, HotkeyAction)>>,
+    /// `config.toml`, layered over the settings stored in the database.
+    pub config_path: PathBuf,
+    /// The live database; backups are read from it over their own connection.
+    pub db_path: PathBuf,
+    pub backup_dir: PathBuf,
+    /// What startup salvaged from a damaged database, until the UI has shown it.
+    pub recovery: Mutex<Option<RecoveryReport>>,
 }
 
 pub fn run() {
     tauri::Builder::default()
         .plugin(
             tauri_plugin_global_shortcut::Builder::new()
-                .with_handler(|app, _shortcut, event| {
+                .with_handler(|app, shortcut, event| {
                     if event.state() == ShortcutState::Pressed {
-                        commands::show_popup_window(app);
+                        handle_hotkey(app, shortcut);
                     }
                 })
                 .build(),
@@ -57,55 +93,248 @@ pub fn run() {
             std::fs::create_dir_all(&app_dir)?;
 
             let db_path = app_dir.join("clipit.db");
-            let storage = Storage::open(&db_path)?;
-            let settings = storage.load_settings()?;
+            let (mut storage, recovery) = Storage::open_or_recover(&db_path)?;
+            if let Some(report) = &recovery {
+                eprintln!(
+                    "database was damaged ({}); moved to {} and salvaged",
+                    report.reason, report.corrupt_path
+                );
+            }
+            let readers = ReadPool::open(&db_path, READ_POOL_SIZE)?;
+            let events = app.handle().clone();
+            storage.set_event_sink(move |event| {
+                let _ = events.emit(event.name(), &event);
+            });
+            let mut settings = storage.load_settings()?;
+
+            let config_path = app
+                .path()
+                .app_config_dir()
+                .context("failed to resolve app config dir")?
+                .join(config::CONFIG_FILE_NAME);
+            match config::read_config(&config_path) {
+                Ok(entries) => {
+                    for err in config::layer_config(&mut settings, &entries) {
+                        eprintln!("{}: {err}", config::CONFIG_FILE_NAME);
+                    }
+                }
+                Err(err) => eprintln!("{err:#}"),
+            }
 
             let state = Arc::new(SharedState {
                 storage: Mutex::new(storage),
+                readers,
                 settings: RwLock::new(settings.clone()),
                 paused: AtomicBool::new(!settings.capture_enabled),
                 last_written: Mutex::new(None),
+                paste_stack: Mutex::new(PasteStack::default()),
+                paste_target: Mutex::new(None),
+                hotkeys: Mutex::new(Vec::new()),
+                config_path,
+                backup_dir: backup::backup_dir(&db_path),
+                db_path,
+                recovery: Mutex::new(recovery),
             });
 
             app.manage(state.clone());
             setup_tray(app.handle())?;
 
-            register_global_shortcut(app.handle(), &settings.hotkey)?;
+            if let Err(err) = register_global_shortcuts(app.handle(), &settings) {
+                eprintln!("failed to register global shortcuts: {err}");
+            }
+            config::start_watcher(app.handle().clone(), state.clone());
+            backup::start_scheduler(state.clone());
+            images::start_scheduler(state.clone());
+            {
+                // Also the one-off backfill after upgrading to compressed text.
+                let state = state.clone();
+                std::thread::spawn(move || compress_long_texts(&state));
+            }
             clipboard::start_clipboard_pipeline(app.handle().clone(), state);
 
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             commands::get_settings,
+            commands::get_settings_schema,
             commands::set_setting,
+            commands::export_settings_config,
             commands::search_items,
+            commands::export_history,
+            commands::import_history,
+            commands::list_backups,
+            commands::create_backup,
+            commands::restore_backup,
+            commands::take_recovery_report,
+            commands::compress_history,
+            commands::find_similar_images,
             commands::get_item_preview,
             commands::open_item_path,
             commands::set_clipboard_item,
+            commands::paste_item,
+            commands::list_transforms,
+            commands::transform_item,
+            commands::merge_items,
+            commands::update_item_text,
+            commands::list_item_revisions,
+            commands::revert_item_revision,
+            commands::list_snippets,
+            commands::save_snippet,
+            commands::delete_snippet,
+            commands::render_snippet,
             commands::favorite_item,
             commands::pin_item,
+            commands::list_tags,
+            commands::create_tag,
+            commands::rename_tag,
+            commands::delete_tag,
+            commands::tag_item,
+            commands::list_collections,
+            commands::create_collection,
+            commands::rename_collection,
+            commands::delete_collection,
+            commands::add_to_collection,
+            commands::remove_from_collection,
+            commands::reorder_collection,
             commands::delete_item,
             commands::clear_history,
             commands::clear_all_history,
             commands::toggle_pause_capture,
+            commands::start_paste_stack,
+            commands::stop_paste_stack,
+            commands::get_paste_stack,
+            commands::finish_paste_stack,
+            commands::advance_paste_stack,
             commands::open_settings_window,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
 
-pub fn register_global_shortcut(app: &tauri::AppHandle, shortcut_str: &str) -> anyhow::Result<()> {
+/// Replaces the shortcuts we registered earlier with the ones `settings` asks
+/// for. Shortcuts owned by anything else are left alone. If any shortcut can't
+/// be registered, the previous set is restored and the failure is returned.
+pub fn register_global_shortcuts(
+    app: &tauri::AppHandle,
+    settings: &Settings,
+) -> Result<(), ShortcutError> {
+    let mut wanted: Vec<(Shortcut, HotkeyAction)> = Vec::new();
+    for (shortcut_str, action) in hotkey_bindings(settings) {
+        let shortcut = parse_shortcut(&shortcut_str)?;
+        if wanted.iter().any(|(other, _)| other.id() == shortcut.id()) {
+            return Err(ShortcutError::Conflict {
+                shortcut: format_shortcut(&shortcut),
+                message: "used by more than one Clip It shortcut".to_string(),
+            });
+        }
+        wanted.push((shortcut, action));
+    }
+
     let manager = app.global_shortcut();
-    let _ = manager.unregister_all();
+    let state = app.state::<Arc<SharedState>>();
+    let mut registered = state.hotkeys.lock().map_err(|_| ShortcutError::Internal {
+        message: "hotkey state is poisoned".to_string(),
+    })?;
 
-    let shortcut = commands::parse_shortcut(shortcut_str)
-        .ok_or_else(|| anyhow::anyhow!("invalid shortcut format: {shortcut_str}"))?;
+    let previous = std::mem::take(&mut *registered);
+    for (shortcut, _) in &previous {
+        let _ = manager.unregister(*shortcut);
+    }
 
-    manager.register(shortcut)?;
+    for (shortcut, action) in &wanted {
+        if let Err(err) = manager.register(*shortcut) {
+            for (added, _) in registered.drain(..) {
+                let _ = manager.unregister(added);
+            }
+            for (old, _) in &previous {
+                let _ = manager.register(*old);
+            }
+            *registered = previous;
+            return Err(ShortcutError::Conflict {
+                shortcut: format_shortcut(shortcut),
+                message: err.to_string(),
+            });
+        }
+        registered.push((*shortcut, *action));
+    }
 
     Ok(())
 }
 
+fn handle_hotkey(app: &tauri::AppHandle, shortcut: &Shortcut) {
+    let state = app.state::<Arc<SharedState>>();
+    let action = state.hotkeys.lock().ok().and_then(|hotkeys| {
+        hotkeys
+            .iter()
+            .find(|(registered, _)| registered.id() == shortcut.id())
+            .map(|(_, action)| *action)
+    });
+
+    let result = match action {
+        Some(HotkeyAction::ShowPopup) | None => {
+            commands::show_popup_window(app);
+            Ok(())
+        }
+        Some(HotkeyAction::CopyRecent(n)) => commands::copy_nth_item(&state, n, false),
+        Some(HotkeyAction::CopyPinned(n)) => commands::copy_nth_item(&state, n, true),
+    };
+    if let Err(err) = result {
+        eprintln!("hotkey action failed: {err}");
+    }
+}
+
+const TRAY_ID: &str = "main";
+const TRAY_TOOLTIP: &str = "Clip It";
+
+/// Shows the paste stack depth on the tray icon and tells the UI about the change.
+pub fn refresh_paste_stack_indicator(app: &tauri::AppHandle, stack: &PasteStackState) {
+    if let Some(tray) = app.tray_by_id(TRAY_ID) {
+        let (tooltip, title) = if stack.active {
+            (
+                format!("{TRAY_TOOLTIP} — paste stack: {}", stack.depth),
+                Some(stack.depth.to_string()),
+            )
+        } else {
+            (TRAY_TOOLTIP.to_string(), None)
+        };
+        let _ = tray.set_tooltip(Some(tooltip));
+        let _ = tray.set_title(title);
+    }
+
+    let _ = app.emit(
+        "paste_stack:changed",
+        PasteStackChangedEvent {
+            active: stack.active,
+            depth: stack.depth,
+        },
+    );
+}
+
+/// Compresses stored texts over `compress_text_above` that predate the setting
+/// or an earlier, higher threshold.
+fn compress_long_texts(state: &SharedState) {
+    let Ok(settings) = state.settings.read().map(|settings| settings.clone()) else {
+        eprintln!("failed to compress long texts: settings are poisoned");
+        return;
+    };
+    match storage::compress_long_texts(&state.readers, &state.storage, &settings) {
+        Ok(report) if report.items > 0 => eprintln!(
+            "compressed {} long texts, saving {} bytes",
+            report.items,
+            report.saved_bytes()
+        ),
+        Ok(_) => {}
+        Err(err) => eprintln!("failed to compress long texts: {err}"),
+    }
+}
+
+/// Re-indexes compressed texts after `compressed_search` changed.
+pub fn apply_compressed_search(state: &SharedState) {
+    if let Err(err) = storage::apply_compressed_search(&state.storage, &state.settings) {
+        eprintln!("failed to re-index compressed texts: {err}");
+    }
+}
+
 fn setup_tray(app: &tauri::AppHandle) -> anyhow::Result<()> {
     let show_item = MenuItem::with_id(app, "show_popup", "Show Clipboard", true, None::<&str>)?;
     let settings_item =
@@ -116,7 +345,8 @@ fn setup_tray(app: &tauri::AppHandle) -> anyhow::Result<()> {
     let menu = Menu::with_items(app, &[&show_item, &settings_item, &pause_item, &quit_item])?;
 
     #[allow(unused_mut)]
-    let mut tray_builder = TrayIconBuilder::new()
+    let mut tray_builder = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip(TRAY_TOOLTIP)
         .icon(tray_icon_image())
         .menu(&menu)
         .on_menu_event(|app, event: MenuEvent| match event.id().as_ref() {
@@ -148,7 +378,7 @@ fn setup_tray(app: &tauri::AppHandle) -> anyhow::Result<()> {
             } = event
             {
                 let app = tray.app_handle();
-                commands::show_popup_window(&app);
+                commands::show_popup_window(app);
             }
         });
 
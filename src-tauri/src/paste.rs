@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+
+/// Keystroke sent to apps without an override.
+pub const DEFAULT_PASTE_KEYS: &str = "ctrl+v";
+/// Terminals treat Ctrl+V as a literal control character.
+const TERMINAL_PASTE_KEYS: &str = "ctrl+shift+v";
+
+/// WM_CLASS values (lowercase) that get `TERMINAL_PASTE_KEYS` unless overridden.
+const TERMINAL_CLASSES: &[&str] = &[
+    "gnome-terminal",
+    "gnome-terminal-server",
+    "konsole",
+    "xterm",
+    "uxterm",
+    "urxvt",
+    "alacritty",
+    "kitty",
+    "tilix",
+    "terminator",
+    "xfce4-terminal",
+    "mate-terminal",
+    "lxterminal",
+    "wezterm",
+    "org.wezfurlong.wezterm",
+    "foot",
+    "st",
+];
+
+const XK_SHIFT_L: u32 = 0xffe1;
+const XK_CONTROL_L: u32 = 0xffe3;
+const XK_ALT_L: u32 = 0xffe9;
+const XK_SUPER_L: u32 = 0xffeb;
+const XK_INSERT: u32 = 0xff63;
+
+/// Picks the paste keystroke for a window from its WM_CLASS names. User
+/// overrides (keyed by lowercase class) win over the built-in terminal list.
+pub fn paste_keys_for(classes: &[String], overrides: &HashMap<String, String>) -> String {
+    let classes: Vec<String> = classes.iter().map(|c| c.to_lowercase()).collect();
+
+    if let Some(keys) = classes.iter().find_map(|class| overrides.get(class)) {
+        return keys.clone();
+    }
+    if classes
+        .iter()
+        .any(|class| TERMINAL_CLASSES.contains(&class.as_str()))
+    {
+        return TERMINAL_PASTE_KEYS.to_string();
+    }
+    DEFAULT_PASTE_KEYS.to_string()
+}
+
+/// Parses `ctrl+shift+v` style keystrokes into X keysyms, modifiers first and
+/// the key last, which is the order they are pressed in.
+pub fn parse_paste_keys(spec: &str) -> Option<Vec<u32>> {
+    let tokens: Vec<String> = spec.split('+').map(|t| t.trim().to_lowercase()).collect();
+    let (key, modifiers) = tokens.split_last()?;
+
+    let mut keysyms = Vec::with_capacity(tokens.len());
+    for modifier in modifiers {
+        keysyms.push(match modifier.as_str() {
+            "ctrl" | "control" => XK_CONTROL_L,
+            "shift" => XK_SHIFT_L,
+            "alt" => XK_ALT_L,
+            "super" | "meta" | "win" => XK_SUPER_L,
+            _ => return None,
+        });
+    }
+
+    let key = match key.as_str() {
+        "insert" | "ins" => XK_INSERT,
+        k if k.len() == 1 && k.chars().all(|c| c.is_ascii_alphanumeric()) => {
+            u32::from(k.as_bytes()[0])
+        }
+        _ => return None,
+    };
+    keysyms.push(key);
+    Some(keysyms)
+}
+
+/// Window that currently has focus, unless it belongs to this process. `None`
+/// where direct paste isn't supported (no X11 connection, macOS, Windows).
+pub fn focused_foreign_window() -> Option<u32> {
+    #[cfg(target_os = "linux")]
+    {
+        x11::active_window()
+            .ok()
+            .flatten()
+            .filter(|window| x11::window_pid(*window) != Some(std::process::id()))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Restores focus to `window` and synthesizes the paste keystroke for it.
+pub fn paste_into(window: u32, overrides: &HashMap<String, String>) -> anyhow::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        let keys = paste_keys_for(&x11::window_classes(window), overrides);
+        let keysyms = parse_paste_keys(&keys)
+            .ok_or_else(|| anyhow::anyhow!("invalid paste keystroke: {keys}"))?;
+        x11::focus_and_send(window, &keysyms)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (window, overrides);
+        anyhow::bail!("direct paste is only supported on X11")
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod x11 {
+    use std::thread;
+    use std::time::Duration;
+
+    use anyhow::{Context, Result};
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{
+        AtomEnum, ClientMessageEvent, ConnectionExt as _, EventMask, InputFocus, KEY_PRESS_EVENT,
+        KEY_RELEASE_EVENT,
+    };
+    use x11rb::protocol::xtest::ConnectionExt as _;
+    use x11rb::rust_connection::RustConnection;
+    use x11rb::wrapper::ConnectionExt as _;
+    use x11rb::CURRENT_TIME;
+
+    /// Time the window manager gets to hand focus back before keys are sent.
+    const FOCUS_SETTLE: Duration = Duration::from_millis(80);
+
+    fn connect() -> Result<(RustConnection, u32)> {
+        let (conn, screen) = x11rb::connect(None).context("failed to connect to X11")?;
+        let root = conn.setup().roots[screen].root;
+        Ok((conn, root))
+    }
+
+    fn atom(conn: &RustConnection, name: &[u8]) -> Result<u32> {
+        Ok(conn.intern_atom(false, name)?.reply()?.atom)
+    }
+
+    pub fn active_window() -> Result<Option<u32>> {
+        let (conn, root) = connect()?;
+        let net_active = atom(&conn, b"_NET_ACTIVE_WINDOW")?;
+        let reply = conn
+            .get_property(false, root, net_active, AtomEnum::WINDOW, 0, 1)?
+            .reply()?;
+        let ewmh = reply
+            .value32()
+            .and_then(|mut v| v.next())
+            .filter(|w| *w != 0);
+        if ewmh.is_some() {
+            return Ok(ewmh);
+        }
+
+        // No EWMH window manager (e.g. bare Xvfb): fall back to input focus.
+        let focus = conn.get_input_focus()?.reply()?.focus;
+        Ok((focus > 1 && focus != root).then_some(focus))
+    }
+
+    pub fn window_pid(window: u32) -> Option<u32> {
+        let (conn, _) = connect().ok()?;
+        let net_wm_pid = atom(&conn, b"_NET_WM_PID").ok()?;
+        let reply = conn
+            .get_property(false, window, net_wm_pid, AtomEnum::CARDINAL, 0, 1)
+            .ok()?
+            .reply()
+            .ok()?;
+        reply.value32().and_then(|mut v| v.next())
+    }
+
+    /// WM_CLASS instance and class names.
+    pub fn window_classes(window: u32) -> Vec<String> {
+        let Ok((conn, _)) = connect() else {
+            return Vec::new();
+        };
+        let reply = conn
+            .get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 256)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok());
+        reply
+            .map(|r| {
+                r.value
+                    .split(|b| *b == 0)
+                    .filter(|part| !part.is_empty())
+                    .map(|part| String::from_utf8_lossy(part).into_owned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn focus_and_send(window: u32, keysyms: &[u32]) -> Result<()> {
+        let (conn, root) = connect()?;
+
+        // Ask the window manager first, then set input focus directly for
+        // setups without one.
+        let net_active = atom(&conn, b"_NET_ACTIVE_WINDOW")?;
+        let activate = ClientMessageEvent::new(32, window, net_active, [2, CURRENT_TIME, 0, 0, 0]);
+        conn.send_event(
+            false,
+            root,
+            EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+            activate,
+        )?;
+        conn.set_input_focus(InputFocus::PARENT, window, CURRENT_TIME)?;
+        conn.sync()?;
+        thread::sleep(FOCUS_SETTLE);
+
+        let keycodes = keysyms
+            .iter()
+            .map(|keysym| keycode_for(&conn, *keysym))
+            .collect::<Result<Vec<u8>>>()?;
+
+        for keycode in &keycodes {
+            conn.xtest_fake_input(KEY_PRESS_EVENT, *keycode, CURRENT_TIME, root, 0, 0, 0)?;
+        }
+        for keycode in keycodes.iter().rev() {
+            conn.xtest_fake_input(KEY_RELEASE_EVENT, *keycode, CURRENT_TIME, root, 0, 0, 0)?;
+        }
+        conn.sync()?;
+        Ok(())
+    }
+
+    fn keycode_for(conn: &RustConnection, keysym: u32) -> Result<u8> {
+        let setup = conn.setup();
+        let (min, max) = (setup.min_keycode, setup.max_keycode);
+        let mapping = conn.get_keyboard_mapping(min, max - min + 1)?.reply()?;
+        let per = usize::from(mapping.keysyms_per_keycode).max(1);
+
+        mapping
+            .keysyms
+            .chunks(per)
+            .position(|syms| syms.contains(&keysym))
+            .map(|index| min + index as u8)
+            .with_context(|| format!("no keycode for keysym {keysym:#x}"))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use x11rb::connection::Connection;
+        use x11rb::protocol::xproto::{
+            ConnectionExt as _, CreateWindowAux, EventMask, WindowClass,
+        };
+        use x11rb::protocol::Event;
+
+        use super::{focus_and_send, keycode_for};
+
+        /// Needs an X server with XTest, e.g. `xvfb-run cargo test -- --ignored`.
+        #[test]
+        #[ignore]
+        fn sends_keystroke_to_focused_window() {
+            let (conn, screen) = x11rb::connect(None).expect("X11 display");
+            let root = conn.setup().roots[screen].root;
+            let window = conn.generate_id().expect("window id");
+            conn.create_window(
+                0,
+                window,
+                root,
+                0,
+                0,
+                100,
+                100,
+                0,
+                WindowClass::INPUT_OUTPUT,
+                0,
+                &CreateWindowAux::new().event_mask(EventMask::KEY_PRESS | EventMask::EXPOSURE),
+            )
+            .expect("create window");
+            conn.map_window(window).expect("map window");
+            conn.flush().expect("flush");
+            while !matches!(conn.wait_for_event().expect("event"), Event::Expose(_)) {}
+
+            focus_and_send(window, &[u32::from(b'v')]).expect("paste keystroke");
+
+            let v = keycode_for(&conn, u32::from(b'v')).expect("keycode");
+            loop {
+                if let Event::KeyPress(press) = conn.wait_for_event().expect("event") {
+                    assert_eq!(press.event, window);
+                    assert_eq!(press.detail, v);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{parse_paste_keys, paste_keys_for, XK_CONTROL_L, XK_INSERT, XK_SHIFT_L};
+
+    fn classes(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_keystrokes_modifiers_first() {
+        assert_eq!(
+            parse_paste_keys("Ctrl+Shift+V"),
+            Some(vec![XK_CONTROL_L, XK_SHIFT_L, u32::from(b'v')])
+        );
+        assert_eq!(
+            parse_paste_keys("shift+insert"),
+            Some(vec![XK_SHIFT_L, XK_INSERT])
+        );
+        assert_eq!(parse_paste_keys("hyper+v"), None);
+        assert_eq!(parse_paste_keys("ctrl+f12"), None);
+    }
+
+    #[test]
+    fn terminals_and_overrides_pick_their_keystroke() {
+        let mut overrides = HashMap::new();
+        assert_eq!(
+            paste_keys_for(&classes(&["Navigator", "firefox"]), &overrides),
+            "ctrl+v"
+        );
+        assert_eq!(
+            paste_keys_for(
+                &classes(&["gnome-terminal-server", "Gnome-terminal"]),
+                &overrides
+            ),
+            "ctrl+shift+v"
+        );
+
+        overrides.insert("xterm".to_string(), "shift+insert".to_string());
+        assert_eq!(
+            paste_keys_for(&classes(&["xterm", "XTerm"]), &overrides),
+            "shift+insert"
+        );
+    }
+}
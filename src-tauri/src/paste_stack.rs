@@ -0,0 +1,174 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StackOrder {
+    /// Paste back in the order things were copied.
+    #[default]
+    Fifo,
+    /// Paste the most recent copy first.
+    Lifo,
+}
+
+/// Collects captured item ids while active so they can be pasted back one by one.
+///
+/// `start` begins collecting: each capture is queued, and the clipboard holds
+/// the latest copy rather than the first entry. Collecting ends with
+/// `finish_collecting`, which the first consumption also does; from then on
+/// the app loads entries onto the clipboard itself, starting with the head,
+/// and its own loads aren't queued again. An entry leaves the queue once it's
+/// loaded (`pop_next`) or pasted directly (`remove`); `stop` drops the rest.
+#[derive(Debug, Default)]
+pub struct PasteStack {
+    active: bool,
+    collecting: bool,
+    order: StackOrder,
+    entries: VecDeque<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasteStackState {
+    pub active: bool,
+    /// Still queueing captures; nothing has been loaded from the stack yet.
+    pub collecting: bool,
+    pub order: StackOrder,
+    pub depth: usize,
+    /// Pending entries, next-to-paste first.
+    pub item_ids: Vec<i64>,
+}
+
+impl PasteStack {
+    pub fn start(&mut self, order: StackOrder) {
+        self.active = true;
+        self.collecting = true;
+        self.order = order;
+        self.entries.clear();
+    }
+
+    pub fn stop(&mut self) {
+        self.active = false;
+        self.collecting = false;
+        self.entries.clear();
+    }
+
+    /// Stops queueing captures, so the entries can be loaded back.
+    pub fn finish_collecting(&mut self) {
+        self.collecting = false;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn depth(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Queues a captured item. Ignored unless the stack is collecting.
+    pub fn push(&mut self, item_id: i64) -> bool {
+        if !self.collecting {
+            return false;
+        }
+        self.entries.push_back(item_id);
+        true
+    }
+
+    /// Takes the entry that should be pasted next.
+    pub fn pop_next(&mut self) -> Option<i64> {
+        match self.order {
+            StackOrder::Fifo => self.entries.pop_front(),
+            StackOrder::Lifo => self.entries.pop_back(),
+        }
+    }
+
+    /// Drops an entry that was pasted directly, so advancing doesn't load it again.
+    pub fn remove(&mut self, item_id: i64) -> bool {
+        let before = self.entries.len();
+        self.entries.retain(|&entry| entry != item_id);
+        self.entries.len() != before
+    }
+
+    pub fn state(&self) -> PasteStackState {
+        let item_ids = match self.order {
+            StackOrder::Fifo => self.entries.iter().copied().collect(),
+            StackOrder::Lifo => self.entries.iter().rev().copied().collect(),
+        };
+        PasteStackState {
+            active: self.active,
+            collecting: self.collecting,
+            order: self.order,
+            depth: self.entries.len(),
+            item_ids,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PasteStack, StackOrder};
+
+    #[test]
+    fn fifo_pastes_in_copy_order() {
+        let mut stack = PasteStack::default();
+        assert!(!stack.push(1));
+
+        stack.start(StackOrder::Fifo);
+        for id in [1, 2, 3] {
+            assert!(stack.push(id));
+        }
+        assert_eq!(stack.state().item_ids, vec![1, 2, 3]);
+        assert_eq!(stack.pop_next(), Some(1));
+        assert_eq!(stack.pop_next(), Some(2));
+        assert_eq!(stack.depth(), 1);
+    }
+
+    #[test]
+    fn finishing_collecting_stops_queueing_captures() {
+        let mut stack = PasteStack::default();
+        stack.start(StackOrder::Fifo);
+        assert!(stack.state().collecting);
+        for id in [1, 2, 3] {
+            stack.push(id);
+        }
+
+        stack.finish_collecting();
+        assert!(stack.is_active());
+        assert!(!stack.state().collecting);
+        // A capture after that, such as the loaded head itself, isn't queued.
+        assert_eq!(stack.pop_next(), Some(1));
+        assert!(!stack.push(1));
+        assert_eq!(stack.state().item_ids, vec![2, 3]);
+    }
+
+    #[test]
+    fn pasting_an_entry_directly_removes_it() {
+        let mut stack = PasteStack::default();
+        stack.start(StackOrder::Fifo);
+        for id in [1, 2, 3] {
+            stack.push(id);
+        }
+        assert!(stack.remove(1));
+        assert!(!stack.remove(7));
+        assert_eq!(stack.pop_next(), Some(2));
+        assert_eq!(stack.state().item_ids, vec![3]);
+    }
+
+    #[test]
+    fn lifo_pastes_latest_first_and_stop_clears() {
+        let mut stack = PasteStack::default();
+        stack.start(StackOrder::Lifo);
+        for id in [1, 2, 3] {
+            stack.push(id);
+        }
+        assert_eq!(stack.state().item_ids, vec![3, 2, 1]);
+        assert_eq!(stack.pop_next(), Some(3));
+
+        stack.stop();
+        assert!(!stack.is_active());
+        assert_eq!(stack.depth(), 0);
+        assert_eq!(stack.pop_next(), None);
+    }
+}
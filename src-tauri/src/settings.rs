@@ -1,4 +1,12 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::paste::parse_paste_keys;
+use crate::shortcut::canonicalize_shortcut;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
@@ -9,6 +17,75 @@ pub struct Settings {
     pub max_items: i64,
     pub window_opacity: i64,
     pub colored_icons: bool,
+    /// Paste keystroke per window class (lowercase WM_CLASS), e.g. `"kitty": "ctrl+shift+v"`.
+    pub paste_overrides: HashMap<String, String>,
+    /// Modifiers for `<prefix>+1`..`9` shortcuts that copy the Nth most recent
+    /// item, e.g. `Ctrl+Alt`. Empty disables them.
+    pub recent_hotkey_prefix: String,
+    /// Same as `recent_hotkey_prefix`, counting only pinned items.
+    pub pinned_hotkey_prefix: String,
+    /// Hours between automatic database snapshots; 0 turns them off.
+    pub backup_interval_hours: i64,
+    /// Snapshots kept in the backups folder; older ones are deleted.
+    pub backup_keep: i64,
+    /// Largest text capture stored as is, in UTF-8 bytes.
+    pub max_text_bytes: i64,
+    /// What happens to text over `max_text_bytes`.
+    pub oversize_text: OversizePolicy,
+    /// Largest image capture stored as raw pixels, in width × height.
+    pub max_image_pixels: i64,
+    /// What happens to images over `max_image_pixels`; `truncate` isn't offered.
+    pub oversize_image: OversizePolicy,
+    /// Text longer than this many bytes is stored zstd-compressed; 0 turns it off.
+    pub compress_text_above: i64,
+    /// How much of a compressed text search sees.
+    pub compressed_search: CompressedSearch,
+    /// Longest side of a stored image; larger ones are downscaled. 0 keeps them whole.
+    pub image_max_dimension: i64,
+    /// How stored images are encoded.
+    pub image_encoding: ImageEncoding,
+    /// WebP quality, 1–100; 100 encodes losslessly.
+    pub image_quality: i64,
+    /// Hours an image is kept as captured before the policy above applies.
+    pub image_keep_original_hours: i64,
+    /// Delete older images that look like a new capture.
+    pub collapse_similar_images: bool,
+    /// Largest perceptual-hash distance, in bits, at which two images count as similar.
+    pub similar_image_distance: i64,
+}
+
+/// How a capture over its size limit is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OversizePolicy {
+    /// Not recorded.
+    Skip,
+    /// Cut to the limit, with a marker saying how much was dropped.
+    Truncate,
+    /// Kept whole but compressed, next to a truncated copy for search and lists.
+    Compress,
+}
+
+/// What the search index keeps of a compressed text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressedSearch {
+    /// All of it, decompressed while indexing.
+    Full,
+    /// Only the head kept uncompressed for lists.
+    Truncated,
+}
+
+/// Encoding of stored images, applied with `image_max_dimension`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageEncoding {
+    /// Raw RGBA pixels, as captured.
+    Raw,
+    /// Lossless PNG.
+    Png,
+    /// WebP at `image_quality`.
+    Webp,
 }
 
 impl Default for Settings {
@@ -27,11 +104,558 @@ impl Default for Settings {
             max_items: 15,
             window_opacity: 78,
             colored_icons: true,
+            paste_overrides: HashMap::new(),
+            recent_hotkey_prefix: String::new(),
+            pinned_hotkey_prefix: String::new(),
+            backup_interval_hours: 24,
+            backup_keep: 7,
+            max_text_bytes: 1024 * 1024,
+            oversize_text: OversizePolicy::Truncate,
+            max_image_pixels: 4096 * 4096,
+            oversize_image: OversizePolicy::Compress,
+            compress_text_above: 64 * 1024,
+            compressed_search: CompressedSearch::Full,
+            image_max_dimension: 0,
+            image_encoding: ImageEncoding::Raw,
+            image_quality: 90,
+            image_keep_original_hours: 0,
+            collapse_similar_images: false,
+            similar_image_distance: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingKind {
+    Bool,
+    /// Whole number, clamped to `min`..=`max`.
+    Integer,
+    /// Full global shortcut, stored in canonical form.
+    Shortcut,
+    /// Modifiers only (`Ctrl+Alt`), or empty.
+    ModifierPrefix,
+    /// Object mapping window classes to paste keystrokes.
+    KeyMap,
+    /// One of `options`, lowercase.
+    Choice,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingSpec {
+    pub key: &'static str,
+    pub kind: SettingKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<&'static [&'static str]>,
+    pub description: &'static str,
+}
+
+const fn spec(key: &'static str, kind: SettingKind, description: &'static str) -> SettingSpec {
+    SettingSpec {
+        key,
+        kind,
+        min: None,
+        max: None,
+        options: None,
+        description,
+    }
+}
+
+const fn ranged(key: &'static str, min: i64, max: i64, description: &'static str) -> SettingSpec {
+    SettingSpec {
+        key,
+        kind: SettingKind::Integer,
+        min: Some(min),
+        max: Some(max),
+        options: None,
+        description,
+    }
+}
+
+const fn choice(
+    key: &'static str,
+    options: &'static [&'static str],
+    description: &'static str,
+) -> SettingSpec {
+    SettingSpec {
+        key,
+        kind: SettingKind::Choice,
+        min: None,
+        max: None,
+        options: Some(options),
+        description,
+    }
+}
+
+/// Every key `set_setting` accepts. Defaults come from `Settings::default()`.
+pub const SETTING_SPECS: &[SettingSpec] = &[
+    spec(
+        "hotkey",
+        SettingKind::Shortcut,
+        "Global shortcut that opens the clipboard popup.",
+    ),
+    spec(
+        "blur_close",
+        SettingKind::Bool,
+        "Hide the popup when it loses focus.",
+    ),
+    ranged(
+        "polling_interval_ms",
+        100,
+        5000,
+        "How often the clipboard is checked for changes, in milliseconds.",
+    ),
+    spec(
+        "capture_enabled",
+        SettingKind::Bool,
+        "Whether clipboard capture starts enabled.",
+    ),
+    ranged(
+        "max_items",
+        10,
+        5000,
+        "History size; pinned, favorite, tagged, collected and imported items don't count.",
+    ),
+    ranged(
+        "window_opacity",
+        35,
+        100,
+        "Popup background opacity, in percent.",
+    ),
+    spec(
+        "colored_icons",
+        SettingKind::Bool,
+        "Use colored icons for item kinds.",
+    ),
+    spec(
+        "paste_overrides",
+        SettingKind::KeyMap,
+        "Paste keystroke per window class for direct paste, e.g. {\"kitty\": \"ctrl+shift+v\"}.",
+    ),
+    spec(
+        "recent_hotkey_prefix",
+        SettingKind::ModifierPrefix,
+        "Modifiers for the 1-9 shortcuts that copy the Nth most recent item. Empty disables them.",
+    ),
+    spec(
+        "pinned_hotkey_prefix",
+        SettingKind::ModifierPrefix,
+        "Modifiers for the 1-9 shortcuts that copy the Nth pinned item. Empty disables them.",
+    ),
+    ranged(
+        "backup_interval_hours",
+        0,
+        168,
+        "Hours between automatic database backups. 0 turns them off.",
+    ),
+    ranged(
+        "backup_keep",
+        1,
+        50,
+        "How many backups to keep; the oldest are deleted first.",
+    ),
+    ranged(
+        "max_text_bytes",
+        4096,
+        256 * 1024 * 1024,
+        "Largest text capture stored as is, in bytes.",
+    ),
+    choice(
+        "oversize_text",
+        &["skip", "truncate", "compress"],
+        "Text over max_text_bytes: skip it, truncate it, or compress it whole.",
+    ),
+    ranged(
+        "max_image_pixels",
+        256 * 256,
+        256 * 1024 * 1024,
+        "Largest image capture stored as raw pixels, in width x height.",
+    ),
+    choice(
+        "oversize_image",
+        &["skip", "compress"],
+        "Images over max_image_pixels: skip them or store them PNG-compressed.",
+    ),
+    ranged(
+        "compress_text_above",
+        0,
+        256 * 1024 * 1024,
+        "Text longer than this many bytes is stored zstd-compressed; 0 turns compression off.",
+    ),
+    choice(
+        "compressed_search",
+        &["full", "truncated"],
+        "Search compressed texts in full, or only their first 4 KiB.",
+    ),
+    ranged(
+        "image_max_dimension",
+        0,
+        65535,
+        "Longest side of stored images in pixels; larger ones are downscaled. 0 keeps full size.",
+    ),
+    choice(
+        "image_encoding",
+        &["raw", "png", "webp"],
+        "How stored images are encoded: raw pixels, lossless PNG, or WebP.",
+    ),
+    ranged(
+        "image_quality",
+        1,
+        100,
+        "WebP quality; 100 encodes losslessly.",
+    ),
+    ranged(
+        "image_keep_original_hours",
+        0,
+        24 * 30,
+        "Hours to keep an image as captured before downscaling and re-encoding it.",
+    ),
+    spec(
+        "collapse_similar_images",
+        SettingKind::Bool,
+        "Replace older copies of an image that look the same, e.g. a repeated or slightly resized screenshot.",
+    ),
+    // Unrelated images differ in about half of the 64 bits; past 16 the
+    // collapse would start removing images that merely share a layout.
+    ranged(
+        "similar_image_distance",
+        0,
+        16,
+        "How many of the 64 perceptual-hash bits two images may differ in and still count as similar.",
+    ),
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingSchema {
+    #[serde(flatten)]
+    pub spec: SettingSpec,
+    pub default: Value,
+}
+
+pub fn settings_schema() -> Vec<SettingSchema> {
+    let defaults = serde_json::to_value(Settings::default()).unwrap_or_default();
+    SETTING_SPECS
+        .iter()
+        .map(|spec| SettingSchema {
+            spec: spec.clone(),
+            default: defaults.get(spec.key).cloned().unwrap_or(Value::Null),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum SettingValueError {
+    UnknownKey { key: String },
+    WrongType { key: String, expected: SettingKind },
+    InvalidValue { key: String, reason: String },
+}
+
+impl fmt::Display for SettingValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownKey { key } => write!(f, "unknown setting \"{key}\""),
+            Self::WrongType { key, expected } => {
+                write!(f, "setting \"{key}\" expects a {expected:?} value")
+            }
+            Self::InvalidValue { key, reason } => {
+                write!(f, "invalid value for \"{key}\": {reason}")
+            }
         }
     }
 }
 
+impl std::error::Error for SettingValueError {}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppliedSetting {
+    pub key: String,
+    /// The value actually stored, after clamping or canonicalising.
+    pub value: Value,
+    /// `true` when `value` differs from what the caller sent.
+    pub adjusted: bool,
+}
+
+impl Settings {
+    /// Validates `value` against the schema for `key` and stores it.
+    pub fn apply(&mut self, key: &str, value: &Value) -> Result<AppliedSetting, SettingValueError> {
+        let spec = SETTING_SPECS
+            .iter()
+            .find(|spec| spec.key == key)
+            .ok_or_else(|| SettingValueError::UnknownKey {
+                key: key.to_string(),
+            })?;
+        let applied = normalize_value(spec, value)?;
+
+        match spec.key {
+            "hotkey" => self.hotkey = typed(key, &applied)?,
+            "blur_close" => self.blur_close = typed(key, &applied)?,
+            "polling_interval_ms" => self.polling_interval_ms = typed(key, &applied)?,
+            "capture_enabled" => self.capture_enabled = typed(key, &applied)?,
+            "max_items" => self.max_items = typed(key, &applied)?,
+            "window_opacity" => self.window_opacity = typed(key, &applied)?,
+            "colored_icons" => self.colored_icons = typed(key, &applied)?,
+            "paste_overrides" => self.paste_overrides = typed(key, &applied)?,
+            "recent_hotkey_prefix" => self.recent_hotkey_prefix = typed(key, &applied)?,
+            "pinned_hotkey_prefix" => self.pinned_hotkey_prefix = typed(key, &applied)?,
+            "backup_interval_hours" => self.backup_interval_hours = typed(key, &applied)?,
+            "backup_keep" => self.backup_keep = typed(key, &applied)?,
+            "max_text_bytes" => self.max_text_bytes = typed(key, &applied)?,
+            "oversize_text" => self.oversize_text = typed(key, &applied)?,
+            "max_image_pixels" => self.max_image_pixels = typed(key, &applied)?,
+            "oversize_image" => self.oversize_image = typed(key, &applied)?,
+            "compress_text_above" => self.compress_text_above = typed(key, &applied)?,
+            "compressed_search" => self.compressed_search = typed(key, &applied)?,
+            "image_max_dimension" => self.image_max_dimension = typed(key, &applied)?,
+            "image_encoding" => self.image_encoding = typed(key, &applied)?,
+            "image_quality" => self.image_quality = typed(key, &applied)?,
+            "image_keep_original_hours" => self.image_keep_original_hours = typed(key, &applied)?,
+            "collapse_similar_images" => self.collapse_similar_images = typed(key, &applied)?,
+            "similar_image_distance" => self.similar_image_distance = typed(key, &applied)?,
+            _ => unreachable!("every SETTING_SPECS key is handled"),
+        }
+
+        Ok(AppliedSetting {
+            key: key.to_string(),
+            adjusted: applied != *value,
+            value: applied,
+        })
+    }
+}
+
+/// Type-checks `value` for `spec` and returns the value to store.
+fn normalize_value(spec: &SettingSpec, value: &Value) -> Result<Value, SettingValueError> {
+    let wrong_type = || SettingValueError::WrongType {
+        key: spec.key.to_string(),
+        expected: spec.kind,
+    };
+    let invalid = |reason: String| SettingValueError::InvalidValue {
+        key: spec.key.to_string(),
+        reason,
+    };
+
+    let normalized = match spec.kind {
+        SettingKind::Bool => Value::Bool(value.as_bool().ok_or_else(wrong_type)?),
+        SettingKind::Integer => {
+            let v = value.as_i64().ok_or_else(wrong_type)?;
+            Value::from(v.clamp(spec.min.unwrap_or(i64::MIN), spec.max.unwrap_or(i64::MAX)))
+        }
+        SettingKind::Shortcut => {
+            let shortcut = value.as_str().ok_or_else(wrong_type)?;
+            Value::String(canonicalize_shortcut(shortcut).map_err(|e| invalid(e.to_string()))?)
+        }
+        SettingKind::ModifierPrefix => {
+            let prefix = value.as_str().ok_or_else(wrong_type)?.trim();
+            if prefix.is_empty() {
+                Value::String(String::new())
+            } else {
+                // Canonicalise through a full shortcut so a stray key is rejected.
+                let canonical = canonicalize_shortcut(&format!("{prefix}+1"))
+                    .map_err(|e| invalid(e.to_string()))?;
+                match canonical.strip_suffix("+1") {
+                    Some(modifiers) if !modifiers.is_empty() => {
+                        Value::String(modifiers.to_string())
+                    }
+                    _ => return Err(invalid("expected modifiers such as Ctrl+Alt".to_string())),
+                }
+            }
+        }
+        SettingKind::KeyMap => {
+            let mut map = Map::new();
+            for (class, keys) in value.as_object().ok_or_else(wrong_type)? {
+                let keys = keys.as_str().ok_or_else(wrong_type)?.trim();
+                if parse_paste_keys(keys).is_none() {
+                    return Err(invalid(format!("unsupported keystroke \"{keys}\"")));
+                }
+                map.insert(class.to_lowercase(), Value::String(keys.to_lowercase()));
+            }
+            Value::Object(map)
+        }
+        SettingKind::Choice => {
+            let chosen = value.as_str().ok_or_else(wrong_type)?.trim().to_lowercase();
+            let options = spec.options.unwrap_or_default();
+            if !options.contains(&chosen.as_str()) {
+                return Err(invalid(format!("expected one of {}", options.join(", "))));
+            }
+            Value::String(chosen)
+        }
+    };
+
+    Ok(normalized)
+}
+
+fn typed<T: DeserializeOwned>(key: &str, value: &Value) -> Result<T, SettingValueError> {
+    serde_json::from_value(value.clone()).map_err(|e| SettingValueError::InvalidValue {
+        key: key.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotkeyAction {
+    ShowPopup,
+    CopyRecent(u32),
+    CopyPinned(u32),
+}
+
+/// Number of slot shortcuts per prefix (`1`..`9`).
+const HOTKEY_SLOTS: u32 = 9;
+
+/// Every global shortcut the settings ask for, paired with what it does. The
+/// popup hotkey comes first.
+pub fn hotkey_bindings(settings: &Settings) -> Vec<(String, HotkeyAction)> {
+    let mut bindings = vec![(settings.hotkey.clone(), HotkeyAction::ShowPopup)];
+
+    let prefixes = [
+        (&settings.recent_hotkey_prefix, false),
+        (&settings.pinned_hotkey_prefix, true),
+    ];
+    for (prefix, pinned_only) in prefixes {
+        if prefix.is_empty() {
+            continue;
+        }
+        for n in 1..=HOTKEY_SLOTS {
+            let action = if pinned_only {
+                HotkeyAction::CopyPinned(n)
+            } else {
+                HotkeyAction::CopyRecent(n)
+            };
+            bindings.push((format!("{prefix}+{n}"), action));
+        }
+    }
+
+    bindings
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct PauseState {
     pub paused: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{
+        hotkey_bindings, settings_schema, HotkeyAction, OversizePolicy, SettingKind,
+        SettingValueError, Settings,
+    };
+
+    #[test]
+    fn slot_hotkeys_follow_prefixes() {
+        let mut settings = Settings {
+            hotkey: "Ctrl+Shift+P".to_string(),
+            ..Settings::default()
+        };
+        assert_eq!(
+            hotkey_bindings(&settings),
+            vec![("Ctrl+Shift+P".to_string(), HotkeyAction::ShowPopup)]
+        );
+
+        settings.recent_hotkey_prefix = "Ctrl+Alt".to_string();
+        settings.pinned_hotkey_prefix = "Ctrl+Alt+Shift".to_string();
+        let bindings = hotkey_bindings(&settings);
+        assert_eq!(bindings.len(), 19);
+        assert_eq!(
+            bindings[1],
+            ("Ctrl+Alt+1".to_string(), HotkeyAction::CopyRecent(1))
+        );
+        assert_eq!(
+            bindings[18],
+            ("Ctrl+Alt+Shift+9".to_string(), HotkeyAction::CopyPinned(9))
+        );
+    }
+
+    #[test]
+    fn schema_covers_every_setting() {
+        let defaults = serde_json::to_value(Settings::default()).expect("serialize");
+        let mut fields: Vec<&str> = defaults
+            .as_object()
+            .expect("object")
+            .keys()
+            .map(String::as_str)
+            .collect();
+        let schema = settings_schema();
+        let mut keys: Vec<&str> = schema.iter().map(|s| s.spec.key).collect();
+        fields.sort_unstable();
+        keys.sort_unstable();
+        assert_eq!(keys, fields);
+        assert!(schema.iter().all(|s| !s.default.is_null()));
+    }
+
+    #[test]
+    fn apply_clamps_and_reports_the_stored_value() {
+        let mut settings = Settings::default();
+
+        let applied = settings.apply("max_items", &json!(1)).expect("valid");
+        assert_eq!(applied.value, json!(10));
+        assert!(applied.adjusted);
+        assert_eq!(settings.max_items, 10);
+
+        let applied = settings.apply("blur_close", &json!(false)).expect("valid");
+        assert!(!applied.adjusted);
+        assert!(!settings.blur_close);
+
+        let applied = settings
+            .apply("hotkey", &json!("shift+ctrl+k"))
+            .expect("valid");
+        assert_eq!(applied.value, json!("Ctrl+Shift+K"));
+        assert_eq!(settings.hotkey, "Ctrl+Shift+K");
+
+        let applied = settings
+            .apply("recent_hotkey_prefix", &json!("alt+ctrl"))
+            .expect("valid");
+        assert_eq!(applied.value, json!("Ctrl+Alt"));
+
+        settings
+            .apply("paste_overrides", &json!({"Kitty": "Ctrl+Shift+V"}))
+            .expect("valid");
+        assert_eq!(settings.paste_overrides["kitty"], "ctrl+shift+v");
+
+        let applied = settings
+            .apply("oversize_text", &json!("Compress"))
+            .expect("valid");
+        assert_eq!(applied.value, json!("compress"));
+        assert_eq!(settings.oversize_text, OversizePolicy::Compress);
+    }
+
+    #[test]
+    fn apply_rejects_unknown_keys_and_bad_values() {
+        let mut settings = Settings::default();
+        let before = settings.max_items;
+
+        assert_eq!(
+            settings.apply("nope", &json!(1)).unwrap_err(),
+            SettingValueError::UnknownKey {
+                key: "nope".to_string()
+            }
+        );
+        assert_eq!(
+            settings.apply("max_items", &json!("many")).unwrap_err(),
+            SettingValueError::WrongType {
+                key: "max_items".to_string(),
+                expected: SettingKind::Integer,
+            }
+        );
+        assert_eq!(settings.max_items, before);
+
+        for (key, value) in [
+            ("hotkey", json!("Ctrl+Nope")),
+            ("recent_hotkey_prefix", json!("Ctrl+A")),
+            ("paste_overrides", json!({"xterm": "hyper+v"})),
+            ("oversize_image", json!("truncate")),
+        ] {
+            assert!(matches!(
+                settings.apply(key, &value),
+                Err(SettingValueError::InvalidValue { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Serialize;
+use tauri_plugin_global_shortcut::{Code, Modifiers, Shortcut};
+
+/// Keys with a friendlier canonical name than their W3C code, plus the
+/// lowercase aliases accepted when parsing.
+const NAMED_KEYS: &[(&str, Code, &[&str])] = &[
+    ("Up", Code::ArrowUp, &["arrowup", "uparrow"]),
+    ("Down", Code::ArrowDown, &["arrowdown", "downarrow"]),
+    ("Left", Code::ArrowLeft, &["arrowleft", "leftarrow"]),
+    ("Right", Code::ArrowRight, &["arrowright", "rightarrow"]),
+    ("Space", Code::Space, &["spacebar"]),
+    ("Enter", Code::Enter, &["return"]),
+    ("Tab", Code::Tab, &[]),
+    ("Escape", Code::Escape, &["esc"]),
+    ("Backspace", Code::Backspace, &[]),
+    ("Delete", Code::Delete, &["del"]),
+    ("Insert", Code::Insert, &["ins"]),
+    ("Home", Code::Home, &[]),
+    ("End", Code::End, &[]),
+    ("PageUp", Code::PageUp, &["pgup"]),
+    ("PageDown", Code::PageDown, &["pgdn"]),
+    ("PrintScreen", Code::PrintScreen, &["print", "prtsc"]),
+    ("Pause", Code::Pause, &["break"]),
+    ("ScrollLock", Code::ScrollLock, &[]),
+    ("CapsLock", Code::CapsLock, &[]),
+    ("NumLock", Code::NumLock, &[]),
+    ("Comma", Code::Comma, &[","]),
+    ("Period", Code::Period, &["."]),
+    ("Slash", Code::Slash, &["/"]),
+    ("Semicolon", Code::Semicolon, &[";"]),
+    ("Quote", Code::Quote, &["'", "apostrophe"]),
+    ("BracketLeft", Code::BracketLeft, &["["]),
+    ("BracketRight", Code::BracketRight, &["]"]),
+    ("Backslash", Code::Backslash, &["\\"]),
+    ("Minus", Code::Minus, &["-"]),
+    ("Equal", Code::Equal, &["=", "equals"]),
+    ("Backquote", Code::Backquote, &["`", "grave"]),
+    ("NumAdd", Code::NumpadAdd, &["numpadadd", "numplus"]),
+    (
+        "NumSubtract",
+        Code::NumpadSubtract,
+        &["numpadsubtract", "numminus"],
+    ),
+    (
+        "NumMultiply",
+        Code::NumpadMultiply,
+        &["numpadmultiply", "nummult"],
+    ),
+    ("NumDivide", Code::NumpadDivide, &["numpaddivide", "numdiv"]),
+    (
+        "NumDecimal",
+        Code::NumpadDecimal,
+        &["numpaddecimal", "numdot"],
+    ),
+    ("NumEnter", Code::NumpadEnter, &["numpadenter"]),
+    (
+        "PlayPause",
+        Code::MediaPlayPause,
+        &["mediaplaypause", "play"],
+    ),
+    ("Stop", Code::MediaStop, &["mediastop"]),
+    (
+        "NextTrack",
+        Code::MediaTrackNext,
+        &["mediatracknext", "medianext"],
+    ),
+    (
+        "PrevTrack",
+        Code::MediaTrackPrevious,
+        &["mediatrackprevious", "mediaprev"],
+    ),
+    ("VolumeUp", Code::AudioVolumeUp, &["audiovolumeup", "volup"]),
+    (
+        "VolumeDown",
+        Code::AudioVolumeDown,
+        &["audiovolumedown", "voldown"],
+    ),
+    (
+        "VolumeMute",
+        Code::AudioVolumeMute,
+        &["audiovolumemute", "mute"],
+    ),
+];
+
+/// Modifier order used by `format_shortcut`.
+const MODIFIER_ORDER: &[(Modifiers, &str)] = &[
+    (Modifiers::CONTROL, "Ctrl"),
+    (Modifiers::ALT, "Alt"),
+    (Modifiers::SHIFT, "Shift"),
+    (Modifiers::SUPER, SUPER_NAME),
+];
+
+const SUPER_NAME: &str = if cfg!(target_os = "macos") {
+    "Cmd"
+} else {
+    "Super"
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum ShortcutError {
+    /// The string doesn't describe a shortcut.
+    Invalid { shortcut: String, reason: String },
+    /// The OS refused the registration, usually because another app (or another
+    /// of our own shortcuts) holds it.
+    Conflict { shortcut: String, message: String },
+    /// Registration failed for a reason unrelated to the shortcut itself.
+    Internal { message: String },
+}
+
+impl fmt::Display for ShortcutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid { shortcut, reason } => {
+                write!(f, "invalid shortcut \"{shortcut}\": {reason}")
+            }
+            Self::Conflict { shortcut, message } => {
+                write!(f, "shortcut \"{shortcut}\" is unavailable: {message}")
+            }
+            Self::Internal { message } => write!(f, "failed to register shortcuts: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ShortcutError {}
+
+/// Parses `Ctrl+Shift+P` style shortcuts. Modifiers may name a side
+/// (`LCtrl`, `RightAlt`, `ShiftLeft`); global shortcuts can't tell the sides
+/// apart, so both map to the same modifier. Keys are matched case-insensitively
+/// by common name, or exactly by W3C `KeyboardEvent.code` name.
+pub fn parse_shortcut(shortcut: &str) -> Result<Shortcut, ShortcutError> {
+    let invalid = |reason: String| ShortcutError::Invalid {
+        shortcut: shortcut.to_string(),
+        reason,
+    };
+
+    let mut mods = Modifiers::empty();
+    let mut key: Option<Code> = None;
+
+    for token in shortcut.split('+').map(str::trim) {
+        if token.is_empty() {
+            return Err(invalid("empty key name".to_string()));
+        }
+        if let Some(modifier) = parse_modifier(token) {
+            mods |= modifier;
+            continue;
+        }
+        let code = parse_key(token).ok_or_else(|| invalid(format!("unknown key \"{token}\"")))?;
+        if key.replace(code).is_some() {
+            return Err(invalid("only one non-modifier key is allowed".to_string()));
+        }
+    }
+
+    let key = key.ok_or_else(|| invalid("missing a non-modifier key".to_string()))?;
+    Ok(Shortcut::new(Some(mods), key))
+}
+
+/// Canonical text for a shortcut: modifiers in `Ctrl+Alt+Shift+Super` order,
+/// then the key. `parse_shortcut` accepts everything this produces.
+pub fn format_shortcut(shortcut: &Shortcut) -> String {
+    let mut parts: Vec<String> = MODIFIER_ORDER
+        .iter()
+        .filter(|(modifier, _)| shortcut.mods.contains(*modifier))
+        .map(|(_, name)| name.to_string())
+        .collect();
+    parts.push(format_key(shortcut.key));
+    parts.join("+")
+}
+
+/// Parses and re-formats, so equivalent spellings are stored the same way.
+pub fn canonicalize_shortcut(shortcut: &str) -> Result<String, ShortcutError> {
+    parse_shortcut(shortcut).map(|s| format_shortcut(&s))
+}
+
+fn parse_modifier(token: &str) -> Option<Modifiers> {
+    let lower: String = token
+        .to_lowercase()
+        .chars()
+        .filter(|c| !matches!(c, '_' | '-' | ' '))
+        .collect();
+    let sided = ["left", "right"]
+        .iter()
+        .find_map(|side| {
+            lower
+                .strip_prefix(side)
+                .or_else(|| lower.strip_suffix(side))
+        })
+        .or_else(|| {
+            lower
+                .strip_prefix(['l', 'r'])
+                .filter(|rest| modifier_named(rest).is_some())
+        })
+        .unwrap_or(&lower);
+    modifier_named(sided)
+}
+
+fn modifier_named(name: &str) -> Option<Modifiers> {
+    match name {
+        "ctrl" | "control" | "ctl" => Some(Modifiers::CONTROL),
+        "shift" => Some(Modifiers::SHIFT),
+        "alt" | "option" | "opt" => Some(Modifiers::ALT),
+        "super" | "cmd" | "command" | "meta" | "win" | "windows" | "os" => Some(Modifiers::SUPER),
+        _ => None,
+    }
+}
+
+fn parse_key(token: &str) -> Option<Code> {
+    let lower = token.to_lowercase();
+
+    if let [c] = lower.as_bytes() {
+        if c.is_ascii_lowercase() {
+            return Code::from_str(&format!("Key{}", c.to_ascii_uppercase() as char)).ok();
+        }
+        if c.is_ascii_digit() {
+            return Code::from_str(&format!("Digit{}", *c as char)).ok();
+        }
+    }
+    if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+        if (1..=24).contains(&n) {
+            return Code::from_str(&format!("F{n}")).ok();
+        }
+    }
+    if let Some(d) = lower
+        .strip_prefix("numpad")
+        .or_else(|| lower.strip_prefix("num"))
+        .filter(|d| d.len() == 1 && d.as_bytes()[0].is_ascii_digit())
+    {
+        return Code::from_str(&format!("Numpad{d}")).ok();
+    }
+    if let Some((_, code, _)) = NAMED_KEYS
+        .iter()
+        .find(|(name, _, aliases)| name.eq_ignore_ascii_case(&lower) || aliases.contains(&&*lower))
+    {
+        return Some(*code);
+    }
+
+    Code::from_str(token)
+        .ok()
+        .filter(|code| !is_modifier_code(*code) && *code != Code::Unidentified)
+}
+
+fn format_key(code: Code) -> String {
+    if let Some((name, _, _)) = NAMED_KEYS.iter().find(|(_, c, _)| *c == code) {
+        return name.to_string();
+    }
+    let w3c = code.to_string();
+    if let Some(rest) = w3c
+        .strip_prefix("Key")
+        .or_else(|| w3c.strip_prefix("Digit"))
+        .filter(|rest| rest.len() == 1)
+    {
+        return rest.to_string();
+    }
+    if let Some(digit) = w3c.strip_prefix("Numpad").filter(|d| d.len() == 1) {
+        return format!("Num{digit}");
+    }
+    w3c
+}
+
+fn is_modifier_code(code: Code) -> bool {
+    matches!(
+        code,
+        Code::ControlLeft
+            | Code::ControlRight
+            | Code::ShiftLeft
+            | Code::ShiftRight
+            | Code::AltLeft
+            | Code::AltRight
+            | Code::MetaLeft
+            | Code::MetaRight
+            | Code::Fn
+            | Code::FnLock
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use tauri_plugin_global_shortcut::{Code, Modifiers};
+
+    use super::{canonicalize_shortcut, format_shortcut, parse_shortcut, ShortcutError};
+
+    fn key(shortcut: &str) -> Code {
+        parse_shortcut(shortcut).expect("valid shortcut").key
+    }
+
+    #[test]
+    fn parses_the_full_key_set() {
+        assert_eq!(key("Ctrl+1"), Code::Digit1);
+        assert_eq!(key("Alt+f12"), Code::F12);
+        assert_eq!(key("Ctrl+F24"), Code::F24);
+        assert_eq!(key("Super+up"), Code::ArrowUp);
+        assert_eq!(key("Ctrl+Space"), Code::Space);
+        assert_eq!(key("Ctrl+return"), Code::Enter);
+        assert_eq!(key("Ctrl+,"), Code::Comma);
+        assert_eq!(key("Ctrl+`"), Code::Backquote);
+        assert_eq!(key("Ctrl+Num5"), Code::Numpad5);
+        assert_eq!(key("Ctrl+numplus"), Code::NumpadAdd);
+        assert_eq!(key("PlayPause"), Code::MediaPlayPause);
+        assert_eq!(key("Ctrl+NumpadMemoryAdd"), Code::NumpadMemoryAdd);
+        assert_eq!(key("Ctrl+LaunchMail"), Code::LaunchMail);
+    }
+
+    #[test]
+    fn left_and_right_modifiers_collapse() {
+        let shortcut = parse_shortcut("LCtrl+RightShift+AltLeft+meta_right+k").expect("valid");
+        assert_eq!(
+            shortcut.mods,
+            Modifiers::CONTROL | Modifiers::SHIFT | Modifiers::ALT | Modifiers::SUPER
+        );
+        assert_eq!(shortcut.key, Code::KeyK);
+        // Bare arrow names are keys, not sided modifiers.
+        assert_eq!(key("Ctrl+Left"), Code::ArrowLeft);
+    }
+
+    #[test]
+    fn formatting_round_trips() {
+        for input in [
+            "shift+ctrl+p",
+            "Ctrl+Alt+9",
+            "ctrl+arrowdown",
+            "alt+numpad0",
+            "Ctrl+Shift+/",
+            "F13",
+            "Ctrl+MediaSelect",
+        ] {
+            let canonical = canonicalize_shortcut(input).expect("valid");
+            let reparsed = parse_shortcut(&canonical).expect("canonical parses");
+            assert_eq!(reparsed, parse_shortcut(input).expect("valid"));
+            assert_eq!(format_shortcut(&reparsed), canonical);
+        }
+        assert_eq!(
+            canonicalize_shortcut("shift+ctrl+p").unwrap(),
+            "Ctrl+Shift+P"
+        );
+        assert_eq!(canonicalize_shortcut("alt + numpad0").unwrap(), "Alt+Num0");
+    }
+
+    #[test]
+    fn rejects_malformed_shortcuts() {
+        for input in [
+            "",
+            "Ctrl+",
+            "Ctrl+Shift",
+            "Ctrl+A+B",
+            "Ctrl+Bogus",
+            "Ctrl+Unidentified",
+        ] {
+            assert!(
+                matches!(parse_shortcut(input), Err(ShortcutError::Invalid { .. })),
+                "{input} should be invalid"
+            );
+        }
+    }
+}
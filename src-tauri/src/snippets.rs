@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+const DEFAULT_TIME_FORMAT: &str = "%H:%M";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(String),
+    Date(String),
+    Time(String),
+    Clipboard,
+    Uuid,
+    Input(String),
+    Cursor,
+}
+
+/// Values a template can pull from at render time.
+pub struct RenderContext {
+    pub now: DateTime<Local>,
+    pub clipboard: Option<String>,
+    pub inputs: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderedSnippet {
+    pub text: String,
+    /// Char offset of the `{cursor}` placeholder in `text`, if the template has one.
+    pub cursor: Option<usize>,
+}
+
+/// Checks that a template parses and only uses known placeholders.
+pub fn validate_template(template: &str) -> Result<()> {
+    parse(template).map(|_| ())
+}
+
+/// Names of the `{input:Name}` placeholders, in first-use order, so the UI can
+/// prompt for them before rendering.
+pub fn template_inputs(template: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for token in parse(template).unwrap_or_default() {
+        if let Token::Input(name) = token {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+pub fn uses_clipboard(template: &str) -> bool {
+    parse(template)
+        .map(|tokens| tokens.contains(&Token::Clipboard))
+        .unwrap_or(false)
+}
+
+pub fn render_template(template: &str, ctx: &RenderContext) -> Result<RenderedSnippet> {
+    let mut text = String::new();
+    let mut cursor = None;
+
+    for token in parse(template)? {
+        match token {
+            Token::Literal(literal) => text.push_str(&literal),
+            Token::Date(format) | Token::Time(format) => {
+                text.push_str(&ctx.now.format(&format).to_string())
+            }
+            Token::Clipboard => text.push_str(ctx.clipboard.as_deref().unwrap_or_default()),
+            Token::Uuid => text.push_str(&uuid::Uuid::new_v4().to_string()),
+            Token::Input(name) => match ctx.inputs.get(&name) {
+                Some(value) => text.push_str(value),
+                None => bail!("missing value for input \"{name}\""),
+            },
+            Token::Cursor => {
+                if cursor.is_none() {
+                    cursor = Some(text.chars().count());
+                }
+            }
+        }
+    }
+
+    Ok(RenderedSnippet { text, cursor })
+}
+
+/// Splits a template into literals and placeholders. `{{` and `}}` escape braces.
+fn parse(template: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut body = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => body.push(c),
+                        None => bail!("unterminated placeholder \"{{{body}\""),
+                    }
+                }
+                if !literal.is_empty() {
+                    tokens.push(Token::Literal(std::mem::take(&mut literal)));
+                }
+                tokens.push(parse_placeholder(&body)?);
+            }
+            _ => literal.push(c),
+        }
+    }
+
+    if !literal.is_empty() {
+        tokens.push(Token::Literal(literal));
+    }
+    Ok(tokens)
+}
+
+fn parse_placeholder(body: &str) -> Result<Token> {
+    let (name, arg) = match body.split_once(':') {
+        Some((name, arg)) => (name.trim(), Some(arg)),
+        None => (body.trim(), None),
+    };
+
+    let token = match (name, arg) {
+        ("date", format) => Token::Date(checked_format(format.unwrap_or(DEFAULT_DATE_FORMAT))?),
+        ("time", format) => Token::Time(checked_format(format.unwrap_or(DEFAULT_TIME_FORMAT))?),
+        ("clipboard", None) => Token::Clipboard,
+        ("uuid", None) => Token::Uuid,
+        ("cursor", None) => Token::Cursor,
+        ("input", Some(label)) if !label.trim().is_empty() => {
+            Token::Input(label.trim().to_string())
+        }
+        ("input", _) => bail!("{{input}} needs a name, e.g. {{input:Name}}"),
+        _ => bail!("unknown placeholder \"{{{body}}}\""),
+    };
+    Ok(token)
+}
+
+/// chrono panics when displaying an invalid strftime string, so reject it up front.
+fn checked_format(format: &str) -> Result<String> {
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        bail!("invalid date/time format \"{format}\"");
+    }
+    Ok(format.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::{Local, TimeZone};
+
+    use super::{
+        render_template, template_inputs, uses_clipboard, validate_template, RenderContext,
+    };
+
+    fn ctx(inputs: &[(&str, &str)]) -> RenderContext {
+        RenderContext {
+            now: Local
+                .with_ymd_and_hms(2024, 3, 9, 14, 5, 0)
+                .single()
+                .expect("valid time"),
+            clipboard: Some("copied".to_string()),
+            inputs: inputs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>(),
+        }
+    }
+
+    #[test]
+    fn renders_date_time_clipboard_and_inputs() {
+        let out = render_template(
+            "{date} {time} {date:%d/%m} [{clipboard}] Hi {input:Name}, {{ok}}",
+            &ctx(&[("Name", "Ada")]),
+        )
+        .expect("render");
+        assert_eq!(out.text, "2024-03-09 14:05 09/03 [copied] Hi Ada, {ok}");
+        assert_eq!(out.cursor, None);
+    }
+
+    #[test]
+    fn cursor_is_removed_and_reported() {
+        let out = render_template("SELECT * FROM {cursor} WHERE é", &ctx(&[])).expect("render");
+        assert_eq!(out.text, "SELECT * FROM  WHERE é");
+        assert_eq!(out.cursor, Some(14));
+    }
+
+    #[test]
+    fn uuid_placeholder_is_random_v4() {
+        let a = render_template("{uuid}", &ctx(&[])).expect("render").text;
+        let b = render_template("{uuid}", &ctx(&[])).expect("render").text;
+        assert_eq!(a.len(), 36);
+        assert_eq!(a.chars().nth(14), Some('4'));
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn rejects_bad_templates() {
+        assert!(validate_template("{nope}").is_err());
+        assert!(validate_template("{input}").is_err());
+        assert!(validate_template("{date:%Q}").is_err());
+        assert!(validate_template("unterminated {date").is_err());
+        assert!(render_template("{input:Missing}", &ctx(&[])).is_err());
+    }
+
+    #[test]
+    fn lists_inputs_once_in_order() {
+        let template = "{input:To} {input:From} {input:To} {clipboard}";
+        assert_eq!(template_inputs(template), vec!["To", "From"]);
+        assert!(uses_clipboard(template));
+        assert!(!uses_clipboard("{date}"));
+    }
+}
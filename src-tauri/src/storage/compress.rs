@@ -0,0 +1,723 @@
+//! Out-of-line zstd storage for long text. A compressed item keeps only a short
+//! head in `text` for lists; the whole text lives in `text_zstd`. Search covers
+//! the whole text (kept in `search_text` for the index) or just the head, per
+//! `compressed_search`.
+
+use std::sync::{Mutex, RwLock};
+
+use rusqlite::functions::{Context as FunctionContext, FunctionFlags};
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection, Row};
+use serde::Serialize;
+
+use super::{ReadPool, Storage};
+use crate::error::{Context, Result};
+use crate::settings::{CompressedSearch, Settings};
+
+const TEXT_ZSTD_LEVEL: i32 = 3;
+
+/// Bytes of a compressed item's text kept readable in `text`.
+pub const TEXT_HEAD_BYTES: usize = 4096;
+
+/// Rows compressed per transaction by `compress_long_texts`.
+const COMPRESS_BATCH: usize = 64;
+
+/// Compresses an item's full text for `NewItem::text_zstd`.
+pub fn compress_text(text: &str) -> Result<Vec<u8>> {
+    Ok(zstd::encode_all(text.as_bytes(), TEXT_ZSTD_LEVEL)?)
+}
+
+/// The first `limit` bytes of `text`, cut at a character boundary, followed by
+/// a line saying how long the original was.
+pub fn truncate_with_marker(text: &str, limit: usize) -> String {
+    let mut end = limit.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!(
+        "{}\n[… truncated, {} bytes in total]",
+        &text[..end],
+        text.len()
+    )
+}
+
+/// A long text as `store_capture` and `compress_long_texts` store it.
+#[derive(Debug)]
+pub struct CompressedText {
+    /// Stored in `text`.
+    pub head: String,
+    /// Stored in `text_zstd`.
+    pub zstd: Vec<u8>,
+}
+
+impl CompressedText {
+    pub fn new(text: &str) -> Result<Self> {
+        Ok(Self {
+            head: truncate_with_marker(text, TEXT_HEAD_BYTES),
+            zstd: compress_text(text)?,
+        })
+    }
+
+    /// Bytes the text takes in its row once compressed.
+    pub fn stored_bytes(&self) -> u64 {
+        (self.head.len() + self.zstd.len()) as u64
+    }
+}
+
+/// A text as it goes into `text`, `text_zstd` and `search_text`.
+#[derive(Debug)]
+pub struct StoredText {
+    pub text: String,
+    pub zstd: Option<Vec<u8>>,
+    pub search_text: Option<String>,
+}
+
+impl StoredText {
+    /// Compressed when over `compress_text_above`, as captures are.
+    pub fn new(text: String, settings: &Settings) -> Result<Self> {
+        if !should_compress(&text, settings) {
+            return Ok(Self::plain(text));
+        }
+        let zstd = compress_text(&text)?;
+        Ok(Self::compressed(text, zstd, settings))
+    }
+
+    pub fn plain(text: String) -> Self {
+        Self {
+            text,
+            zstd: None,
+            search_text: None,
+        }
+    }
+
+    /// `text` with `zstd`, its `compress_text` output.
+    pub fn compressed(text: String, zstd: Vec<u8>, settings: &Settings) -> Self {
+        Self {
+            text: truncate_with_marker(&text, TEXT_HEAD_BYTES),
+            zstd: Some(zstd),
+            search_text: (settings.compressed_search == CompressedSearch::Full).then_some(text),
+        }
+    }
+}
+
+/// Whether text over `settings.compress_text_above` bytes should be compressed.
+pub fn should_compress(text: &str, settings: &Settings) -> bool {
+    settings.compress_text_above > 0 && text.len() as i64 > settings.compress_text_above
+}
+
+/// Outcome of `compress_long_texts`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompressionReport {
+    pub items: u64,
+    /// Plain-text bytes of those items before.
+    pub bytes_before: u64,
+    /// Head plus compressed bytes after.
+    pub bytes_after: u64,
+}
+
+impl CompressionReport {
+    pub fn saved_bytes(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+impl Storage {
+    /// Compresses every stored text over `compress_text_above` bytes that isn't
+    /// compressed yet, on this one connection. Freed pages are reused by later
+    /// captures; the file itself only shrinks on `VACUUM`.
+    pub fn compress_long_texts(&self, settings: &Settings) -> Result<CompressionReport> {
+        compress_in_batches(
+            settings,
+            |after_id| self.texts_to_compress(settings.compress_text_above, after_id),
+            |batch, search_full| self.store_compressed_texts(batch, search_full),
+        )
+    }
+
+    /// Up to `COMPRESS_BATCH` uncompressed texts over `above` bytes with ids
+    /// above `after_id`, in id order.
+    fn texts_to_compress(&self, above: i64, after_id: i64) -> Result<Vec<(i64, String)>> {
+        // `octet_length` reads the size from the record header, not the text.
+        let mut stmt = self.conn.prepare(
+            "SELECT id, text FROM items
+             WHERE id > ?1 AND kind != 'image' AND text_zstd IS NULL
+               AND octet_length(text) > ?2
+             ORDER BY id
+             LIMIT ?3",
+        )?;
+        let rows = stmt.query_map(params![after_id, above, COMPRESS_BATCH], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Writes one batch from `texts_to_compress`, skipping rows whose text
+    /// changed since it was read.
+    fn store_compressed_texts(
+        &self,
+        batch: &[(i64, String, CompressedText)],
+        search_full: bool,
+    ) -> Result<CompressionReport> {
+        let mut report = CompressionReport::default();
+        let tx = self.conn.unchecked_transaction()?;
+        for (id, text, compressed) in batch {
+            let changed = tx.execute(
+                "UPDATE items SET text = ?1, text_zstd = ?2, search_text = ?3
+                 WHERE id = ?4 AND text_zstd IS NULL AND text = ?5",
+                params![
+                    compressed.head,
+                    compressed.zstd,
+                    search_full.then_some(text),
+                    id,
+                    text
+                ],
+            )?;
+            if changed > 0 {
+                report.items += 1;
+                report.bytes_before += text.len() as u64;
+                report.bytes_after += compressed.stored_bytes();
+            }
+        }
+        tx.commit()?;
+        Ok(report)
+    }
+
+    /// Brings up to `COMPRESS_BATCH` compressed rows with ids above `after_id`
+    /// in line with `full`: `search_text` holds the decompressed text, or
+    /// nothing. The update trigger re-indexes each row. Returns how many rows
+    /// changed and the last id looked at, `None` once there are no more.
+    fn sync_search_text(&self, full: bool, after_id: i64) -> Result<(usize, Option<i64>)> {
+        let tx = self.conn.unchecked_transaction()?;
+        let rows = {
+            let mut stmt = tx.prepare(
+                "SELECT id, CASE WHEN ?2 THEN text_zstd END FROM items
+                 WHERE id > ?1 AND text_zstd IS NOT NULL AND (search_text IS NULL) = ?2
+                 ORDER BY id
+                 LIMIT ?3",
+            )?;
+            let rows = stmt.query_map(params![after_id, full, COMPRESS_BATCH], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, Option<Vec<u8>>>(1)?))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+
+        let mut changed = 0;
+        for (id, zstd) in &rows {
+            let search_text = match zstd.as_deref().map(decompress_text) {
+                Some(Ok(text)) => Some(text),
+                Some(Err(reason)) => {
+                    eprintln!("item {id}: compressed text left unindexed: {reason}");
+                    continue;
+                }
+                None => None,
+            };
+            changed += tx.execute(
+                "UPDATE items SET search_text = ?1 WHERE id = ?2",
+                params![search_text, id],
+            )?;
+        }
+        tx.commit()?;
+        Ok((changed, rows.last().map(|(id, _)| *id)))
+    }
+
+    /// Databases from before `search_text` flagged rows searched in full with
+    /// `search_full` and indexed them through an app-only SQL function. Fills
+    /// `search_text` for those rows and drops the flag; runs after the index
+    /// was recreated, so the triggers index the filled rows.
+    pub(super) fn migrate_search_full(&self) -> Result<()> {
+        let flagged: bool = self.conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('items') WHERE name = 'search_full'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !flagged {
+            return Ok(());
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        let ids = tx
+            .prepare("SELECT id FROM items WHERE search_full != 0 AND text_zstd IS NOT NULL")?
+            .query_map([], |row| row.get::<_, i64>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for id in ids {
+            let zstd: Vec<u8> =
+                tx.query_row("SELECT text_zstd FROM items WHERE id = ?1", [id], |row| {
+                    row.get(0)
+                })?;
+            match decompress_text(&zstd) {
+                Ok(text) => {
+                    tx.execute(
+                        "UPDATE items SET search_text = ?1 WHERE id = ?2",
+                        params![text, id],
+                    )?;
+                }
+                Err(reason) => eprintln!("item {id}: compressed text left unindexed: {reason}"),
+            }
+        }
+        tx.execute_batch("ALTER TABLE items DROP COLUMN search_full")?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+/// Re-indexes compressed texts after `compressed_search` changed, so rows
+/// stored earlier are searched the new way too. The setting is read before
+/// every batch, so a change made meanwhile wins.
+pub fn apply_compressed_search(
+    writer: &Mutex<Storage>,
+    settings: &RwLock<Settings>,
+) -> Result<usize> {
+    let mut changed = 0;
+    let mut applied = None;
+    let mut after_id = 0;
+    loop {
+        let full = settings.read()?.compressed_search == CompressedSearch::Full;
+        if applied != Some(full) {
+            applied = Some(full);
+            after_id = 0;
+        }
+        let (batch, last_id) = writer.lock()?.sync_search_text(full, after_id)?;
+        changed += batch;
+        match last_id {
+            Some(id) => after_id = id,
+            None => return Ok(changed),
+        }
+    }
+}
+
+/// `Storage::compress_long_texts` for the running app: texts are read on a
+/// pooled reader and compressed without holding the writer, which is only
+/// taken to store each batch, so captures keep flowing in between.
+pub fn compress_long_texts(
+    readers: &ReadPool,
+    writer: &Mutex<Storage>,
+    settings: &Settings,
+) -> Result<CompressionReport> {
+    compress_in_batches(
+        settings,
+        |after_id| {
+            readers
+                .get()?
+                .texts_to_compress(settings.compress_text_above, after_id)
+        },
+        |batch, search_full| writer.lock()?.store_compressed_texts(batch, search_full),
+    )
+}
+
+fn compress_in_batches(
+    settings: &Settings,
+    mut read: impl FnMut(i64) -> Result<Vec<(i64, String)>>,
+    mut write: impl FnMut(&[(i64, String, CompressedText)], bool) -> Result<CompressionReport>,
+) -> Result<CompressionReport> {
+    let mut report = CompressionReport::default();
+    if settings.compress_text_above <= 0 {
+        return Ok(report);
+    }
+    let search_full = settings.compressed_search == CompressedSearch::Full;
+
+    let mut after_id = 0_i64;
+    loop {
+        let texts = read(after_id)?;
+        let Some((last_id, _)) = texts.last() else {
+            return Ok(report);
+        };
+        after_id = *last_id;
+
+        let mut batch = Vec::with_capacity(texts.len());
+        for (id, text) in texts {
+            let compressed = CompressedText::new(&text)?;
+            batch.push((id, text, compressed));
+        }
+        let written = write(&batch, search_full)?;
+        report.items += written.items;
+        report.bytes_before += written.bytes_before;
+        report.bytes_after += written.bytes_after;
+    }
+}
+
+/// The item's full text: `text_zstd` decompressed when present, else `text`.
+pub(super) fn full_text(
+    row: &Row<'_>,
+    text: usize,
+    text_zstd: usize,
+) -> rusqlite::Result<Option<String>> {
+    let Some(compressed) = row.get::<_, Option<Vec<u8>>>(text_zstd)? else {
+        return row.get(text);
+    };
+    decompress_text(&compressed).map(Some).map_err(|reason| {
+        rusqlite::Error::FromSqlConversionFailure(
+            text_zstd,
+            rusqlite::types::Type::Blob,
+            format!("unreadable compressed text: {reason}").into(),
+        )
+    })
+}
+
+fn decompress_text(compressed: &[u8]) -> std::result::Result<String, String> {
+    zstd::decode_all(compressed)
+        .map_err(|err| err.to_string())
+        .and_then(|bytes| String::from_utf8(bytes).map_err(|err| err.to_string()))
+}
+
+/// Registers `item_full_text(text, text_zstd)`, an item's whole text for
+/// queries that match it in SQL, such as regex search: `text_zstd`
+/// decompressed when present, else `text`. Only queries call it, never the
+/// schema, so other SQLite clients can still write items.
+pub(super) fn register_full_text_function(conn: &Connection) -> Result<()> {
+    conn.create_scalar_function(
+        "item_full_text",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        item_full_text,
+    )
+    .context("failed to register item_full_text function")?;
+    Ok(())
+}
+
+fn item_full_text(ctx: &FunctionContext<'_>) -> rusqlite::Result<Option<String>> {
+    if let ValueRef::Blob(compressed) = ctx.get_raw(1) {
+        // A damaged blob still leaves the head to match.
+        if let Ok(text) = decompress_text(compressed) {
+            return Ok(Some(text));
+        }
+    }
+    Ok(match ctx.get_raw(0) {
+        ValueRef::Text(bytes) => Some(String::from_utf8_lossy(bytes).into_owned()),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, RwLock};
+
+    use super::{apply_compressed_search, compress_long_texts, CompressedText, TEXT_HEAD_BYTES};
+    use crate::settings::{CompressedSearch, Settings};
+    use crate::storage::{ReadPool, SearchParams, Storage};
+    use crate::test_support::{insert_text, temp_db_path};
+
+    /// A log whose only mention of `needle` is past the kept head.
+    fn long_log(needle: &str) -> String {
+        let mut log = "GET /health 200 in 3ms\n".repeat(2000);
+        log.push_str(needle);
+        log
+    }
+
+    fn hits(storage: &Storage, query: &str) -> u32 {
+        storage
+            .search_items(&SearchParams {
+                query: query.to_string(),
+                limit: 50,
+                ..Default::default()
+            })
+            .expect("search")
+            .total
+    }
+
+    fn check_fts(storage: &Storage) {
+        storage
+            .conn
+            .execute_batch("INSERT INTO items_fts(items_fts) VALUES ('integrity-check')")
+            .expect("fts integrity");
+    }
+
+    #[test]
+    fn backfill_compresses_long_texts_and_keeps_them_searchable() {
+        let db_path = temp_db_path("backfill");
+        let storage = Storage::open(&db_path).expect("open db");
+        let log = long_log("panicked at worker.rs");
+        let id = insert_text(&storage, &log);
+        insert_text(&storage, "short note");
+
+        let settings = Settings {
+            compress_text_above: 1024,
+            ..Settings::default()
+        };
+        let report = storage.compress_long_texts(&settings).expect("compress");
+        assert_eq!(report.items, 1);
+        assert_eq!(report.bytes_before, log.len() as u64);
+        assert!(report.saved_bytes() > log.len() as u64 / 2, "{report:?}");
+        assert_eq!(
+            storage.compress_long_texts(&settings).expect("again").items,
+            0
+        );
+
+        let (head, compressed): (String, bool) = storage
+            .conn
+            .query_row(
+                "SELECT text, text_zstd IS NOT NULL FROM items WHERE id = ?1",
+                [id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .expect("row");
+        assert!(compressed);
+        assert!(head.len() < TEXT_HEAD_BYTES + 64);
+        let payload = storage
+            .get_item_clipboard_payload(id)
+            .expect("payload")
+            .expect("item");
+        assert_eq!(payload.text.as_deref(), Some(log.as_str()));
+        assert_eq!(hits(&storage, "worker"), 1);
+        check_fts(&storage);
+
+        storage
+            .conn
+            .execute("DELETE FROM items WHERE id = ?1", [id])
+            .expect("delete");
+        assert_eq!(hits(&storage, "worker"), 0);
+        check_fts(&storage);
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn pooled_backfill_skips_texts_edited_since_they_were_read() {
+        let db_path = temp_db_path("pooled");
+        let storage = Storage::open(&db_path).expect("open db");
+        let log = long_log("panicked at worker.rs");
+        let edited = insert_text(&storage, &log);
+        insert_text(&storage, &long_log("timed out"));
+
+        let read = storage.texts_to_compress(1024, 0).expect("read");
+        assert_eq!(read.len(), 2);
+        storage
+            .conn
+            .execute(
+                "UPDATE items SET text = 'short now' WHERE id = ?1",
+                [edited],
+            )
+            .expect("edit");
+        let batch: Vec<_> = read
+            .into_iter()
+            .map(|(id, text)| {
+                let compressed = CompressedText::new(&text).expect("compress");
+                (id, text, compressed)
+            })
+            .collect();
+        let report = storage.store_compressed_texts(&batch, true).expect("store");
+        assert_eq!(report.items, 1);
+        let edited_text: String = storage
+            .conn
+            .query_row("SELECT text FROM items WHERE id = ?1", [edited], |row| {
+                row.get(0)
+            })
+            .expect("row");
+        assert_eq!(edited_text, "short now");
+
+        let readers = ReadPool::open(&db_path, 1).expect("readers");
+        let writer = Mutex::new(storage);
+        insert_text(&writer.lock().expect("writer"), &log);
+        let settings = Settings {
+            compress_text_above: 1024,
+            ..Settings::default()
+        };
+        let report = compress_long_texts(&readers, &writer, &settings).expect("backfill");
+        assert_eq!(report.items, 1);
+        assert!(readers
+            .get()
+            .expect("reader")
+            .texts_to_compress(1024, 0)
+            .expect("read")
+            .is_empty());
+        assert_eq!(hits(&writer.lock().expect("writer"), "worker"), 1);
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn truncated_search_only_sees_the_head() {
+        let db_path = temp_db_path("truncated");
+        let storage = Storage::open(&db_path).expect("open db");
+        insert_text(&storage, &long_log("panicked at worker.rs"));
+
+        let settings = Settings {
+            compress_text_above: 1024,
+            compressed_search: CompressedSearch::Truncated,
+            ..Settings::default()
+        };
+        assert_eq!(
+            storage
+                .compress_long_texts(&settings)
+                .expect("compress")
+                .items,
+            1
+        );
+        assert_eq!(hits(&storage, "worker"), 0);
+        assert_eq!(hits(&storage, "health"), 1);
+        check_fts(&storage);
+
+        // Switching back re-indexes the rows compressed meanwhile.
+        let settings = RwLock::new(Settings {
+            compressed_search: CompressedSearch::Full,
+            ..settings
+        });
+        let writer = Mutex::new(storage);
+        assert_eq!(
+            apply_compressed_search(&writer, &settings).expect("re-index"),
+            1
+        );
+        let storage = writer.into_inner().expect("writer");
+        assert_eq!(hits(&storage, "worker"), 1);
+        check_fts(&storage);
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn regex_search_and_export_match_past_the_head() {
+        let db_path = temp_db_path("regex-full");
+        let storage = Storage::open(&db_path).expect("open db");
+        insert_text(&storage, &long_log("panicked at worker.rs:42"));
+        let settings = Settings {
+            compress_text_above: 1024,
+            compressed_search: CompressedSearch::Truncated,
+            ..Settings::default()
+        };
+        storage.compress_long_texts(&settings).expect("compress");
+
+        let params = SearchParams {
+            query: r"worker\.rs:\d+".to_string(),
+            mode: Some("regex".to_string()),
+            limit: 50,
+            ..Default::default()
+        };
+        let found = storage.search_items(&params).expect("search");
+        assert_eq!(found.total, 1);
+        let hit = &found.items[0];
+        let range = hit.match_ranges[0];
+        let highlighted: String = hit
+            .preview_text
+            .chars()
+            .skip(range.start)
+            .take(range.end - range.start)
+            .collect();
+        assert_eq!(highlighted, "worker.rs:42");
+
+        let exported = storage
+            .for_each_export_item(&params, |_| Ok(()))
+            .expect("export");
+        assert_eq!(exported, 1);
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn edited_texts_are_compressed_like_captures() {
+        let db_path = temp_db_path("edited");
+        let storage = Storage::open(&db_path).expect("open db");
+        let id = insert_text(&storage, "short note");
+        let settings = Settings {
+            compress_text_above: 1024,
+            ..Settings::default()
+        };
+        let log = long_log("panicked at worker.rs");
+        assert!(storage
+            .update_item_text(id, &log, "fp-log", None, None, &settings)
+            .expect("edit"));
+
+        let compressed: bool = storage
+            .conn
+            .query_row(
+                "SELECT text_zstd IS NOT NULL AND search_text IS NOT NULL FROM items WHERE id = ?1",
+                [id],
+                |row| row.get(0),
+            )
+            .expect("row");
+        assert!(compressed);
+        let payload = storage
+            .get_item_clipboard_payload(id)
+            .expect("payload")
+            .expect("item");
+        assert_eq!(payload.text.as_deref(), Some(log.as_str()));
+        assert_eq!(hits(&storage, "worker"), 1);
+        check_fts(&storage);
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn plain_connections_can_write_items_and_rebuild_the_index() {
+        let db_path = temp_db_path("plain");
+        let storage = Storage::open(&db_path).expect("open db");
+        let settings = Settings {
+            compress_text_above: 1024,
+            ..Settings::default()
+        };
+        insert_text(&storage, &long_log("panicked at worker.rs"));
+        storage.compress_long_texts(&settings).expect("compress");
+
+        // No app functions registered, as in the sqlite3 shell.
+        let conn = rusqlite::Connection::open(&db_path).expect("plain connection");
+        conn.execute_batch(
+            "INSERT INTO items(created_at, kind, text, fingerprint) VALUES (1, 'text', 'from a shell', 'fp-shell');
+             UPDATE items SET text = 'edited in a shell' WHERE fingerprint = 'fp-shell';
+             DELETE FROM items WHERE kind = 'text' AND text_zstd IS NOT NULL;
+             INSERT INTO items_fts(items_fts) VALUES ('rebuild');",
+        )
+        .expect("plain writes");
+        drop(conn);
+
+        assert_eq!(hits(&storage, "shell"), 1);
+        assert_eq!(hits(&storage, "worker"), 0);
+        check_fts(&storage);
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn upgrading_moves_full_search_flags_to_search_text() {
+        let db_path = temp_db_path("search-full");
+        {
+            let storage = Storage::open(&db_path).expect("open db");
+            insert_text(&storage, &long_log("panicked at worker.rs"));
+            let settings = Settings {
+                compress_text_above: 1024,
+                compressed_search: CompressedSearch::Truncated,
+                ..Settings::default()
+            };
+            storage.compress_long_texts(&settings).expect("compress");
+            storage
+                .conn
+                .execute_batch(
+                    "ALTER TABLE items ADD COLUMN search_full INTEGER NOT NULL DEFAULT 0;
+                     UPDATE items SET search_full = 1 WHERE text_zstd IS NOT NULL;
+                     PRAGMA user_version = 7;",
+                )
+                .expect("downgrade");
+        }
+
+        let storage = Storage::open(&db_path).expect("reopen");
+        assert_eq!(hits(&storage, "worker"), 1);
+        let flagged: i64 = storage
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('items') WHERE name = 'search_full'",
+                [],
+                |row| row.get(0),
+            )
+            .expect("columns");
+        assert_eq!(flagged, 0);
+        check_fts(&storage);
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn upgrading_rebuilds_the_search_index() {
+        let db_path = temp_db_path("upgrade");
+        {
+            let storage = Storage::open(&db_path).expect("open db");
+            insert_text(&storage, "kept across the upgrade");
+            storage
+                .conn
+                .pragma_update(None, "user_version", 5)
+                .expect("downgrade");
+        }
+
+        let storage = Storage::open(&db_path).expect("reopen");
+        assert_eq!(hits(&storage, "upgrade"), 1);
+        check_fts(&storage);
+
+        let _ = std::fs::remove_file(db_path);
+    }
+}
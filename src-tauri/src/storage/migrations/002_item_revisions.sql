@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS item_revisions (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  item_id INTEGER NOT NULL REFERENCES items(id) ON DELETE CASCADE,
+  text TEXT NOT NULL,
+  fingerprint TEXT NOT NULL,
+  created_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_item_revisions_item ON item_revisions(item_id, created_at DESC);
@@ -0,0 +1,36 @@
+-- Index `search_text` where a compressed item is searched in full, else
+-- `text`. The FTS table reads snippets through the `items_search` view, which
+-- blanks out char(1) and char(2): `snippet()` marks hits with them, and both
+-- separate tokens anyway. Everything here is plain SQL, so any client can
+-- write items and rebuild the index.
+DROP TRIGGER IF EXISTS items_ai;
+DROP TRIGGER IF EXISTS items_ad;
+DROP TRIGGER IF EXISTS items_au;
+DROP TABLE IF EXISTS items_fts;
+DROP VIEW IF EXISTS items_search;
+
+CREATE VIEW items_search AS
+  SELECT id, replace(replace(COALESCE(search_text, text, ''), char(1), ' '), char(2), ' ') AS text
+  FROM items;
+
+CREATE VIRTUAL TABLE items_fts
+USING fts5(text, content='items_search', content_rowid='id');
+
+CREATE TRIGGER items_ai AFTER INSERT ON items BEGIN
+  INSERT INTO items_fts(rowid, text)
+  VALUES (new.id, replace(replace(COALESCE(new.search_text, new.text, ''), char(1), ' '), char(2), ' '));
+END;
+
+CREATE TRIGGER items_ad AFTER DELETE ON items BEGIN
+  INSERT INTO items_fts(items_fts, rowid, text)
+  VALUES ('delete', old.id, replace(replace(COALESCE(old.search_text, old.text, ''), char(1), ' '), char(2), ' '));
+END;
+
+CREATE TRIGGER items_au AFTER UPDATE OF text, search_text ON items BEGIN
+  INSERT INTO items_fts(items_fts, rowid, text)
+  VALUES ('delete', old.id, replace(replace(COALESCE(old.search_text, old.text, ''), char(1), ' '), char(2), ' '));
+  INSERT INTO items_fts(rowid, text)
+  VALUES (new.id, replace(replace(COALESCE(new.search_text, new.text, ''), char(1), ' '), char(2), ' '));
+END;
+
+INSERT INTO items_fts(items_fts) VALUES ('rebuild');
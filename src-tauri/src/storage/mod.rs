@@ -1,13 +1,37 @@
+mod compress;
+mod pool;
+mod recovery;
+
+use std::cell::RefCell;
 use std::collections::HashSet;
 use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use anyhow::{Context, Result};
-use rusqlite::{params, Connection, OptionalExtension};
-use serde::Serialize;
+use regex::{Regex, RegexBuilder};
+use rusqlite::backup::{Backup, StepResult};
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::{ToSql, ValueRef};
+use rusqlite::{params, Connection, ErrorCode, OpenFlags, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::settings::Settings;
+use crate::classify::classify_text;
+use crate::error::{ClipItError, Context, Result};
+use crate::events::{
+    HistoryClearedEvent, ImagesCollapsedEvent, ItemChange, ItemDeletedEvent, ItemUpdatedEvent,
+    ItemsPrunedEvent, StorageEvent, ITEM_EVENT_SCHEMA_VERSION,
+};
+use crate::images::hash_distance;
+use crate::settings::{ImageEncoding, Settings};
+use crate::snippets::template_inputs;
+use compress::full_text;
+
+pub use compress::{
+    apply_compressed_search, compress_long_texts, compress_text, truncate_with_marker,
+    CompressionReport, StoredText,
+};
+pub use pool::{ReadPool, READ_POOL_SIZE};
+pub use recovery::RecoveryReport;
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -15,12 +39,79 @@ pub struct SearchItem {
     pub id: i64,
     pub created_at: i64,
     pub kind: String,
+    pub subtype: Option<String>,
+    pub language: Option<String>,
     pub text: String,
     pub preview_text: String,
+    /// Highlighted hits inside `preview_text`, as char offsets.
+    pub match_ranges: Vec<MatchRange>,
     pub image_width: Option<i64>,
     pub image_height: Option<i64>,
     pub favorite: bool,
     pub pinned: bool,
+    pub tags: Vec<String>,
+}
+
+/// A highlighted span of `preview_text`, in UTF-16 code units so the UI can
+/// slice its JavaScript strings with it directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MatchRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchParams {
+    pub query: String,
+    pub limit: u32,
+    pub offset: u32,
+    /// `all` (default), `favorites` or `pinned`.
+    pub filter: Option<String>,
+    /// `recent` (default) or `relevance`; only applies to text queries.
+    pub sort: Option<String>,
+    /// `text` (default, FTS prefix match) or `regex`.
+    pub mode: Option<String>,
+    /// Restricts results to one text subtype, e.g. `url` or `color`.
+    pub subtype: Option<String>,
+    pub tag_id: Option<i64>,
+    /// Restricts results to one collection; with an empty query they come back
+    /// in the collection's manual order.
+    pub collection_id: Option<i64>,
+}
+
+/// The WHERE-clause inputs shared by every item listing; see `ITEM_FILTER_SQL`.
+struct ItemFilter<'a> {
+    filter: &'a str,
+    subtype: Option<&'a str>,
+    tag_id: Option<i64>,
+    collection_id: Option<i64>,
+}
+
+impl<'a> ItemFilter<'a> {
+    fn from_params(params: &'a SearchParams) -> Self {
+        let filter = match params.filter.as_deref().unwrap_or("all") {
+            f @ ("favorites" | "pinned") => f,
+            _ => "all",
+        };
+        Self {
+            filter,
+            subtype: params.subtype.as_deref().filter(|s| !s.is_empty()),
+            tag_id: params.tag_id,
+            collection_id: params.collection_id,
+        }
+    }
+
+    fn bind<'p>(&'p self, extra: &[(&'p str, &'p dyn ToSql)]) -> Vec<(&'p str, &'p dyn ToSql)> {
+        let mut bound: Vec<(&str, &dyn ToSql)> = vec![
+            (":filter", &self.filter),
+            (":subtype", &self.subtype),
+            (":tag_id", &self.tag_id),
+            (":collection_id", &self.collection_id),
+        ];
+        bound.extend_from_slice(extra);
+        bound
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -37,6 +128,28 @@ pub struct ClipboardPayload {
     pub image_rgba: Option<Vec<u8>>,
     pub image_width: Option<i64>,
     pub image_height: Option<i64>,
+    /// As captured; a shrunk image's pixels no longer hash to it.
+    pub fingerprint: String,
+}
+
+/// A stored image decoded to RGBA, for `images::shrink_stored_images`.
+/// `rgba` is the decoding error when the stored bytes are unreadable, so one
+/// bad item doesn't fail the whole batch.
+#[derive(Debug)]
+pub struct ItemImage {
+    pub id: i64,
+    pub rgba: Result<Vec<u8>>,
+    pub width: i64,
+    pub height: i64,
+}
+
+/// An image found by `similar_images`, with how far its hash is from the query's.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimilarImage {
+    #[serde(flatten)]
+    pub item: SearchItem,
+    pub distance: u32,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -49,30 +162,189 @@ pub struct ItemPreview {
     pub image_height: Option<i64>,
 }
 
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemRevision {
+    pub id: i64,
+    pub item_id: i64,
+    pub text: String,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tag {
+    pub id: i64,
+    pub name: String,
+    pub item_count: u32,
+}
+
+/// A named, manually ordered board of items.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Collection {
+    pub id: i64,
+    pub name: String,
+    pub item_count: u32,
+}
+
+/// A reusable template; see `crate::snippets` for the placeholder syntax.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snippet {
+    pub id: i64,
+    pub name: String,
+    pub template: String,
+    pub inputs: Vec<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// A full item row for export, image pixels included.
+#[derive(Debug, Clone)]
+pub struct ExportItem {
+    pub id: i64,
+    pub created_at: i64,
+    pub kind: String,
+    pub subtype: Option<String>,
+    pub language: Option<String>,
+    pub text: Option<String>,
+    pub image_rgba: Option<Vec<u8>>,
+    pub image_width: Option<i64>,
+    pub image_height: Option<i64>,
+    pub favorite: bool,
+    pub pinned: bool,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct NewItem<'a> {
+    /// When the item was captured; `insert_item` uses the current time when unset.
+    pub created_at: Option<i64>,
+    pub kind: &'a str,
+    pub subtype: Option<&'a str>,
+    pub language: Option<&'a str>,
+    /// With `text_zstd`, a truncated copy used for search and lists.
+    pub text: Option<&'a str>,
+    /// The full text, from `compress_text`.
+    pub text_zstd: Option<&'a [u8]>,
+    /// With `text_zstd`, the full text when search covers all of it rather
+    /// than just `text`.
+    pub search_text: Option<&'a str>,
+    pub fingerprint: &'a str,
+    pub image_rgba: Option<&'a [u8]>,
+    /// An encoded image (PNG or WebP) stored instead of `image_rgba`.
+    pub image_encoded: Option<&'a [u8]>,
+    /// The `image_quality` a WebP `image_encoded` was written at.
+    pub image_quality: Option<i64>,
+    /// From `images::perceptual_hash`, of the image as captured.
+    pub image_phash: Option<i64>,
+    pub image_width: Option<i64>,
+    pub image_height: Option<i64>,
+}
+
+/// An item from another clipboard history, kept with its original time and flags.
+#[derive(Debug, Default)]
+pub struct ImportItem<'a> {
+    pub item: NewItem<'a>,
+    pub created_at: i64,
+    pub favorite: bool,
+    pub pinned: bool,
+    pub tags: &'a [String],
+}
+
+/// `PRAGMA user_version` after `run_migrations`. Bump it with every migration.
+pub const SCHEMA_VERSION: i64 = 8;
+
 pub struct Storage {
     conn: Connection,
+    events: Option<Box<dyn Fn(StorageEvent) + Send>>,
 }
 
 impl Storage {
     pub fn open(path: &Path) -> Result<Self> {
         if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent).context("failed to create db parent directory")?;
+            std::fs::create_dir_all(parent).map_err(|err| {
+                ClipItError::storage(format!("failed to create db parent directory: {err}"))
+            })?;
         }
 
         let conn = Connection::open(path).context("failed to open sqlite db")?;
-        let mut storage = Self { conn };
+        register_sql_functions(&conn)?;
+        let mut storage = Self { conn, events: None };
         storage.run_migrations()?;
         Ok(storage)
     }
 
+    /// Receives an event after every successful item mutation.
+    pub fn set_event_sink(&mut self, sink: impl Fn(StorageEvent) + Send + 'static) {
+        self.events = Some(Box::new(sink));
+    }
+
+    fn notify(&self, event: StorageEvent) {
+        if let Some(sink) = &self.events {
+            sink(event);
+        }
+    }
+
+    fn notify_updated(&self, item_id: i64, change: ItemChange) -> Result<()> {
+        if let Some(item) = self.get_search_item(item_id)? {
+            self.notify(StorageEvent::ItemUpdated(ItemUpdatedEvent {
+                schema_version: ITEM_EVENT_SCHEMA_VERSION,
+                change,
+                item,
+            }));
+        }
+        Ok(())
+    }
+
+    /// Runs a soft-delete `UPDATE ... RETURNING id` and collects the ids.
+    fn soft_delete(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<i64>> {
+        let mut stmt = self.conn.prepare(sql)?;
+        let ids = stmt
+            .query_map(params, |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<i64>>>()?;
+        Ok(ids)
+    }
+
     pub fn run_migrations(&mut self) -> Result<()> {
+        let version: i64 = self
+            .conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))?;
         self.conn
             .execute_batch(include_str!("migrations/001_init.sql"))
             .context("failed to run migrations")?;
         self.ensure_item_columns()?;
+        self.conn
+            .execute_batch(include_str!("migrations/002_item_revisions.sql"))
+            .context("failed to run item revisions migration")?;
+        self.conn
+            .execute_batch(include_str!("migrations/003_tags_collections.sql"))
+            .context("failed to run tags migration")?;
+        self.conn
+            .execute_batch(include_str!("migrations/004_snippets.sql"))
+            .context("failed to run snippets migration")?;
+        if version < 8 {
+            self.conn
+                .execute_batch(include_str!("migrations/005_compressed_text_search.sql"))
+                .context("failed to run compressed text search migration")?;
+            self.migrate_search_full()?;
+        }
+        self.conn
+            .pragma_update(None, "user_version", SCHEMA_VERSION)
+            .context("failed to record schema version")?;
         Ok(())
     }
 
+    /// Replaces the whole database with the one at `path`, through this
+    /// connection so nothing else has to reopen the file, then migrates it.
+    pub fn restore_from(&mut self, path: &Path) -> Result<()> {
+        let source = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .context("failed to open backup")?;
+        copy_database(&source, &mut self.conn).context("failed to restore backup")?;
+        self.run_migrations()
+    }
+
     fn ensure_item_columns(&self) -> Result<()> {
         let mut cols = HashSet::new();
         let mut stmt = self.conn.prepare("PRAGMA table_info(items)")?;
@@ -97,11 +369,69 @@ impl Storage {
             self.conn
                 .execute("ALTER TABLE items ADD COLUMN favorite INTEGER NOT NULL DEFAULT 0", [])?;
         }
+        if !cols.contains("text_zstd") {
+            self.conn
+                .execute("ALTER TABLE items ADD COLUMN text_zstd BLOB", [])?;
+        }
+        if !cols.contains("image_encoded") {
+            self.conn
+                .execute("ALTER TABLE items ADD COLUMN image_encoded BLOB", [])?;
+        }
+        if !cols.contains("image_quality") {
+            self.conn
+                .execute("ALTER TABLE items ADD COLUMN image_quality INTEGER", [])?;
+        }
+        if !cols.contains("image_phash") {
+            self.conn
+                .execute("ALTER TABLE items ADD COLUMN image_phash INTEGER", [])?;
+        }
+        if !cols.contains("imported") {
+            self.conn.execute(
+                "ALTER TABLE items ADD COLUMN imported INTEGER NOT NULL DEFAULT 0",
+                [],
+            )?;
+        }
+        if !cols.contains("search_text") {
+            self.conn
+                .execute("ALTER TABLE items ADD COLUMN search_text TEXT", [])?;
+        }
+        if !cols.contains("subtype") {
+            self.conn
+                .execute("ALTER TABLE items ADD COLUMN subtype TEXT", [])?;
+            self.conn
+                .execute("ALTER TABLE items ADD COLUMN language TEXT", [])?;
+            self.backfill_subtypes()?;
+        }
         self.conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_items_favorite_deleted ON items(favorite, deleted)",
             [],
         )?;
+        self.conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_items_subtype_deleted ON items(subtype, deleted)",
+            [],
+        )?;
+
+        Ok(())
+    }
+
+    /// Classifies text items captured before subtypes existed.
+    fn backfill_subtypes(&self) -> Result<()> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, COALESCE(text, '') FROM items WHERE kind = 'text'")?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
 
+        let mut update = self
+            .conn
+            .prepare("UPDATE items SET subtype = ?1, language = ?2 WHERE id = ?3")?;
+        for (id, text) in rows {
+            let class = classify_text(&text);
+            update.execute(params![class.subtype, class.language, id])?;
+        }
         Ok(())
     }
 
@@ -121,7 +451,9 @@ impl Storage {
         for row in rows {
             let (key, value_json) = row?;
             let value: Value = serde_json::from_str(&value_json).unwrap_or(Value::Null);
-            apply_setting_value(&mut settings, &key, value);
+            if let Err(err) = settings.apply(&key, &value) {
+                eprintln!("ignoring stored setting: {err}");
+            }
         }
 
         Ok(settings)
@@ -152,49 +484,144 @@ impl Storage {
         }
     }
 
-    pub fn insert_item(
-        &self,
-        kind: &str,
-        text: Option<&str>,
-        fingerprint: &str,
-        image_rgba: Option<&[u8]>,
-        image_width: Option<i64>,
-        image_height: Option<i64>,
-    ) -> Result<i64> {
-        let now = unix_ms();
+    pub fn insert_item(&self, item: &NewItem<'_>) -> Result<i64> {
+        let created_at = item.created_at.unwrap_or_else(unix_ms);
         self.conn.execute(
-            "INSERT INTO items(created_at, kind, text, fingerprint, image_rgba, image_width, image_height, favorite, pinned, deleted)
-             VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, 0, 0, 0)",
-            params![now, kind, text, fingerprint, image_rgba, image_width, image_height],
+            "INSERT INTO items(created_at, kind, subtype, language, text, text_zstd, search_text, fingerprint, image_rgba, image_encoded, image_quality, image_width, image_height, image_phash, favorite, pinned, deleted)
+             VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, 0, 0, 0)",
+            params![
+                created_at,
+                item.kind,
+                item.subtype,
+                item.language,
+                item.text,
+                item.text_zstd,
+                item.search_text,
+                item.fingerprint,
+                item.image_rgba,
+                item.image_encoded,
+                item.image_quality,
+                item.image_width,
+                item.image_height,
+                item.image_phash
+            ],
         )?;
         Ok(self.conn.last_insert_rowid())
     }
 
+    /// Inserts an imported item, or returns `None` when a live item already has
+    /// its fingerprint; that item then gains the import's favorite, pinned and
+    /// tags instead. Raises no events: imports are announced as a whole.
+    pub fn import_item(&self, import: &ImportItem<'_>) -> Result<Option<i64>> {
+        let item = &import.item;
+        let existing: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT id FROM items WHERE fingerprint = ?1 AND deleted = 0 LIMIT 1",
+                params![item.fingerprint],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        let (item_id, inserted) = match existing {
+            Some(item_id) => {
+                self.conn.execute(
+                    "UPDATE items SET favorite = favorite OR ?2, pinned = pinned OR ?3
+                     WHERE id = ?1",
+                    params![item_id, import.favorite, import.pinned],
+                )?;
+                (item_id, false)
+            }
+            None => {
+                self.conn.execute(
+                    "INSERT INTO items(created_at, kind, subtype, language, text, text_zstd, search_text, fingerprint, image_rgba, image_encoded, image_quality, image_width, image_height, image_phash, favorite, pinned, imported, deleted)
+                     VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, 1, 0)",
+                    params![
+                        import.created_at,
+                        item.kind,
+                        item.subtype,
+                        item.language,
+                        item.text,
+                        item.text_zstd,
+                        item.search_text,
+                        item.fingerprint,
+                        item.image_rgba,
+                        item.image_encoded,
+                        item.image_quality,
+                        item.image_width,
+                        item.image_height,
+                        item.image_phash,
+                        import.favorite,
+                        import.pinned
+                    ],
+                )?;
+                (self.conn.last_insert_rowid(), true)
+            }
+        };
+
+        for name in import.tags {
+            let Ok(name) = validate_name(name) else {
+                continue;
+            };
+            self.conn.execute(
+                "INSERT OR IGNORE INTO tags(name, created_at) VALUES(?1, ?2)",
+                params![name, unix_ms()],
+            )?;
+            self.conn.execute(
+                "INSERT OR IGNORE INTO item_tags(item_id, tag_id)
+                 SELECT ?1, id FROM tags WHERE name = ?2",
+                params![item_id, name],
+            )?;
+        }
+
+        Ok(inserted.then_some(item_id))
+    }
+
+    /// Runs `f` in one transaction, rolled back if it fails. `f` must not start
+    /// a transaction of its own.
+    pub fn batch<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let tx = self.conn.unchecked_transaction()?;
+        let value = f()?;
+        tx.commit()?;
+        Ok(value)
+    }
+
     pub fn enforce_max_items(&self, max_items: i64) -> Result<()> {
         if max_items <= 0 {
             return Ok(());
         }
 
-        self.conn.execute(
-            "UPDATE items
-             SET deleted = 1
-             WHERE id IN (
-               SELECT id
-               FROM items
-               WHERE deleted = 0 AND pinned = 0 AND favorite = 0
-               ORDER BY created_at DESC
-               LIMIT -1 OFFSET ?1
-             )",
+        let ids = self.soft_delete(
+            &format!(
+                "UPDATE items
+                 SET deleted = 1
+                 WHERE id IN (
+                   SELECT id
+                   FROM items
+                   WHERE deleted = 0 AND NOT {RETAINED_ITEM_SQL}
+                   ORDER BY created_at DESC
+                   LIMIT -1 OFFSET ?1
+                 )
+                 RETURNING id"
+            ),
             params![max_items],
         )?;
 
+        if !ids.is_empty() {
+            self.notify(StorageEvent::ItemsPruned(ItemsPrunedEvent {
+                schema_version: ITEM_EVENT_SCHEMA_VERSION,
+                max_items,
+                ids,
+            }));
+        }
         Ok(())
     }
 
     pub fn get_item_clipboard_payload(&self, item_id: i64) -> Result<Option<ClipboardPayload>> {
         self.conn
             .query_row(
-                "SELECT kind, text, image_rgba, image_width, image_height
+                "SELECT kind, text, text_zstd, image_rgba, image_encoded, image_width, image_height,
+                        fingerprint
                  FROM items
                  WHERE id = ?1 AND deleted = 0
                  LIMIT 1",
@@ -202,10 +629,11 @@ impl Storage {
                 |row| {
                     Ok(ClipboardPayload {
                         kind: row.get(0)?,
-                        text: row.get(1)?,
-                        image_rgba: row.get(2)?,
-                        image_width: row.get(3)?,
-                        image_height: row.get(4)?,
+                        text: full_text(row, 1, 2)?,
+                        image_rgba: image_pixels(row, 3, 4)?,
+                        image_width: row.get(5)?,
+                        image_height: row.get(6)?,
+                        fingerprint: row.get(7)?,
                     })
                 },
             )
@@ -213,10 +641,193 @@ impl Storage {
             .map_err(Into::into)
     }
 
+    /// Images captured before `captured_before` (Unix ms) that `encoding` at
+    /// `quality` would store smaller: a side longer than `max_dimension` (when
+    /// above 0), still raw when an encoding is wanted, and with WebP, still
+    /// PNG or WebP above `quality`. Returns up to `limit` with ids above
+    /// `after_id`, in id order.
+    pub fn images_to_shrink(
+        &self,
+        captured_before: i64,
+        max_dimension: i64,
+        encoding: ImageEncoding,
+        quality: i64,
+        after_id: i64,
+        limit: usize,
+    ) -> Result<Vec<ItemImage>> {
+        // WebP files are RIFF containers with "WEBP" at byte 9.
+        let mut stmt = self.conn.prepare(
+            "SELECT id, image_rgba, image_encoded, image_width, image_height
+             FROM items
+             WHERE kind = 'image' AND deleted = 0 AND id > ?1 AND created_at < ?2
+               AND (image_rgba IS NOT NULL OR image_encoded IS NOT NULL)
+               AND ((?3 > 0 AND (image_width > ?3 OR image_height > ?3))
+                    OR (?4 AND image_encoded IS NULL)
+                    OR (?5 AND image_encoded IS NOT NULL
+                        AND (substr(image_encoded, 9, 4) != CAST('WEBP' AS BLOB)
+                             OR image_quality > ?6)))
+             ORDER BY id
+             LIMIT ?7",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                after_id,
+                captured_before,
+                max_dimension,
+                encoding != ImageEncoding::Raw,
+                encoding == ImageEncoding::Webp,
+                quality,
+                limit
+            ],
+            |row| {
+                Ok(ItemImage {
+                    id: row.get(0)?,
+                    rgba: decoded_pixels(row),
+                    width: row.get(3)?,
+                    height: row.get(4)?,
+                })
+            },
+        )?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Swaps an image item's pixels for a downscaled or re-encoded version
+    /// and relabels it with the new size. The fingerprint keeps describing
+    /// the image as captured.
+    pub fn replace_item_image(
+        &self,
+        item_id: i64,
+        rgba: Option<&[u8]>,
+        encoded: Option<&[u8]>,
+        quality: Option<i64>,
+        width: i64,
+        height: i64,
+    ) -> Result<bool> {
+        let changed = self.conn.execute(
+            "UPDATE items SET image_rgba = ?1, image_encoded = ?2, image_quality = ?3,
+                    image_width = ?4, image_height = ?5, text = 'image://' || ?4 || 'x' || ?5
+             WHERE id = ?6 AND kind = 'image' AND deleted = 0",
+            params![rgba, encoded, quality, width, height, item_id],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// Image items without a perceptual hash, decoded to RGBA; up to `limit`
+    /// with ids above `after_id`, in id order.
+    pub fn images_without_hash(&self, after_id: i64, limit: usize) -> Result<Vec<ItemImage>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, image_rgba, image_encoded, image_width, image_height
+             FROM items
+             WHERE kind = 'image' AND deleted = 0 AND id > ?1 AND image_phash IS NULL
+               AND (image_rgba IS NOT NULL OR image_encoded IS NOT NULL)
+             ORDER BY id
+             LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![after_id, limit], |row| {
+            Ok(ItemImage {
+                id: row.get(0)?,
+                rgba: decoded_pixels(row),
+                width: row.get(3)?,
+                height: row.get(4)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn set_image_hash(&self, item_id: i64, hash: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE items SET image_phash = ?1 WHERE id = ?2 AND kind = 'image'",
+            params![hash, item_id],
+        )?;
+        Ok(())
+    }
+
+    /// Deletes the images within `max_distance` of `hash` other than
+    /// `keep_id`, sparing pinned, favorite, tagged and collected ones.
+    pub fn collapse_similar_images(
+        &self,
+        keep_id: i64,
+        hash: i64,
+        max_distance: u32,
+    ) -> Result<Vec<i64>> {
+        let ids = self.soft_delete(
+            &format!(
+                "UPDATE items
+                 SET deleted = 1
+                 WHERE kind = 'image' AND deleted = 0 AND id != ?1
+                   AND hamming_distance(image_phash, ?2) <= ?3
+                   AND NOT {RETAINED_ITEM_SQL}
+                 RETURNING id"
+            ),
+            params![keep_id, hash, max_distance],
+        )?;
+
+        if !ids.is_empty() {
+            self.notify(StorageEvent::ImagesCollapsed(ImagesCollapsedEvent {
+                schema_version: ITEM_EVENT_SCHEMA_VERSION,
+                kept: keep_id,
+                ids: ids.clone(),
+            }));
+        }
+        Ok(ids)
+    }
+
+    /// Images that look like item `item_id`: within `max_distance` of its
+    /// perceptual hash, closest first. Empty when it has no hash yet.
+    pub fn similar_images(
+        &self,
+        item_id: i64,
+        max_distance: u32,
+        limit: u32,
+    ) -> Result<Vec<SimilarImage>> {
+        let hash: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT image_phash FROM items WHERE id = ?1 AND deleted = 0",
+                params![item_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or(ClipItError::item_not_found(item_id))?;
+        let Some(hash) = hash else {
+            return Ok(Vec::new());
+        };
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {SEARCH_COLUMNS}, hamming_distance(i.image_phash, ?2) AS distance
+             FROM items i
+             WHERE i.kind = 'image' AND i.deleted = 0 AND i.id != ?1 AND distance <= ?3
+             ORDER BY distance ASC, i.created_at DESC
+             LIMIT ?4"
+        ))?;
+        let rows = stmt.query_map(
+            params![item_id, hash, max_distance, limit.clamp(1, 200)],
+            |row| {
+                Ok(SimilarImage {
+                    item: search_item_from_row(row)?,
+                    distance: row.get(SEARCH_COLUMN_COUNT)?,
+                })
+            },
+        )?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// The item as a search/list row, without match highlighting.
+    pub fn get_search_item(&self, item_id: i64) -> Result<Option<SearchItem>> {
+        self.conn
+            .query_row(
+                &format!("SELECT {SEARCH_COLUMNS} FROM items i WHERE i.id = ?1 AND i.deleted = 0"),
+                params![item_id],
+                search_item_from_row,
+            )
+            .optional()
+            .map_err(Into::into)
+    }
+
     pub fn get_item_preview(&self, item_id: i64) -> Result<Option<ItemPreview>> {
         self.conn
             .query_row(
-                "SELECT kind, COALESCE(text, ''), image_rgba, image_width, image_height
+                "SELECT kind, text, text_zstd, image_rgba, image_encoded, image_width, image_height
                  FROM items
                  WHERE id = ?1 AND deleted = 0
                  LIMIT 1",
@@ -224,10 +835,10 @@ impl Storage {
                 |row| {
                     Ok(ItemPreview {
                         kind: row.get(0)?,
-                        text: row.get(1)?,
-                        image_rgba: row.get(2)?,
-                        image_width: row.get(3)?,
-                        image_height: row.get(4)?,
+                        text: full_text(row, 1, 2)?.unwrap_or_default(),
+                        image_rgba: image_pixels(row, 3, 4)?,
+                        image_width: row.get(5)?,
+                        image_height: row.get(6)?,
                     })
                 },
             )
@@ -235,103 +846,97 @@ impl Storage {
             .map_err(Into::into)
     }
 
-    pub fn search_items(
-        &self,
-        query: &str,
-        limit: u32,
-        offset: u32,
-        filter: &str,
-    ) -> Result<SearchResponse> {
-        let capped_limit = limit.clamp(1, 200);
-        let q = query.trim();
-        let filter = match filter {
-            "favorites" | "pinned" => filter,
-            _ => "all",
-        };
+    pub fn search_items(&self, params: &SearchParams) -> Result<SearchResponse> {
+        let limit = params.limit.clamp(1, 200);
+        let q = params.query.trim();
+        let item_filter = ItemFilter::from_params(params);
 
         if q.is_empty() {
-            let mut stmt = self.conn.prepare(
-                "SELECT id, created_at, kind, COALESCE(text, ''), image_width, image_height, favorite, pinned
-                 FROM items
-                 WHERE deleted = 0
-                   AND (?3 = 'all' OR (?3 = 'favorites' AND favorite = 1) OR (?3 = 'pinned' AND pinned = 1))
-                 ORDER BY pinned DESC, favorite DESC, created_at DESC
-                 LIMIT ?1 OFFSET ?2",
+            let total: u32 = self.conn.query_row(
+                &format!("SELECT COUNT(*) FROM items i WHERE i.deleted = 0 AND {ITEM_FILTER_SQL}"),
+                item_filter.bind(&[]).as_slice(),
+                |r| r.get(0),
             )?;
 
-            let rows = stmt.query_map(params![capped_limit, offset, filter], |row| {
-                let kind: String = row.get(2)?;
-                let text: String = row.get(3)?;
-                let w: Option<i64> = row.get(4)?;
-                let h: Option<i64> = row.get(5)?;
-                Ok(SearchItem {
-                    id: row.get(0)?,
-                    created_at: row.get(1)?,
-                    kind: kind.clone(),
-                    preview_text: preview_text(&kind, &text),
-                    text,
-                    image_width: w,
-                    image_height: h,
-                    favorite: row.get::<_, i64>(6)? == 1,
-                    pinned: row.get::<_, i64>(7)? == 1,
-                })
-            })?;
+            // Collections have a manual order; everything else lists newest first.
+            let order_by = if item_filter.collection_id.is_some() {
+                "(SELECT c.position FROM collection_items c
+                  WHERE c.item_id = i.id AND c.collection_id = :collection_id) ASC"
+            } else {
+                "i.pinned DESC, i.favorite DESC, i.created_at DESC"
+            };
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT {SEARCH_COLUMNS}
+                 FROM items i
+                 WHERE i.deleted = 0 AND {ITEM_FILTER_SQL}
+                 ORDER BY {order_by}
+                 LIMIT :limit OFFSET :offset"
+            ))?;
+            let rows = stmt.query_map(
+                item_filter
+                    .bind(&[(":limit", &limit), (":offset", &params.offset)])
+                    .as_slice(),
+                search_item_from_row,
+            )?;
 
             let mut items = Vec::new();
             for row in rows {
                 items.push(row?);
             }
 
-            let total: u32 = self.conn.query_row(
-                "SELECT COUNT(*) FROM items
-                 WHERE deleted = 0
-                   AND (?1 = 'all' OR (?1 = 'favorites' AND favorite = 1) OR (?1 = 'pinned' AND pinned = 1))",
-                params![filter],
-                |r| r.get(0),
-            )?;
-
             return Ok(SearchResponse { total, items });
         }
 
+        if params.mode.as_deref() == Some("regex") {
+            return self.search_items_regex(q, limit, params.offset, &item_filter);
+        }
+
         let match_query = format!("\"{}\"*", q.replace('"', " "));
 
         let total: u32 = self.conn.query_row(
-            "SELECT COUNT(*)
-             FROM items_fts f
-             JOIN items i ON i.id = f.rowid
-             WHERE f.text MATCH ?1 AND i.deleted = 0
-               AND (?2 = 'all' OR (?2 = 'favorites' AND i.favorite = 1) OR (?2 = 'pinned' AND i.pinned = 1))",
-            params![match_query, filter],
+            &format!(
+                "SELECT COUNT(*)
+                 FROM items_fts f
+                 JOIN items i ON i.id = f.rowid
+                 WHERE f.text MATCH :query AND i.deleted = 0 AND {ITEM_FILTER_SQL}"
+            ),
+            item_filter.bind(&[(":query", &match_query)]).as_slice(),
             |r| r.get(0),
         )?;
 
-        let mut stmt = self.conn.prepare(
-            "SELECT i.id, i.created_at, i.kind, COALESCE(i.text, ''), i.image_width, i.image_height, i.favorite, i.pinned
+        // bm25() is lower-is-better; snippet() wraps each hit in the SNIPPET_OPEN/CLOSE
+        // sentinels, which are stripped again into `match_ranges`.
+        let order_by = match params.sort.as_deref() {
+            Some("relevance") => "bm25(items_fts) ASC, i.created_at DESC",
+            _ => "i.pinned DESC, i.favorite DESC, i.created_at DESC",
+        };
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {SEARCH_COLUMNS},
+                    snippet(items_fts, 0, char(1), char(2), '...', {SNIPPET_TOKENS})
              FROM items_fts f
              JOIN items i ON i.id = f.rowid
-             WHERE f.text MATCH ?1 AND i.deleted = 0
-               AND (?4 = 'all' OR (?4 = 'favorites' AND i.favorite = 1) OR (?4 = 'pinned' AND i.pinned = 1))
-             ORDER BY i.pinned DESC, i.favorite DESC, i.created_at DESC
-             LIMIT ?2 OFFSET ?3",
-        )?;
+             WHERE f.text MATCH :query AND i.deleted = 0 AND {ITEM_FILTER_SQL}
+             ORDER BY {order_by}
+             LIMIT :limit OFFSET :offset"
+        ))?;
 
-        let rows = stmt.query_map(params![match_query, capped_limit, offset, filter], |row| {
-            let kind: String = row.get(2)?;
-            let text: String = row.get(3)?;
-            let w: Option<i64> = row.get(4)?;
-            let h: Option<i64> = row.get(5)?;
-            Ok(SearchItem {
-                id: row.get(0)?,
-                created_at: row.get(1)?,
-                kind: kind.clone(),
-                preview_text: preview_text(&kind, &text),
-                text,
-                image_width: w,
-                image_height: h,
-                favorite: row.get::<_, i64>(6)? == 1,
-                pinned: row.get::<_, i64>(7)? == 1,
-            })
-        })?;
+        let rows = stmt.query_map(
+            item_filter
+                .bind(&[
+                    (":query", &match_query),
+                    (":limit", &limit),
+                    (":offset", &params.offset),
+                ])
+                .as_slice(),
+            |row| {
+                let mut item = search_item_from_row(row)?;
+                if item.kind != "image" {
+                    let snippet: String = row.get(SEARCH_COLUMN_COUNT)?;
+                    (item.preview_text, item.match_ranges) = parse_snippet(&snippet);
+                }
+                Ok(item)
+            },
+        )?;
 
         let mut items = Vec::new();
         for row in rows {
@@ -341,95 +946,868 @@ impl Storage {
         Ok(SearchResponse { total, items })
     }
 
-    pub fn set_favorite(&self, item_id: i64, favorite: bool) -> Result<()> {
-        self.conn.execute(
-            "UPDATE items SET favorite = ?1 WHERE id = ?2",
-            params![if favorite { 1 } else { 0 }, item_id],
-        )?;
-        Ok(())
-    }
+    /// Calls `f` for every item matching `params` (query, mode and filters as in
+    /// `search_items`, without paging), oldest first. Rows are read one at a time
+    /// so exporting a large history doesn't hold it all in memory. Returns the
+    /// number of items visited.
+    pub fn for_each_export_item(
+        &self,
+        params: &SearchParams,
+        mut f: impl FnMut(ExportItem) -> Result<()>,
+    ) -> Result<u32> {
+        let q = params.query.trim();
+        let item_filter = ItemFilter::from_params(params);
+        let match_query = format!("\"{}\"*", q.replace('"', " "));
 
-    pub fn pin_item(&self, item_id: i64, pinned: bool) -> Result<()> {
-        self.conn.execute(
-            "UPDATE items SET pinned = ?1 WHERE id = ?2",
-            params![if pinned { 1 } else { 0 }, item_id],
-        )?;
-        Ok(())
-    }
+        let query_sql = if q.is_empty() {
+            "1"
+        } else if params.mode.as_deref() == Some("regex") {
+            build_regex(q)
+                .map_err(|err| ClipItError::invalid_input(format!("invalid regex: {err}")))?;
+            "i.kind != 'image' AND item_full_text(i.text, i.text_zstd) REGEXP :pattern"
+        } else {
+            "i.id IN (SELECT rowid FROM items_fts WHERE text MATCH :query)"
+        };
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT i.id, i.created_at, i.kind, i.subtype, i.language, i.text,
+                    i.image_rgba, i.image_width, i.image_height, i.favorite, i.pinned,
+                    (SELECT group_concat(t.name, char(31)) FROM item_tags it
+                     JOIN tags t ON t.id = it.tag_id WHERE it.item_id = i.id),
+                    i.text_zstd, i.image_encoded
+             FROM items i
+             WHERE i.deleted = 0 AND {query_sql} AND {ITEM_FILTER_SQL}
+             ORDER BY i.created_at ASC, i.id ASC"
+        ))?;
 
-    pub fn delete_item(&self, item_id: i64) -> Result<()> {
-        self.conn
-            .execute("UPDATE items SET deleted = 1 WHERE id = ?1", params![item_id])?;
-        Ok(())
-    }
+        // Only bind the parameters the statement actually uses.
+        let mut extra: Vec<(&str, &dyn ToSql)> = Vec::new();
+        if stmt.parameter_index(":pattern")?.is_some() {
+            extra.push((":pattern", &q));
+        }
+        if stmt.parameter_index(":query")?.is_some() {
+            extra.push((":query", &match_query));
+        }
+        let mut rows = stmt.query(item_filter.bind(&extra).as_slice())?;
 
-    pub fn clear_history(&self) -> Result<()> {
-        self.conn
-            .execute("UPDATE items SET deleted = 1 WHERE pinned = 0 AND favorite = 0", [])?;
-        Ok(())
+        let mut count = 0;
+        while let Some(row) = rows.next()? {
+            f(ExportItem {
+                id: row.get(0)?,
+                created_at: row.get(1)?,
+                kind: row.get(2)?,
+                subtype: row.get(3)?,
+                language: row.get(4)?,
+                text: full_text(row, 5, 12)?,
+                image_rgba: image_pixels(row, 6, 13)?,
+                image_width: row.get(7)?,
+                image_height: row.get(8)?,
+                favorite: row.get::<_, i64>(9)? == 1,
+                pinned: row.get::<_, i64>(10)? == 1,
+                tags: split_tags(row.get(11)?),
+            })?;
+            count += 1;
+        }
+        Ok(count)
     }
 
-    pub fn clear_all_history(&self) -> Result<()> {
-        self.conn
-            .execute("UPDATE items SET deleted = 1 WHERE deleted = 0", [])?;
-        Ok(())
-    }
-}
+    fn search_items_regex(
+        &self,
+        pattern: &str,
+        limit: u32,
+        offset: u32,
+        item_filter: &ItemFilter<'_>,
+    ) -> Result<SearchResponse> {
+        let re = build_regex(pattern)
+            .map_err(|err| ClipItError::invalid_input(format!("invalid regex: {err}")))?;
 
-pub fn apply_setting_value(settings: &mut Settings, key: &str, value: Value) {
-    match key {
-        "hotkey" => {
-            if let Some(v) = value.as_str() {
-                settings.hotkey = v.to_string();
-            }
-        }
-        "blur_close" => {
-            if let Some(v) = value.as_bool() {
-                settings.blur_close = v;
+        self.with_deadline(REGEX_SEARCH_TIMEOUT, || {
+            let total: u32 = self.conn.query_row(
+                &format!(
+                    "SELECT COUNT(*) FROM items i
+                     WHERE i.deleted = 0 AND i.kind != 'image'
+                       AND item_full_text(i.text, i.text_zstd) REGEXP :pattern
+                       AND {ITEM_FILTER_SQL}"
+                ),
+                item_filter.bind(&[(":pattern", &pattern)]).as_slice(),
+                |r| r.get(0),
+            )?;
+
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT {SEARCH_COLUMNS}, item_full_text(i.text, i.text_zstd) AS full_text
+                 FROM items i
+                 WHERE i.deleted = 0 AND i.kind != 'image' AND full_text REGEXP :pattern
+                   AND {ITEM_FILTER_SQL}
+                 ORDER BY i.pinned DESC, i.favorite DESC, i.created_at DESC
+                 LIMIT :limit OFFSET :offset"
+            ))?;
+
+            let rows = stmt.query_map(
+                item_filter
+                    .bind(&[
+                        (":pattern", &pattern),
+                        (":limit", &limit),
+                        (":offset", &offset),
+                    ])
+                    .as_slice(),
+                |row| {
+                    let mut item = search_item_from_row(row)?;
+                    // Compressed items keep only a head in `text`; preview the whole text.
+                    let full_text: String = row.get(SEARCH_COLUMN_COUNT)?;
+                    (item.preview_text, item.match_ranges) = regex_preview(&full_text, &re);
+                    Ok(item)
+                },
+            )?;
+
+            let mut items = Vec::new();
+            for row in rows {
+                items.push(row?);
             }
+
+            Ok(SearchResponse { total, items })
+        })
+    }
+
+    /// Runs `f` with a progress handler that interrupts the statement once `timeout`
+    /// has elapsed, so a slow scan can't hold the storage mutex indefinitely.
+    fn with_deadline<T>(
+        &self,
+        timeout: Duration,
+        f: impl FnOnce() -> rusqlite::Result<T>,
+    ) -> Result<T> {
+        let deadline = Instant::now() + timeout;
+        self.conn
+            .progress_handler(1_000, Some(move || Instant::now() >= deadline));
+        let out = f();
+        self.conn.progress_handler(0, None::<fn() -> bool>);
+
+        out.map_err(|err| match err.sqlite_error_code() {
+            Some(ErrorCode::OperationInterrupted) => ClipItError::SearchTimeout {
+                timeout_ms: timeout.as_millis() as u64,
+            },
+            _ => err.into(),
+        })
+    }
+
+    pub fn set_favorite(&self, item_id: i64, favorite: bool) -> Result<()> {
+        let changed = self.conn.execute(
+            "UPDATE items SET favorite = ?1 WHERE id = ?2 AND favorite != ?1",
+            params![if favorite { 1 } else { 0 }, item_id],
+        )?;
+        if changed > 0 {
+            self.notify_updated(item_id, ItemChange::Favorite)?;
         }
-        "polling_interval_ms" => {
-            if let Some(v) = value.as_u64() {
-                settings.polling_interval_ms = v.clamp(100, 5000);
-            }
+        Ok(())
+    }
+
+    pub fn pin_item(&self, item_id: i64, pinned: bool) -> Result<()> {
+        let changed = self.conn.execute(
+            "UPDATE items SET pinned = ?1 WHERE id = ?2 AND pinned != ?1",
+            params![if pinned { 1 } else { 0 }, item_id],
+        )?;
+        if changed > 0 {
+            self.notify_updated(item_id, ItemChange::Pinned)?;
         }
-        "capture_enabled" => {
-            if let Some(v) = value.as_bool() {
-                settings.capture_enabled = v;
-            }
+        Ok(())
+    }
+
+    /// Id of the `n`th (1-based) most recent item, counting only pinned items
+    /// when `pinned_only` is set.
+    pub fn nth_recent_item_id(&self, n: u32, pinned_only: bool) -> Result<Option<i64>> {
+        if n == 0 {
+            return Ok(None);
         }
-        "max_items" => {
-            if let Some(v) = value.as_i64() {
-                settings.max_items = v.clamp(10, 5000);
-            }
+        self.conn
+            .query_row(
+                "SELECT id FROM items
+                 WHERE deleted = 0 AND (?1 = 0 OR pinned = 1)
+                 ORDER BY created_at DESC, id DESC
+                 LIMIT 1 OFFSET ?2",
+                params![pinned_only, n - 1],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(Into::into)
+    }
+
+    pub fn delete_item(&self, item_id: i64) -> Result<()> {
+        let changed = self.conn.execute(
+            "UPDATE items SET deleted = 1 WHERE id = ?1 AND deleted = 0",
+            params![item_id],
+        )?;
+        if changed > 0 {
+            self.notify(StorageEvent::ItemDeleted(ItemDeletedEvent {
+                schema_version: ITEM_EVENT_SCHEMA_VERSION,
+                id: item_id,
+            }));
         }
-        "window_opacity" => {
-            if let Some(v) = value.as_i64() {
-                settings.window_opacity = v.clamp(35, 100);
-            }
+        Ok(())
+    }
+
+    /// Replaces an item's text, keeping the previous text as a revision. Returns
+    /// `false` if the item doesn't exist or is an image. Long texts are
+    /// compressed per `settings`, as captures are.
+    pub fn update_item_text(
+        &self,
+        item_id: i64,
+        text: &str,
+        fingerprint: &str,
+        subtype: Option<&str>,
+        language: Option<&str>,
+        settings: &Settings,
+    ) -> Result<bool> {
+        let tx = self.conn.unchecked_transaction()?;
+        let previous: Option<(String, String)> = tx
+            .query_row(
+                "SELECT text, text_zstd, fingerprint FROM items
+                 WHERE id = ?1 AND deleted = 0 AND kind != 'image'",
+                params![item_id],
+                |row| Ok((full_text(row, 0, 1)?.unwrap_or_default(), row.get(2)?)),
+            )
+            .optional()?;
+        let Some((old_text, old_fingerprint)) = previous else {
+            return Ok(false);
+        };
+        if old_text == text {
+            return Ok(true);
+        }
+
+        tx.execute(
+            "INSERT INTO item_revisions(item_id, text, fingerprint, created_at)
+             VALUES(?1, ?2, ?3, ?4)",
+            params![item_id, old_text, old_fingerprint, unix_ms()],
+        )?;
+        let stored = StoredText::new(text.to_string(), settings)?;
+        tx.execute(
+            "UPDATE items SET text = ?1, text_zstd = ?2, search_text = ?3, fingerprint = ?4,
+                    subtype = ?5, language = ?6
+             WHERE id = ?7",
+            params![
+                stored.text,
+                stored.zstd,
+                stored.search_text,
+                fingerprint,
+                subtype,
+                language,
+                item_id
+            ],
+        )?;
+        tx.commit()?;
+        self.notify_updated(item_id, ItemChange::Text)?;
+        Ok(true)
+    }
+
+    pub fn list_item_revisions(&self, item_id: i64) -> Result<Vec<ItemRevision>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, item_id, text, created_at FROM item_revisions
+             WHERE item_id = ?1
+             ORDER BY created_at DESC, id DESC",
+        )?;
+        let rows = stmt.query_map(params![item_id], |row| {
+            Ok(ItemRevision {
+                id: row.get(0)?,
+                item_id: row.get(1)?,
+                text: row.get(2)?,
+                created_at: row.get(3)?,
+            })
+        })?;
+
+        let mut revisions = Vec::new();
+        for row in rows {
+            revisions.push(row?);
         }
-        "colored_icons" => {
-            if let Some(v) = value.as_bool() {
-                settings.colored_icons = v;
+        Ok(revisions)
+    }
+
+    pub fn get_item_revision(&self, revision_id: i64) -> Result<Option<ItemRevision>> {
+        self.conn
+            .query_row(
+                "SELECT id, item_id, text, created_at FROM item_revisions WHERE id = ?1",
+                params![revision_id],
+                |row| {
+                    Ok(ItemRevision {
+                        id: row.get(0)?,
+                        item_id: row.get(1)?,
+                        text: row.get(2)?,
+                        created_at: row.get(3)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(Into::into)
+    }
+
+    pub fn list_tags(&self) -> Result<Vec<Tag>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT t.id, t.name,
+                    (SELECT COUNT(*) FROM item_tags it JOIN items i ON i.id = it.item_id
+                     WHERE it.tag_id = t.id AND i.deleted = 0)
+             FROM tags t
+             ORDER BY t.name COLLATE NOCASE",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Tag {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                item_count: row.get(2)?,
+            })
+        })?;
+
+        let mut tags = Vec::new();
+        for row in rows {
+            tags.push(row?);
+        }
+        Ok(tags)
+    }
+
+    pub fn create_tag(&self, name: &str) -> Result<Tag> {
+        let name = validate_name(name)?;
+        self.conn
+            .execute(
+                "INSERT INTO tags(name, created_at) VALUES(?1, ?2)",
+                params![name, unix_ms()],
+            )
+            .map_err(already_exists("tag", name))?;
+        Ok(Tag {
+            id: self.conn.last_insert_rowid(),
+            name: name.to_string(),
+            item_count: 0,
+        })
+    }
+
+    pub fn rename_tag(&self, tag_id: i64, name: &str) -> Result<()> {
+        let name = validate_name(name)?;
+        self.conn
+            .execute(
+                "UPDATE tags SET name = ?1 WHERE id = ?2",
+                params![name, tag_id],
+            )
+            .map_err(already_exists("tag", name))?;
+        Ok(())
+    }
+
+    pub fn delete_tag(&self, tag_id: i64) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM tags WHERE id = ?1", params![tag_id])?;
+        Ok(())
+    }
+
+    pub fn set_item_tag(&self, item_id: i64, tag_id: i64, tagged: bool) -> Result<()> {
+        let changed = if tagged {
+            self.conn.execute(
+                "INSERT OR IGNORE INTO item_tags(item_id, tag_id) VALUES(?1, ?2)",
+                params![item_id, tag_id],
+            )?
+        } else {
+            self.conn.execute(
+                "DELETE FROM item_tags WHERE item_id = ?1 AND tag_id = ?2",
+                params![item_id, tag_id],
+            )?
+        };
+        if changed > 0 {
+            self.notify_updated(item_id, ItemChange::Tags)?;
+        }
+        Ok(())
+    }
+
+    pub fn list_collections(&self) -> Result<Vec<Collection>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT c.id, c.name,
+                    (SELECT COUNT(*) FROM collection_items ci JOIN items i ON i.id = ci.item_id
+                     WHERE ci.collection_id = c.id AND i.deleted = 0)
+             FROM collections c
+             ORDER BY c.name COLLATE NOCASE",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Collection {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                item_count: row.get(2)?,
+            })
+        })?;
+
+        let mut collections = Vec::new();
+        for row in rows {
+            collections.push(row?);
+        }
+        Ok(collections)
+    }
+
+    pub fn create_collection(&self, name: &str) -> Result<Collection> {
+        let name = validate_name(name)?;
+        self.conn
+            .execute(
+                "INSERT INTO collections(name, created_at) VALUES(?1, ?2)",
+                params![name, unix_ms()],
+            )
+            .map_err(already_exists("collection", name))?;
+        Ok(Collection {
+            id: self.conn.last_insert_rowid(),
+            name: name.to_string(),
+            item_count: 0,
+        })
+    }
+
+    pub fn rename_collection(&self, collection_id: i64, name: &str) -> Result<()> {
+        let name = validate_name(name)?;
+        self.conn
+            .execute(
+                "UPDATE collections SET name = ?1 WHERE id = ?2",
+                params![name, collection_id],
+            )
+            .map_err(already_exists("collection", name))?;
+        Ok(())
+    }
+
+    pub fn delete_collection(&self, collection_id: i64) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM collections WHERE id = ?1", params![collection_id])?;
+        Ok(())
+    }
+
+    /// Appends an item to the end of a collection; a no-op if it's already there.
+    pub fn add_to_collection(&self, collection_id: i64, item_id: i64) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO collection_items(collection_id, item_id, position)
+             VALUES(?1, ?2, (SELECT COALESCE(MAX(position), -1) + 1
+                             FROM collection_items WHERE collection_id = ?1))",
+            params![collection_id, item_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_from_collection(&self, collection_id: i64, item_id: i64) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM collection_items WHERE collection_id = ?1 AND item_id = ?2",
+            params![collection_id, item_id],
+        )?;
+        Ok(())
+    }
+
+    /// Rewrites positions so `item_ids` come first, in that order. Members not
+    /// listed keep their relative order after them.
+    pub fn reorder_collection(&self, collection_id: i64, item_ids: &[i64]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let members: Vec<i64> = {
+            let mut stmt = tx.prepare(
+                "SELECT item_id FROM collection_items
+                 WHERE collection_id = ?1
+                 ORDER BY position ASC",
+            )?;
+            let rows = stmt.query_map(params![collection_id], |row| row.get(0))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+
+        let ordered = item_ids
+            .iter()
+            .filter(|id| members.contains(id))
+            .chain(members.iter().filter(|id| !item_ids.contains(id)));
+        {
+            let mut update = tx.prepare(
+                "UPDATE collection_items SET position = ?1
+                 WHERE collection_id = ?2 AND item_id = ?3",
+            )?;
+            for (position, item_id) in ordered.enumerate() {
+                update.execute(params![position as i64, collection_id, item_id])?;
             }
         }
-        _ => {}
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Lists snippets whose name contains `query` (case-insensitive), by name.
+    pub fn list_snippets(&self, query: &str) -> Result<Vec<Snippet>> {
+        let pattern = format!(
+            "%{}%",
+            query
+                .trim()
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        let mut stmt = self.conn.prepare(
+            "SELECT id, name, template, created_at, updated_at FROM snippets
+             WHERE name LIKE ?1 ESCAPE '\\'
+             ORDER BY name COLLATE NOCASE",
+        )?;
+        let rows = stmt.query_map(params![pattern], snippet_from_row)?;
+
+        let mut snippets = Vec::new();
+        for row in rows {
+            snippets.push(row?);
+        }
+        Ok(snippets)
+    }
+
+    pub fn get_snippet(&self, snippet_id: i64) -> Result<Option<Snippet>> {
+        self.conn
+            .query_row(
+                "SELECT id, name, template, created_at, updated_at FROM snippets WHERE id = ?1",
+                params![snippet_id],
+                snippet_from_row,
+            )
+            .optional()
+            .map_err(Into::into)
+    }
+
+    /// Creates a snippet, or updates it when `snippet_id` is given.
+    pub fn save_snippet(
+        &self,
+        snippet_id: Option<i64>,
+        name: &str,
+        template: &str,
+    ) -> Result<Snippet> {
+        let name = validate_name(name)?;
+        let now = unix_ms();
+        let id = match snippet_id {
+            Some(id) => {
+                let changed = self
+                    .conn
+                    .execute(
+                        "UPDATE snippets SET name = ?1, template = ?2, updated_at = ?3 WHERE id = ?4",
+                        params![name, template, now, id],
+                    )
+                    .map_err(already_exists("snippet", name))?;
+                if changed == 0 {
+                    return Err(snippet_not_found(id));
+                }
+                id
+            }
+            None => {
+                self.conn
+                    .execute(
+                        "INSERT INTO snippets(name, template, created_at, updated_at)
+                         VALUES(?1, ?2, ?3, ?3)",
+                        params![name, template, now],
+                    )
+                    .map_err(already_exists("snippet", name))?;
+                self.conn.last_insert_rowid()
+            }
+        };
+
+        self.get_snippet(id)?.ok_or_else(|| snippet_not_found(id))
+    }
+
+    pub fn delete_snippet(&self, snippet_id: i64) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM snippets WHERE id = ?1", params![snippet_id])?;
+        Ok(())
+    }
+
+    /// Removes everything but pinned and favorite items.
+    pub fn clear_history(&self) -> Result<()> {
+        let ids = self.soft_delete(
+            "UPDATE items SET deleted = 1
+             WHERE deleted = 0 AND pinned = 0 AND favorite = 0
+             RETURNING id",
+            [],
+        )?;
+        self.notify_cleared(false, ids);
+        Ok(())
+    }
+
+    pub fn clear_all_history(&self) -> Result<()> {
+        let ids = self.soft_delete(
+            "UPDATE items SET deleted = 1 WHERE deleted = 0 RETURNING id",
+            [],
+        )?;
+        self.notify_cleared(true, ids);
+        Ok(())
+    }
+
+    fn notify_cleared(&self, all: bool, ids: Vec<i64>) {
+        self.notify(StorageEvent::HistoryCleared(HistoryClearedEvent {
+            schema_version: ITEM_EVENT_SCHEMA_VERSION,
+            all,
+            ids,
+        }));
     }
 }
 
+const SEARCH_COLUMNS: &str =
+    "i.id, i.created_at, i.kind, i.subtype, i.language, COALESCE(i.text, ''),
+     i.image_width, i.image_height, i.favorite, i.pinned,
+     (SELECT group_concat(t.name, char(31)) FROM item_tags it
+      JOIN tags t ON t.id = it.tag_id WHERE it.item_id = i.id)";
+const SEARCH_COLUMN_COUNT: usize = 11;
+/// Shared WHERE fragment for `ItemFilter`; expects `items` aliased as `i`.
+const ITEM_FILTER_SQL: &str = "(:filter = 'all' OR (:filter = 'favorites' AND i.favorite = 1) OR (:filter = 'pinned' AND i.pinned = 1))
+     AND (:subtype IS NULL OR i.subtype = :subtype)
+     AND (:tag_id IS NULL OR EXISTS (
+       SELECT 1 FROM item_tags t WHERE t.item_id = i.id AND t.tag_id = :tag_id))
+     AND (:collection_id IS NULL OR EXISTS (
+       SELECT 1 FROM collection_items c WHERE c.item_id = i.id AND c.collection_id = :collection_id))";
+/// Items matching this are never removed by retention.
+/// Imported history keeps its original, older timestamps, so retention would
+/// otherwise drop it first.
+const RETAINED_ITEM_SQL: &str = "(pinned = 1 OR favorite = 1 OR imported = 1
+     OR EXISTS (SELECT 1 FROM item_tags t WHERE t.item_id = items.id)
+     OR EXISTS (SELECT 1 FROM collection_items c WHERE c.item_id = items.id))";
+const PREVIEW_CHARS: usize = 140;
+const REGEX_PREVIEW_LEAD: usize = 40;
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+const REGEX_SEARCH_TIMEOUT: Duration = Duration::from_millis(750);
+const SNIPPET_TOKENS: u32 = 24;
+const SNIPPET_OPEN: char = '\u{1}';
+const SNIPPET_CLOSE: char = '\u{2}';
+
 fn preview_text(kind: &str, text: &str) -> String {
     if kind == "image" {
         return "Image".to_string();
     }
 
-    let compact = text.replace('\n', " ").replace('\r', " ");
-    let mut out = compact.chars().take(140).collect::<String>();
-    if compact.chars().count() > 140 {
+    let compact = text.replace(['\n', '\r'], " ");
+    let mut out = compact.chars().take(PREVIEW_CHARS).collect::<String>();
+    if compact.chars().count() > PREVIEW_CHARS {
         out.push_str("...");
     }
     out
 }
 
+fn search_item_from_row(row: &Row<'_>) -> rusqlite::Result<SearchItem> {
+    let kind: String = row.get(2)?;
+    let text: String = row.get(5)?;
+    Ok(SearchItem {
+        id: row.get(0)?,
+        created_at: row.get(1)?,
+        preview_text: preview_text(&kind, &text),
+        kind,
+        subtype: row.get(3)?,
+        language: row.get(4)?,
+        text,
+        match_ranges: Vec::new(),
+        image_width: row.get(6)?,
+        image_height: row.get(7)?,
+        favorite: row.get::<_, i64>(8)? == 1,
+        pinned: row.get::<_, i64>(9)? == 1,
+        tags: split_tags(row.get(10)?),
+    })
+}
+
+/// Splits the `group_concat(name, char(31))` tag column.
+fn split_tags(names: Option<String>) -> Vec<String> {
+    names
+        .map(|names| names.split('\u{1f}').map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+fn snippet_from_row(row: &Row<'_>) -> rusqlite::Result<Snippet> {
+    let template: String = row.get(2)?;
+    Ok(Snippet {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        inputs: template_inputs(&template),
+        template,
+        created_at: row.get(3)?,
+        updated_at: row.get(4)?,
+    })
+}
+
+fn parse_snippet(snippet: &str) -> (String, Vec<MatchRange>) {
+    let mut out = String::with_capacity(snippet.len());
+    let mut ranges = Vec::new();
+    let mut len = 0;
+    let mut open: Option<usize> = None;
+
+    for c in snippet.chars() {
+        match c {
+            SNIPPET_OPEN => open = Some(len),
+            SNIPPET_CLOSE => {
+                if let Some(start) = open.take() {
+                    if len > start {
+                        ranges.push(MatchRange { start, end: len });
+                    }
+                }
+            }
+            '\n' | '\r' => {
+                out.push(' ');
+                len += 1;
+            }
+            _ => {
+                out.push(c);
+                len += c.len_utf16();
+            }
+        }
+    }
+
+    (out, ranges)
+}
+
+/// Copies every page of `from` into `to` with SQLite's online backup API. One
+/// step copies everything, so concurrent writers to `from` can't restart it.
+pub fn copy_database(from: &Connection, to: &mut Connection) -> rusqlite::Result<()> {
+    let backup = Backup::new(from, to)?;
+    loop {
+        if backup.step(-1)? == StepResult::Done {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn validate_name(name: &str) -> Result<&str> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ClipItError::invalid_input("name cannot be empty"));
+    }
+    Ok(name)
+}
+
+/// Maps a unique-constraint failure on `name` to `AlreadyExists`.
+fn already_exists<'a>(
+    entity: &'static str,
+    name: &'a str,
+) -> impl FnOnce(rusqlite::Error) -> ClipItError + 'a {
+    move |err| match err.sqlite_error_code() {
+        Some(ErrorCode::ConstraintViolation) => ClipItError::AlreadyExists {
+            entity,
+            name: name.to_string(),
+        },
+        _ => err.into(),
+    }
+}
+
+fn snippet_not_found(snippet_id: i64) -> ClipItError {
+    ClipItError::NotFound {
+        entity: "snippet",
+        id: Some(snippet_id),
+    }
+}
+
+thread_local! {
+    /// The last pattern `build_regex` compiled on this thread. A regex search
+    /// validates the pattern and then runs two statements whose `REGEXP` calls
+    /// each ask for it again; all of them share this one compilation.
+    static LAST_REGEX: RefCell<Option<(String, Regex)>> = const { RefCell::new(None) };
+}
+
+fn build_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    LAST_REGEX.with_borrow_mut(|last| {
+        if let Some((cached, re)) = last.as_ref() {
+            if cached == pattern {
+                return Ok(re.clone());
+            }
+        }
+        let re = RegexBuilder::new(pattern)
+            .size_limit(REGEX_SIZE_LIMIT)
+            .build()?;
+        *last = Some((pattern.to_string(), re.clone()));
+        Ok(re)
+    })
+}
+
+/// The item's RGBA pixels: `image_encoded` decoded when present, else `image_rgba`.
+fn image_pixels(row: &Row<'_>, rgba: usize, encoded: usize) -> rusqlite::Result<Option<Vec<u8>>> {
+    let Some(encoded_bytes) = row.get::<_, Option<Vec<u8>>>(encoded)? else {
+        return row.get(rgba);
+    };
+    image::load_from_memory(&encoded_bytes)
+        .map(|image| Some(image.to_rgba8().into_raw()))
+        .map_err(|err| {
+            rusqlite::Error::FromSqlConversionFailure(
+                encoded,
+                rusqlite::types::Type::Blob,
+                Box::new(err),
+            )
+        })
+}
+
+/// `image_pixels` for an `ItemImage` read as `id, image_rgba, image_encoded, ..`.
+fn decoded_pixels(row: &Row<'_>) -> Result<Vec<u8>> {
+    Ok(image_pixels(row, 1, 2)?.unwrap_or_default())
+}
+
+/// Registers `REGEXP` so `text REGEXP ?` works in queries, `item_full_text`
+/// (see `compress`), and `hamming_distance(a, b)` for perceptual hashes (NULL
+/// when either is NULL).
+/// The compiled pattern is kept as auxiliary data for the rest of the statement,
+/// and `build_regex` hands every statement of a query the same compilation.
+fn register_sql_functions(conn: &Connection) -> Result<()> {
+    compress::register_full_text_function(conn)?;
+    conn.create_scalar_function(
+        "hamming_distance",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            Ok(match (ctx.get_raw(0), ctx.get_raw(1)) {
+                (ValueRef::Integer(a), ValueRef::Integer(b)) => Some(hash_distance(a, b)),
+                _ => None,
+            })
+        },
+    )
+    .context("failed to register hamming_distance function")?;
+    conn.create_scalar_function(
+        "regexp",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let re = ctx.get_or_create_aux(
+                0,
+                |pattern| -> Result<Regex, Box<dyn std::error::Error + Send + Sync>> {
+                    Ok(build_regex(pattern.as_str()?)?)
+                },
+            )?;
+            let is_match = match ctx.get_raw(1) {
+                ValueRef::Text(bytes) => std::str::from_utf8(bytes)
+                    .map(|text| re.is_match(text))
+                    .unwrap_or(false),
+                _ => false,
+            };
+            Ok(is_match)
+        },
+    )
+    .context("failed to register regexp function")?;
+    Ok(())
+}
+
+/// Builds a preview window around the first regex hit, with every hit inside the
+/// window reported as a char range.
+fn regex_preview(text: &str, re: &Regex) -> (String, Vec<MatchRange>) {
+    let Some(first) = re.find(text) else {
+        return (preview_text("text", text), Vec::new());
+    };
+
+    let start = text[..first.start()]
+        .char_indices()
+        .rev()
+        .take(REGEX_PREVIEW_LEAD)
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(first.start());
+    let end = text[start..]
+        .char_indices()
+        .nth(PREVIEW_CHARS)
+        .map(|(i, _)| start + i)
+        .unwrap_or(text.len());
+
+    let mut out = String::new();
+    if start > 0 {
+        out.push_str("...");
+    }
+    let base = out.encode_utf16().count();
+    out.push_str(&text[start..end].replace(['\n', '\r'], " "));
+    if end < text.len() {
+        out.push_str("...");
+    }
+
+    let mut ranges = Vec::new();
+    for m in re.find_iter(text) {
+        if m.start() < first.start() || m.is_empty() {
+            continue;
+        }
+        if m.start() >= end {
+            break;
+        }
+        let range_start = base + text[start..m.start()].encode_utf16().count();
+        let range_end = base + text[start..m.end().min(end)].encode_utf16().count();
+        ranges.push(MatchRange {
+            start: range_start,
+            end: range_end,
+        });
+    }
+
+    (out, ranges)
+}
+
 fn unix_ms() -> i64 {
     let dur = SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -439,41 +1817,644 @@ fn unix_ms() -> i64 {
 
 #[cfg(test)]
 mod tests {
-    use super::Storage;
+    use std::sync::{Arc, Mutex};
+
+    use super::{
+        build_regex, parse_snippet, regex_preview, MatchRange, NewItem, SearchParams, Storage,
+    };
+    use crate::error::ClipItError;
+    use crate::events::{ItemChange, StorageEvent, ITEM_EVENT_SCHEMA_VERSION};
+    use crate::settings::Settings;
+    use crate::test_support::{insert_text, temp_db_path};
+
+    fn search(storage: &Storage, params: SearchParams) -> super::SearchResponse {
+        storage
+            .search_items(&SearchParams {
+                limit: 50,
+                ..params
+            })
+            .expect("search")
+    }
+
+    #[test]
+    fn reopening_adds_each_missing_column() {
+        let db_path = temp_db_path("columns");
+        {
+            let storage = Storage::open(&db_path).expect("open db");
+            storage
+                .conn
+                .execute_batch("ALTER TABLE items DROP COLUMN image_encoded")
+                .expect("drop column");
+        }
+
+        let storage = Storage::open(&db_path).expect("reopen");
+        let has_column: bool = storage
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('items') WHERE name = 'image_encoded'",
+                [],
+                |row| row.get(0),
+            )
+            .expect("table info");
+        assert!(has_column);
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn insert_item_keeps_the_capture_time() {
+        let db_path = temp_db_path("created-at");
+        let storage = Storage::open(&db_path).expect("open db");
+        let id = storage
+            .insert_item(&NewItem {
+                created_at: Some(1_700_000_000_000),
+                kind: "text",
+                text: Some("captured earlier"),
+                fingerprint: "captured earlier",
+                ..Default::default()
+            })
+            .expect("insert");
+
+        let created_at: i64 = storage
+            .conn
+            .query_row("SELECT created_at FROM items WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })
+            .expect("created_at");
+        assert_eq!(created_at, 1_700_000_000_000);
+
+        let _ = std::fs::remove_file(db_path);
+    }
 
     #[test]
     fn search_filter_favorites_and_pinned() {
-        let db_path = std::env::temp_dir().join(format!(
-            "clipit-test-{}.db",
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_nanos()
-        ));
+        let db_path = temp_db_path("filters");
 
         let storage = Storage::open(&db_path).expect("open db");
-        let a = storage
-            .insert_item("text", Some("alpha"), "fp-a", None, None, None)
-            .expect("insert a");
-        let b = storage
-            .insert_item("text", Some("beta"), "fp-b", None, None, None)
-            .expect("insert b");
+        let a = insert_text(&storage, "alpha");
+        let b = insert_text(&storage, "beta");
 
         storage.set_favorite(a, true).expect("favorite a");
         storage.pin_item(b, true).expect("pin b");
 
-        let fav = storage
-            .search_items("", 50, 0, "favorites")
-            .expect("search favorites");
+        let fav = search(
+            &storage,
+            SearchParams {
+                filter: Some("favorites".to_string()),
+                ..Default::default()
+            },
+        );
         assert_eq!(fav.items.len(), 1);
         assert_eq!(fav.items[0].id, a);
 
-        let pinned = storage
-            .search_items("", 50, 0, "pinned")
-            .expect("search pinned");
+        let pinned = search(
+            &storage,
+            SearchParams {
+                filter: Some("pinned".to_string()),
+                ..Default::default()
+            },
+        );
         assert_eq!(pinned.items.len(), 1);
         assert_eq!(pinned.items[0].id, b);
 
         let _ = std::fs::remove_file(db_path);
     }
+
+    #[test]
+    fn search_relevance_orders_by_bm25_and_highlights_hits() {
+        let db_path = temp_db_path("relevance");
+        let storage = Storage::open(&db_path).expect("open db");
+
+        let dense = insert_text(&storage, "token token token");
+        let long_text = format!("{} token at the end", "filler ".repeat(60));
+        insert_text(&storage, &long_text);
+
+        let ranked = search(
+            &storage,
+            SearchParams {
+                query: "token".to_string(),
+                sort: Some("relevance".to_string()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(ranked.total, 2);
+        assert_eq!(ranked.items[0].id, dense);
+
+        let hit = &ranked.items[1];
+        assert!(hit.preview_text.starts_with("..."));
+        let range = hit.match_ranges[0];
+        let highlighted: String = hit
+            .preview_text
+            .chars()
+            .skip(range.start)
+            .take(range.end - range.start)
+            .collect();
+        assert_eq!(highlighted, "token");
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn marker_characters_in_item_text_are_not_highlighted() {
+        let db_path = temp_db_path("markers");
+        let storage = Storage::open(&db_path).expect("open db");
+        insert_text(&storage, "a\u{1}b\u{2} token \u{2}c");
+
+        let hits = search(
+            &storage,
+            SearchParams {
+                query: "token".to_string(),
+                ..Default::default()
+            },
+        );
+        assert_eq!(hits.total, 1);
+        let hit = &hits.items[0];
+        assert_eq!(hit.preview_text, "a b  token  c");
+        assert_eq!(hit.match_ranges, vec![MatchRange { start: 5, end: 10 }]);
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn parse_snippet_strips_markers_into_ranges() {
+        let (text, ranges) = parse_snippet("a\u{1}bc\u{2}\nd\u{1}é\u{2} 🦀\u{1}x\u{2}");
+        assert_eq!(text, "abc dé 🦀x");
+        // Offsets count UTF-16 code units, as JavaScript strings do.
+        assert_eq!(
+            ranges,
+            vec![
+                MatchRange { start: 1, end: 3 },
+                MatchRange { start: 5, end: 6 },
+                MatchRange { start: 9, end: 10 }
+            ]
+        );
+    }
+
+    #[test]
+    fn search_regex_mode_matches_patterns() {
+        let db_path = temp_db_path("regex");
+        let storage = Storage::open(&db_path).expect("open db");
+
+        let uuid = insert_text(
+            &storage,
+            "request id 3f2504e0-4f89-11d3-9a0c-0305e82c3301 failed",
+        );
+        let github = insert_text(&storage, "https://github.com/asterd/clip-it");
+        insert_text(&storage, "see https://github.com for details");
+
+        let regex = |pattern: &str| SearchParams {
+            query: pattern.to_string(),
+            mode: Some("regex".to_string()),
+            ..Default::default()
+        };
+
+        let uuids = search(
+            &storage,
+            regex(r"[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}"),
+        );
+        assert_eq!(uuids.total, 1);
+        assert_eq!(uuids.items[0].id, uuid);
+        let range = uuids.items[0].match_ranges[0];
+        assert_eq!(range.end - range.start, 36);
+
+        let anchored = search(&storage, regex("^https://github"));
+        assert_eq!(anchored.total, 1);
+        assert_eq!(anchored.items[0].id, github);
+
+        let invalid = storage.search_items(&regex("(unclosed"));
+        assert!(invalid.is_err());
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn regex_preview_centers_on_first_match() {
+        let text = format!("{}needle{}", "a".repeat(100), "b".repeat(300));
+        let re = build_regex("needle").expect("regex");
+        let (preview, ranges) = regex_preview(&text, &re);
+
+        assert!(preview.starts_with("..."));
+        assert!(preview.ends_with("..."));
+        assert_eq!(ranges, vec![MatchRange { start: 43, end: 49 }]);
+        let hit: String = preview.chars().skip(43).take(6).collect();
+        assert_eq!(hit, "needle");
+
+        let (preview, ranges) = regex_preview("🦀 needle", &re);
+        let units: Vec<u16> = preview.encode_utf16().collect();
+        assert_eq!(ranges, vec![MatchRange { start: 3, end: 9 }]);
+        assert_eq!(String::from_utf16_lossy(&units[3..9]), "needle");
+    }
+
+    #[test]
+    fn search_filters_by_subtype() {
+        let db_path = temp_db_path("subtype");
+        let storage = Storage::open(&db_path).expect("open db");
+
+        let url = insert_text(&storage, "https://example.com/docs");
+        let color = insert_text(&storage, "#ff8800");
+        insert_text(&storage, "plain note about example docs");
+
+        let colors = search(
+            &storage,
+            SearchParams {
+                subtype: Some("color".to_string()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(colors.total, 1);
+        assert_eq!(colors.items[0].id, color);
+        assert_eq!(colors.items[0].subtype.as_deref(), Some("color"));
+
+        let urls = search(
+            &storage,
+            SearchParams {
+                query: "example".to_string(),
+                subtype: Some("url".to_string()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(urls.total, 1);
+        assert_eq!(urls.items[0].id, url);
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn edits_keep_revisions_and_reindex() {
+        let db_path = temp_db_path("revisions");
+        let storage = Storage::open(&db_path).expect("open db");
+
+        let id = insert_text(&storage, "teh quick fox");
+        assert!(storage
+            .update_item_text(
+                id,
+                "the quick fox",
+                "fp-v2",
+                Some("prose"),
+                None,
+                &Settings::default()
+            )
+            .expect("edit"));
+
+        let by_old = search(
+            &storage,
+            SearchParams {
+                query: "teh".to_string(),
+                ..Default::default()
+            },
+        );
+        assert_eq!(by_old.total, 0);
+        let by_new = search(
+            &storage,
+            SearchParams {
+                query: "the".to_string(),
+                ..Default::default()
+            },
+        );
+        assert_eq!(by_new.items[0].text, "the quick fox");
+
+        let revisions = storage.list_item_revisions(id).expect("revisions");
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0].text, "teh quick fox");
+
+        let revision = storage
+            .get_item_revision(revisions[0].id)
+            .expect("get revision")
+            .expect("revision exists");
+        storage
+            .update_item_text(
+                id,
+                &revision.text,
+                "fp-v1",
+                Some("prose"),
+                None,
+                &Settings::default(),
+            )
+            .expect("revert");
+        let payload = storage
+            .get_item_clipboard_payload(id)
+            .expect("payload")
+            .expect("item exists");
+        assert_eq!(payload.text.as_deref(), Some("teh quick fox"));
+        assert_eq!(storage.list_item_revisions(id).expect("revisions").len(), 2);
+
+        assert!(!storage
+            .update_item_text(9999, "x", "fp-x", None, None, &Settings::default())
+            .expect("missing item"));
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn tags_filter_search_and_survive_retention() {
+        let db_path = temp_db_path("tags");
+        let storage = Storage::open(&db_path).expect("open db");
+
+        let tag = storage.create_tag("work").expect("create tag");
+        assert!(storage.create_tag(" Work ").is_err());
+        assert!(storage.create_tag("  ").is_err());
+
+        let tagged = insert_text(&storage, "tagged note");
+        for i in 0..5 {
+            insert_text(&storage, &format!("filler {i}"));
+        }
+        storage
+            .set_item_tag(tagged, tag.id, true)
+            .expect("tag item");
+        storage.enforce_max_items(2).expect("retention");
+
+        let by_tag = search(
+            &storage,
+            SearchParams {
+                tag_id: Some(tag.id),
+                ..Default::default()
+            },
+        );
+        assert_eq!(by_tag.total, 1);
+        assert_eq!(by_tag.items[0].id, tagged);
+        assert_eq!(by_tag.items[0].tags, vec!["work".to_string()]);
+        assert_eq!(search(&storage, SearchParams::default()).total, 3);
+
+        storage.rename_tag(tag.id, "office").expect("rename");
+        let tags = storage.list_tags().expect("list tags");
+        assert_eq!(tags[0].name, "office");
+        assert_eq!(tags[0].item_count, 1);
+
+        storage.delete_tag(tag.id).expect("delete tag");
+        storage.clear_history().expect("clear");
+        assert_eq!(search(&storage, SearchParams::default()).total, 0);
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn collections_keep_manual_order() {
+        let db_path = temp_db_path("collections");
+        let storage = Storage::open(&db_path).expect("open db");
+
+        let board = storage
+            .create_collection("Release")
+            .expect("create collection");
+        let a = insert_text(&storage, "first");
+        let b = insert_text(&storage, "second");
+        let c = insert_text(&storage, "third");
+        for id in [a, b, c] {
+            storage.add_to_collection(board.id, id).expect("add");
+        }
+        storage.add_to_collection(board.id, a).expect("add again");
+
+        let in_board = |storage: &Storage| -> Vec<i64> {
+            search(
+                storage,
+                SearchParams {
+                    collection_id: Some(board.id),
+                    ..Default::default()
+                },
+            )
+            .items
+            .iter()
+            .map(|item| item.id)
+            .collect()
+        };
+        assert_eq!(in_board(&storage), vec![a, b, c]);
+
+        storage
+            .reorder_collection(board.id, &[c, a])
+            .expect("reorder");
+        assert_eq!(in_board(&storage), vec![c, a, b]);
+
+        storage.remove_from_collection(board.id, a).expect("remove");
+        assert_eq!(in_board(&storage), vec![c, b]);
+
+        insert_text(&storage, "fourth");
+        storage.enforce_max_items(1).expect("retention");
+        assert_eq!(search(&storage, SearchParams::default()).total, 3);
+        let collections = storage.list_collections().expect("list collections");
+        assert_eq!(collections[0].item_count, 2);
+
+        // Clearing keeps only pinned and favorite items, collected or not.
+        storage.clear_history().expect("clear");
+        assert_eq!(search(&storage, SearchParams::default()).total, 0);
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn snippets_are_searchable_by_name_and_outlive_history() {
+        let db_path = temp_db_path("snippets");
+        let storage = Storage::open(&db_path).expect("open db");
+
+        let reply = storage
+            .save_snippet(None, "Reply: thanks", "Thanks {input:Name}!")
+            .expect("create snippet");
+        storage
+            .save_snippet(None, "SQL 100%_select", "SELECT {cursor} FROM t")
+            .expect("create sql snippet");
+        assert_eq!(reply.inputs, vec!["Name".to_string()]);
+        assert!(storage.save_snippet(None, "reply: THANKS", "dup").is_err());
+
+        let found = storage.list_snippets("reply").expect("search");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, reply.id);
+        assert_eq!(storage.list_snippets("%_").expect("literal like").len(), 1);
+        assert_eq!(storage.list_snippets("").expect("all").len(), 2);
+
+        let updated = storage
+            .save_snippet(Some(reply.id), "Reply: thanks", "Cheers {input:Who}")
+            .expect("update snippet");
+        assert_eq!(updated.inputs, vec!["Who".to_string()]);
+
+        storage.clear_all_history().expect("clear");
+        storage.enforce_max_items(10).expect("retention");
+        assert_eq!(storage.list_snippets("").expect("all").len(), 2);
+
+        storage.delete_snippet(reply.id).expect("delete");
+        assert!(storage.get_snippet(reply.id).expect("get").is_none());
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn nth_recent_item_counts_from_newest() {
+        let db_path = temp_db_path("nth-recent");
+        let storage = Storage::open(&db_path).expect("open db");
+
+        let first = insert_text(&storage, "first");
+        let second = insert_text(&storage, "second");
+        let third = insert_text(&storage, "third");
+        storage.pin_item(first, true).expect("pin");
+        storage.delete_item(second).expect("delete");
+
+        let nth = |n, pinned_only| storage.nth_recent_item_id(n, pinned_only).expect("nth");
+        assert_eq!(nth(1, false), Some(third));
+        assert_eq!(nth(2, false), Some(first));
+        assert_eq!(nth(3, false), None);
+        assert_eq!(nth(1, true), Some(first));
+        assert_eq!(nth(0, false), None);
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn failures_carry_stable_error_codes() {
+        let db_path = temp_db_path("error-codes");
+        let storage = Storage::open(&db_path).expect("open db");
+
+        storage.create_tag("work").expect("create tag");
+        let code = |err: ClipItError| err.code();
+        assert_eq!(
+            storage.create_tag("work").map_err(code).unwrap_err(),
+            "already_exists"
+        );
+        assert_eq!(
+            storage.create_tag(" ").map_err(code).unwrap_err(),
+            "invalid_input"
+        );
+        assert_eq!(
+            storage
+                .save_snippet(Some(404), "missing", "text")
+                .map_err(code)
+                .unwrap_err(),
+            "not_found"
+        );
+
+        let bad_regex = storage.search_items(&SearchParams {
+            query: "(".to_string(),
+            mode: Some("regex".to_string()),
+            limit: 10,
+            ..Default::default()
+        });
+        assert_eq!(bad_regex.map_err(code).unwrap_err(), "invalid_input");
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn out_of_line_payloads_are_decoded_on_read() {
+        let db_path = temp_db_path("out-of-line");
+        let storage = Storage::open(&db_path).expect("open db");
+
+        let full = "lorem ipsum ".repeat(1000);
+        let zstd = super::compress::compress_text(&full).expect("compress");
+        let text_id = storage
+            .insert_item(&NewItem {
+                kind: "text",
+                text: Some("lorem ipsum [… truncated]"),
+                text_zstd: Some(&zstd),
+                fingerprint: "long text",
+                ..Default::default()
+            })
+            .expect("insert text");
+        let payload = storage
+            .get_item_clipboard_payload(text_id)
+            .expect("payload")
+            .expect("item");
+        assert_eq!(payload.text.as_deref(), Some(full.as_str()));
+        let preview = storage.get_item_preview(text_id).expect("preview");
+        assert_eq!(preview.expect("item").text, full);
+
+        // Editing keeps the full text as the revision and drops the blob.
+        storage
+            .update_item_text(
+                text_id,
+                "edited",
+                "edited",
+                None,
+                None,
+                &Settings::default(),
+            )
+            .expect("edit");
+        let revisions = storage.list_item_revisions(text_id).expect("revisions");
+        assert_eq!(revisions[0].text, full);
+        let payload = storage
+            .get_item_clipboard_payload(text_id)
+            .expect("payload");
+        assert_eq!(payload.expect("item").text.as_deref(), Some("edited"));
+
+        let rgba: Vec<u8> = (0..2 * 2 * 4).map(|i| i as u8 * 15).collect();
+        let mut png = Vec::new();
+        image::ImageEncoder::write_image(
+            image::codecs::png::PngEncoder::new(&mut png),
+            &rgba,
+            2,
+            2,
+            image::ExtendedColorType::Rgba8,
+        )
+        .expect("encode");
+        let image_id = storage
+            .insert_item(&NewItem {
+                kind: "image",
+                text: Some("image://2x2"),
+                fingerprint: "png image",
+                image_encoded: Some(&png),
+                image_width: Some(2),
+                image_height: Some(2),
+                ..Default::default()
+            })
+            .expect("insert image");
+        let payload = storage
+            .get_item_clipboard_payload(image_id)
+            .expect("payload")
+            .expect("item");
+        assert_eq!(payload.image_rgba, Some(rgba));
+
+        let _ = std::fs::remove_file(db_path);
+    }
+
+    #[test]
+    fn mutations_raise_lifecycle_events() {
+        let db_path = temp_db_path("events");
+        let mut storage = Storage::open(&db_path).expect("open db");
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        storage.set_event_sink(move |event| sink.lock().expect("events").push(event));
+        let take = || std::mem::take(&mut *events.lock().expect("events"));
+
+        let a = insert_text(&storage, "alpha");
+        let b = insert_text(&storage, "beta");
+        let c = insert_text(&storage, "gamma");
+
+        storage.pin_item(a, true).expect("pin");
+        storage.pin_item(a, true).expect("pin again");
+        storage
+            .update_item_text(a, "alpha 2", "fp-a2", None, None, &Settings::default())
+            .expect("edit");
+        storage.delete_item(b).expect("delete");
+        storage.delete_item(b).expect("delete again");
+        let events = take();
+        assert_eq!(events.len(), 3);
+        match &events[0] {
+            StorageEvent::ItemUpdated(e) => {
+                assert_eq!(e.change, ItemChange::Pinned);
+                assert!(e.item.pinned);
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        match &events[1] {
+            StorageEvent::ItemUpdated(e) => assert_eq!(e.item.text, "alpha 2"),
+            other => panic!("unexpected {other:?}"),
+        }
+        assert!(matches!(&events[2], StorageEvent::ItemDeleted(e) if e.id == b));
+
+        let d = insert_text(&storage, "delta");
+        storage.enforce_max_items(1).expect("retention");
+        storage.clear_history().expect("clear");
+        let events = take();
+        let (StorageEvent::ItemsPruned(pruned), StorageEvent::HistoryCleared(cleared)) =
+            (&events[0], &events[1])
+        else {
+            panic!("unexpected {events:?}");
+        };
+        assert_eq!(pruned.ids.len(), 1);
+        assert!(!cleared.all);
+        let mut removed = [pruned.ids.clone(), cleared.ids.clone()].concat();
+        removed.sort();
+        assert_eq!(removed, vec![c, d]);
+
+        let json = serde_json::to_value(&events[1]).expect("json");
+        assert_eq!(json["schemaVersion"], ITEM_EVENT_SCHEMA_VERSION);
+        assert_eq!(events[1].name(), "clipboard:history_cleared");
+
+        let _ = std::fs::remove_file(db_path);
+    }
 }
@@ -0,0 +1,245 @@
+//! Read-only connections for queries. The database runs in WAL mode, so
+//! readers see the last committed state without waiting for the writer, and a
+//! long insert on the writer never holds up a search keystroke.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use std::time::Duration;
+
+use rusqlite::{Connection, OpenFlags};
+
+use super::{register_sql_functions, Storage};
+use crate::error::{Context, Result};
+
+/// Readers kept open; enough for the popup, preview and an export at once.
+pub const READ_POOL_SIZE: usize = 3;
+
+/// How long a reader waits out a writer's checkpoint before `StorageLocked`.
+const READER_BUSY_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct ReadPool {
+    readers: Vec<Mutex<Storage>>,
+    next: AtomicUsize,
+}
+
+impl ReadPool {
+    /// Opens `size` readers onto the database at `path`, which `Storage::open`
+    /// must already have created and migrated.
+    pub fn open(path: &Path, size: usize) -> Result<Self> {
+        let readers = (0..size.max(1))
+            .map(|_| Storage::open_reader(path).map(Mutex::new))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            readers,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// A reader for queries; mutations through it fail as read-only. Takes the
+    /// first idle reader, or waits for one in turn when all are busy.
+    pub fn get(&self) -> Result<MutexGuard<'_, Storage>> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let count = self.readers.len();
+        for offset in 0..count {
+            match self.readers[(start + offset) % count].try_lock() {
+                Ok(reader) => return Ok(reader),
+                Err(TryLockError::WouldBlock) => continue,
+                Err(TryLockError::Poisoned(err)) => return Err(err.into()),
+            }
+        }
+        Ok(self.readers[start % count].lock()?)
+    }
+}
+
+impl Storage {
+    /// A read-only connection onto the database at `path`. It shares the
+    /// writer's SQL functions but runs no migrations and raises no events.
+    pub fn open_reader(path: &Path) -> Result<Self> {
+        let conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .context("failed to open sqlite reader")?;
+        conn.busy_timeout(READER_BUSY_TIMEOUT)?;
+        register_sql_functions(&conn)?;
+        Ok(Self { conn, events: None })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::ReadPool;
+    use crate::storage::{NewItem, SearchParams, Storage};
+    use crate::test_support::{insert_text, temp_dir};
+
+    fn query(text: &str) -> SearchParams {
+        SearchParams {
+            query: text.to_string(),
+            limit: 50,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn readers_see_commits_and_reject_writes() {
+        let dir = temp_dir("readers");
+        let db_path = dir.join("clipit.db");
+        let writer = Storage::open(&db_path).expect("open db");
+        let pool = ReadPool::open(&db_path, 2).expect("pool");
+
+        insert_text(&writer, "visible to readers");
+        let found = pool
+            .get()
+            .expect("reader")
+            .search_items(&query("visible"))
+            .expect("search");
+        assert_eq!(found.total, 1);
+
+        let err = pool
+            .get()
+            .expect("reader")
+            .insert_item(&NewItem {
+                kind: "text",
+                text: Some("nope"),
+                fingerprint: "nope",
+                ..Default::default()
+            })
+            .expect_err("read-only");
+        assert_eq!(err.code(), "storage_failure");
+
+        // Both readers are handed out while one is held.
+        let held = pool.get().expect("first");
+        let other = pool.get().expect("second");
+        assert!(!std::ptr::eq(&*held, &*other));
+
+        drop((held, other));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn searches_run_while_the_writer_is_mid_transaction() {
+        let dir = temp_dir("concurrent");
+        let db_path = dir.join("clipit.db");
+        let writer = Storage::open(&db_path).expect("open db");
+        insert_text(&writer, "committed before");
+        let pool = ReadPool::open(&db_path, 1).expect("pool");
+
+        writer
+            .batch(|| {
+                insert_text(&writer, "still uncommitted");
+                let reader = pool.get()?;
+                assert_eq!(reader.search_items(&query("committed"))?.total, 1);
+                assert_eq!(reader.search_items(&query("uncommitted"))?.total, 0);
+                Ok(())
+            })
+            .expect("batch");
+        assert_eq!(
+            pool.get()
+                .expect("reader")
+                .search_items(&query("uncommitted"))
+                .expect("search")
+                .total,
+            1
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    /// Search latency while another thread keeps inserting 4K screenshots,
+    /// with searches sharing the writer's lock (as before the pool) and with
+    /// a reader. `cargo test --release -- --ignored search_latency --nocapture`
+    #[test]
+    #[ignore]
+    fn search_latency_under_capture_load() {
+        const SEARCHES: usize = 200;
+        let dir = temp_dir("bench");
+        let db_path = dir.join("clipit.db");
+        let writer = Arc::new(Mutex::new(Storage::open(&db_path).expect("open db")));
+        {
+            let writer = writer.lock().expect("writer");
+            writer
+                .batch(|| {
+                    for i in 0..5_000 {
+                        insert_text(&writer, &format!("log line {i} request served in {i}ms"));
+                    }
+                    Ok(())
+                })
+                .expect("seed");
+        }
+        let pool = ReadPool::open(&db_path, super::READ_POOL_SIZE).expect("pool");
+
+        let measure = |label: &'static str, search: &dyn Fn() -> usize| {
+            let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+            let capture = {
+                let (writer, stop) = (writer.clone(), stop.clone());
+                thread::spawn(move || {
+                    let rgba = vec![0x7F_u8; 3840 * 2160 * 4];
+                    let mut captured = 0;
+                    while !stop.load(std::sync::atomic::Ordering::Relaxed) {
+                        let fingerprint = format!("bench-image-{label}-{captured}");
+                        writer
+                            .lock()
+                            .expect("writer")
+                            .insert_item(&NewItem {
+                                kind: "image",
+                                text: Some("image://3840x2160"),
+                                fingerprint: &fingerprint,
+                                image_rgba: Some(&rgba),
+                                image_width: Some(3840),
+                                image_height: Some(2160),
+                                ..Default::default()
+                            })
+                            .expect("capture");
+                        captured += 1;
+                    }
+                    captured
+                })
+            };
+
+            let mut latencies: Vec<Duration> = (0..SEARCHES)
+                .map(|_| {
+                    let started = Instant::now();
+                    assert!(search() > 0);
+                    let elapsed = started.elapsed();
+                    thread::sleep(Duration::from_millis(5));
+                    elapsed
+                })
+                .collect();
+            stop.store(true, std::sync::atomic::Ordering::Relaxed);
+            let captured = capture.join().expect("capture thread");
+
+            latencies.sort();
+            let at = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+            println!(
+                "{label:>12}: p50 {:?}  p95 {:?}  max {:?}  ({captured} images captured)",
+                at(50),
+                at(95),
+                at(100)
+            );
+            at(95)
+        };
+
+        let shared = measure("shared lock", &|| {
+            let storage = writer.lock().expect("writer");
+            storage
+                .search_items(&query("request"))
+                .expect("search")
+                .total as usize
+        });
+        let pooled = measure("read pool", &|| {
+            let reader = pool.get().expect("reader");
+            reader
+                .search_items(&query("request"))
+                .expect("search")
+                .total as usize
+        });
+        assert!(pooled <= shared, "pool p95 {pooled:?} vs shared {shared:?}");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,447 @@
+//! Startup integrity check and salvage. A database that fails `quick_check`
+//! is moved aside and whatever rows can still be read are copied, one rowid
+//! range at a time, into a fresh database, so one bad page costs the rows on
+//! it rather than the whole history.
+
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection, ErrorCode, OpenFlags};
+use serde::Serialize;
+
+use super::Storage;
+use crate::error::{ClipItError, Context, Result};
+
+/// Tables copied during salvage, parents before the tables referencing them.
+const SALVAGED_TABLES: [&str; 8] = [
+    "settings",
+    "items",
+    "item_revisions",
+    "tags",
+    "item_tags",
+    "collections",
+    "collection_items",
+    "snippets",
+];
+
+/// Rows read per query. A range that fails is retried one rowid at a time.
+const SALVAGE_CHUNK: i64 = 256;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveryReport {
+    /// Why the database was rejected: the `quick_check` findings or the error
+    /// that kept it from opening.
+    pub reason: String,
+    /// Where the damaged file was moved.
+    pub corrupt_path: String,
+    pub tables: Vec<SalvagedTable>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SalvagedTable {
+    pub name: String,
+    pub recovered: u32,
+    /// Rowids in the table's range that could not be read or stored.
+    pub lost: u32,
+    /// False when the table couldn't be found in the damaged file at all.
+    pub readable: bool,
+}
+
+impl RecoveryReport {
+    pub fn table(&self, name: &str) -> Option<&SalvagedTable> {
+        self.tables.iter().find(|table| table.name == name)
+    }
+}
+
+impl Storage {
+    /// Opens the database at `path` like `open`, but first runs
+    /// `PRAGMA quick_check`. A damaged file is moved aside and salvaged into a
+    /// fresh database, and the report of what was kept is returned with it.
+    pub fn open_or_recover(path: &Path) -> Result<(Self, Option<RecoveryReport>)> {
+        let Some(reason) = quick_check(path)? else {
+            return Ok((Self::open(path)?, None));
+        };
+
+        let corrupt_path = move_aside(path)?;
+        let storage = Self::open(path)?;
+        let tables = storage.salvage_from(&corrupt_path)?;
+        Ok((
+            storage,
+            Some(RecoveryReport {
+                reason,
+                corrupt_path: corrupt_path.display().to_string(),
+                tables,
+            }),
+        ))
+    }
+
+    fn salvage_from(&self, corrupt_path: &Path) -> Result<Vec<SalvagedTable>> {
+        let source = match Connection::open_with_flags(
+            corrupt_path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        // Lets SQLite read past a header that claims more pages than the
+        // (truncated) file has, instead of refusing to load the schema.
+        .and_then(|source| {
+            source.pragma_update(None, "writable_schema", true)?;
+            Ok(source)
+        }) {
+            Ok(source) => Some(source),
+            Err(err) => {
+                eprintln!("damaged database can't be opened for salvage: {err}");
+                None
+            }
+        };
+
+        // Salvaged rows may point at parents that were lost; orphans are
+        // dropped after the copy instead of failing it.
+        self.conn.pragma_update(None, "foreign_keys", false)?;
+        let tx = self.conn.unchecked_transaction()?;
+        let tables = SALVAGED_TABLES
+            .iter()
+            .map(|table| match &source {
+                Some(source) => salvage_table(source, &self.conn, table),
+                None => Ok(SalvagedTable {
+                    name: table.to_string(),
+                    recovered: 0,
+                    lost: 0,
+                    readable: false,
+                }),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.conn.execute_batch(
+            "DELETE FROM item_tags WHERE item_id NOT IN (SELECT id FROM items)
+                OR tag_id NOT IN (SELECT id FROM tags);
+             DELETE FROM collection_items WHERE item_id NOT IN (SELECT id FROM items)
+                OR collection_id NOT IN (SELECT id FROM collections);
+             DELETE FROM item_revisions WHERE item_id NOT IN (SELECT id FROM items);
+             INSERT INTO items_fts(items_fts) VALUES('rebuild');",
+        )?;
+        tx.commit()?;
+        self.conn.pragma_update(None, "foreign_keys", true)?;
+        Ok(tables)
+    }
+}
+
+/// `Some` carries the findings, or the error that marked the file as damaged;
+/// a missing file passes, it will be created. Errors that say nothing about
+/// the file itself (busy, locked, permissions, I/O) are returned, so a healthy
+/// database is never moved aside because another process holds it.
+fn quick_check(path: &Path) -> Result<Option<String>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let findings = Connection::open(path).and_then(|conn| {
+        conn.prepare("PRAGMA quick_check")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+    });
+    match findings {
+        Ok(findings) => match findings.as_slice() {
+            [ok] if ok == "ok" => Ok(None),
+            _ => Ok(Some(findings.join("; "))),
+        },
+        Err(err) if is_damage(&err) => Ok(Some(err.to_string())),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn is_damage(err: &rusqlite::Error) -> bool {
+    matches!(
+        err.sqlite_error_code(),
+        Some(ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase)
+    )
+}
+
+/// Renames the database and its WAL/shared-memory files to
+/// `<name>.corrupt-<timestamp>` and returns the new database path.
+fn move_aside(path: &Path) -> Result<PathBuf> {
+    let suffix = format!("corrupt-{}", Local::now().format("%Y%m%d-%H%M%S"));
+    let moved = path.with_extension(format!(
+        "{}.{suffix}",
+        path.extension().unwrap_or_default().to_string_lossy()
+    ));
+    std::fs::rename(path, &moved).map_err(|err| {
+        ClipItError::storage(format!("failed to move damaged database aside: {err}"))
+    })?;
+    for companion in ["-wal", "-shm"] {
+        let mut from = path.as_os_str().to_owned();
+        from.push(companion);
+        let mut to = moved.as_os_str().to_owned();
+        to.push(companion);
+        let _ = std::fs::rename(from, to);
+    }
+    Ok(moved)
+}
+
+fn salvage_table(source: &Connection, dest: &Connection, table: &str) -> Result<SalvagedTable> {
+    let mut report = SalvagedTable {
+        name: table.to_string(),
+        recovered: 0,
+        lost: 0,
+        readable: false,
+    };
+
+    // Older databases may lack columns added since; copy the ones both have.
+    let wanted = column_names(dest, table).context("failed to read salvage schema")?;
+    let Ok(present) = column_names(source, table) else {
+        return Ok(report);
+    };
+    let columns: Vec<&String> = wanted.iter().filter(|c| present.contains(c)).collect();
+    let Some(max_rowid) = max_rowid(source, table) else {
+        return Ok(report);
+    };
+    if columns.is_empty() {
+        return Ok(report);
+    }
+    report.readable = true;
+
+    let list = columns
+        .iter()
+        .map(|c| format!("\"{c}\""))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let select = format!("SELECT {list} FROM {table} WHERE rowid BETWEEN ?1 AND ?2");
+    let insert = format!(
+        "INSERT OR REPLACE INTO {table}({list}) VALUES({})",
+        vec!["?"; columns.len()].join(", ")
+    );
+
+    let mut start = 1;
+    while start <= max_rowid {
+        let end = start.saturating_add(SALVAGE_CHUNK - 1).min(max_rowid);
+        match read_rows(source, &select, start, end, columns.len()) {
+            Ok(rows) => store_rows(dest, &insert, rows, &mut report),
+            // Retry the range row by row to keep what's readable around the bad page.
+            Err(_) => {
+                for rowid in start..=end {
+                    match read_rows(source, &select, rowid, rowid, columns.len()) {
+                        Ok(rows) => store_rows(dest, &insert, rows, &mut report),
+                        Err(_) => report.lost += 1,
+                    }
+                }
+            }
+        }
+        start = end + 1;
+    }
+    Ok(report)
+}
+
+/// The table's last rowid, or for `AUTOINCREMENT` tables the last one handed
+/// out when the rightmost pages needed for `MAX(rowid)` are gone.
+fn max_rowid(source: &Connection, table: &str) -> Option<i64> {
+    source
+        .query_row(
+            &format!("SELECT COALESCE(MAX(rowid), 0) FROM {table}"),
+            [],
+            |row| row.get(0),
+        )
+        .or_else(|_| {
+            source.query_row(
+                "SELECT seq FROM sqlite_sequence WHERE name = ?1",
+                [table],
+                |row| row.get(0),
+            )
+        })
+        .ok()
+}
+
+fn column_names(conn: &Connection, table: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+    let names = stmt
+        .query_map([], |row| row.get(1))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    if names.is_empty() {
+        return Err(rusqlite::Error::QueryReturnedNoRows);
+    }
+    Ok(names)
+}
+
+fn read_rows(
+    source: &Connection,
+    select: &str,
+    start: i64,
+    end: i64,
+    width: usize,
+) -> rusqlite::Result<Vec<Vec<Value>>> {
+    let mut stmt = source.prepare_cached(select)?;
+    let rows = stmt
+        .query_map([start, end], |row| {
+            (0..width).map(|i| row.get::<_, Value>(i)).collect()
+        })?
+        .collect();
+    rows
+}
+
+fn store_rows(dest: &Connection, insert: &str, rows: Vec<Vec<Value>>, report: &mut SalvagedTable) {
+    for row in rows {
+        let stored = dest
+            .prepare_cached(insert)
+            .and_then(|mut stmt| stmt.execute(params_from_iter(row)));
+        match stored {
+            Ok(_) => report.recovered += 1,
+            Err(_) => report.lost += 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::error::ClipItError;
+    use crate::storage::{NewItem, Storage};
+    use crate::test_support::temp_dir;
+
+    const ITEMS: usize = 400;
+
+    /// A closed database with `ITEMS` items, one tag and a changed setting.
+    fn fixture_db(dir: &Path) -> PathBuf {
+        let db_path = dir.join("clipit.db");
+        let storage = Storage::open(&db_path).expect("open db");
+        for i in 0..ITEMS {
+            let text = format!("clip number {i} {}", "padding ".repeat(20));
+            let id = storage
+                .insert_item(&NewItem {
+                    kind: "text",
+                    text: Some(&text),
+                    fingerprint: &format!("fingerprint-{i}"),
+                    ..Default::default()
+                })
+                .expect("insert");
+            if i == 0 {
+                let tag = storage.create_tag("keep").expect("tag");
+                storage.set_item_tag(id, tag.id, true).expect("tag item");
+            }
+        }
+        storage
+            .upsert_setting("max_items", &serde_json::json!(500))
+            .expect("setting");
+        drop(storage);
+        db_path
+    }
+
+    /// Live items, or those whose FTS entry matches `query` when given.
+    fn count(storage: &Storage, query: Option<&str>) -> usize {
+        let counted = match query {
+            Some(query) => storage.conn.query_row(
+                "SELECT COUNT(*) FROM items_fts WHERE items_fts MATCH ?1",
+                [query],
+                |row| row.get::<_, i64>(0),
+            ),
+            None => storage
+                .conn
+                .query_row("SELECT COUNT(*) FROM items", [], |row| row.get(0)),
+        };
+        counted.expect("count") as usize
+    }
+
+    const PAGE_SIZE: usize = 4096;
+
+    #[test]
+    fn healthy_databases_open_without_a_report() {
+        let dir = temp_dir("healthy");
+        let db_path = fixture_db(&dir);
+
+        let (storage, report) = Storage::open_or_recover(&db_path).expect("open");
+        assert!(report.is_none());
+        assert_eq!(count(&storage, None), ITEMS);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn locked_databases_are_left_in_place() {
+        let dir = temp_dir("locked");
+        let db_path = fixture_db(&dir);
+        let holder = rusqlite::Connection::open(&db_path).expect("open holder");
+        holder
+            .execute_batch("PRAGMA locking_mode = EXCLUSIVE; BEGIN EXCLUSIVE;")
+            .expect("lock");
+
+        let err = Storage::open_or_recover(&db_path).err().expect("locked");
+        assert!(matches!(err, ClipItError::StorageLocked), "{err:?}");
+        drop(holder);
+        let (storage, report) = Storage::open_or_recover(&db_path).expect("open");
+        assert!(report.is_none());
+        assert_eq!(count(&storage, None), ITEMS);
+        assert!(std::fs::read_dir(&dir)
+            .expect("dir")
+            .flatten()
+            .all(|entry| !entry.file_name().to_string_lossy().contains("corrupt")));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn salvages_rows_around_corrupted_pages() {
+        let dir = temp_dir("corrupt");
+        let db_path = fixture_db(&dir);
+        let mut bytes = std::fs::read(&db_path).expect("read");
+        let needle = b"clip number 200 ";
+        let hit = bytes
+            .windows(needle.len())
+            .position(|window| window == needle)
+            .expect("item on disk");
+        let page = hit / PAGE_SIZE * PAGE_SIZE;
+        bytes[page..page + PAGE_SIZE].fill(0xA5);
+        std::fs::write(&db_path, bytes).expect("corrupt");
+
+        let (storage, report) = Storage::open_or_recover(&db_path).expect("recover");
+        let report = report.expect("recovery report");
+        assert!(Path::new(&report.corrupt_path).exists());
+        let items = report.table("items").expect("items");
+        assert!(items.readable);
+        assert!(items.recovered > 0, "{report:?}");
+        assert!((items.recovered as usize) < ITEMS, "{report:?}");
+        assert_eq!(report.table("settings").expect("settings").recovered, 1);
+
+        assert_eq!(count(&storage, None), items.recovered as usize);
+        assert_eq!(count(&storage, Some("padding")), items.recovered as usize);
+        assert_eq!(storage.load_settings().expect("settings").max_items, 500);
+        assert_eq!(super::quick_check(&db_path).expect("check"), None);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn truncated_databases_keep_their_leading_rows() {
+        let dir = temp_dir("truncated");
+        let db_path = fixture_db(&dir);
+        let len = std::fs::metadata(&db_path).expect("meta").len();
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&db_path)
+            .and_then(|file| file.set_len(len * 2 / 3 / PAGE_SIZE as u64 * PAGE_SIZE as u64))
+            .expect("truncate");
+
+        let (storage, report) = Storage::open_or_recover(&db_path).expect("recover");
+        let report = report.expect("recovery report");
+        let recovered = report.table("items").expect("items").recovered as usize;
+        assert!(recovered > 0 && recovered < ITEMS, "{report:?}");
+        assert_eq!(count(&storage, Some("padding")), recovered);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn unreadable_files_start_a_fresh_history() {
+        let dir = temp_dir("garbage");
+        let db_path = dir.join("clipit.db");
+        std::fs::write(&db_path, vec![0x5A; 8192]).expect("write");
+
+        let (storage, report) = Storage::open_or_recover(&db_path).expect("recover");
+        let report = report.expect("recovery report");
+        assert!(report.tables.iter().all(|table| table.recovered == 0));
+        assert_eq!(count(&storage, None), 0);
+        assert_eq!(
+            std::fs::read(&report.corrupt_path).expect("moved").len(),
+            8192
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,47 @@
+//! Fixtures shared by the unit tests.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::classify::classify_text;
+use crate::storage::{NewItem, Storage};
+
+/// A path under the system temp dir no other test uses.
+fn unique_temp_path(name: &str) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "clipit-{name}-{}-{}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// A fresh, empty directory; remove it when the test is done.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = unique_temp_path(name);
+    std::fs::create_dir_all(&dir).expect("temp dir");
+    dir
+}
+
+/// A path for a database that doesn't exist yet.
+pub fn temp_db_path(name: &str) -> PathBuf {
+    unique_temp_path(name).with_extension("db")
+}
+
+/// Inserts a classified text item fingerprinted by its text.
+pub fn insert_text(storage: &Storage, text: &str) -> i64 {
+    let class = classify_text(text);
+    storage
+        .insert_item(&NewItem {
+            kind: "text",
+            subtype: Some(class.subtype),
+            language: class.language,
+            text: Some(text),
+            fingerprint: text,
+            ..Default::default()
+        })
+        .expect("insert text")
+}
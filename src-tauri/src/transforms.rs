@@ -0,0 +1,344 @@
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Unreserved characters from RFC 3986 are left as-is when URL-encoding.
+const URL_COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Query parameters dropped by `strip_tracking`, matched case-insensitively.
+/// Any parameter starting with `utm_` is dropped as well.
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid", "gclid", "dclid", "gbraid", "wbraid", "msclkid", "mc_cid", "mc_eid", "igshid",
+    "yclid", "_hsenc", "_hsmi", "mkt_tok", "ref_src", "si",
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransformOutput {
+    pub text: String,
+    /// Set when the result was saved as a new history item.
+    pub item_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Transform {
+    pub id: &'static str,
+    pub label: &'static str,
+    #[serde(skip)]
+    apply: fn(&str) -> Result<String>,
+}
+
+pub const TRANSFORMS: &[Transform] = &[
+    Transform {
+        id: "json_pretty",
+        label: "JSON: pretty-print",
+        apply: json_pretty,
+    },
+    Transform {
+        id: "json_minify",
+        label: "JSON: minify",
+        apply: json_minify,
+    },
+    Transform {
+        id: "trim",
+        label: "Trim whitespace",
+        apply: trim,
+    },
+    Transform {
+        id: "upper_case",
+        label: "UPPER CASE",
+        apply: upper_case,
+    },
+    Transform {
+        id: "lower_case",
+        label: "lower case",
+        apply: lower_case,
+    },
+    Transform {
+        id: "title_case",
+        label: "Title Case",
+        apply: title_case,
+    },
+    Transform {
+        id: "url_encode",
+        label: "URL encode",
+        apply: url_encode,
+    },
+    Transform {
+        id: "url_decode",
+        label: "URL decode",
+        apply: url_decode,
+    },
+    Transform {
+        id: "base64_encode",
+        label: "Base64 encode",
+        apply: base64_encode,
+    },
+    Transform {
+        id: "base64_decode",
+        label: "Base64 decode",
+        apply: base64_decode,
+    },
+    Transform {
+        id: "strip_tracking",
+        label: "Strip tracking parameters",
+        apply: strip_tracking,
+    },
+    Transform {
+        id: "shell_escape",
+        label: "Escape for shell",
+        apply: shell_escape,
+    },
+    Transform {
+        id: "sort_lines",
+        label: "Sort lines",
+        apply: sort_lines,
+    },
+    Transform {
+        id: "dedupe_lines",
+        label: "Remove duplicate lines",
+        apply: dedupe_lines,
+    },
+];
+
+pub fn apply_transform(id: &str, input: &str) -> Result<String> {
+    let transform = TRANSFORMS
+        .iter()
+        .find(|t| t.id == id)
+        .ok_or_else(|| anyhow::anyhow!("unknown transform: {id}"))?;
+    (transform.apply)(input)
+}
+
+fn json_pretty(input: &str) -> Result<String> {
+    let value: Value = serde_json::from_str(input.trim()).context("input is not valid JSON")?;
+    Ok(serde_json::to_string_pretty(&value)?)
+}
+
+fn json_minify(input: &str) -> Result<String> {
+    let value: Value = serde_json::from_str(input.trim()).context("input is not valid JSON")?;
+    Ok(serde_json::to_string(&value)?)
+}
+
+fn trim(input: &str) -> Result<String> {
+    Ok(input
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string())
+}
+
+fn upper_case(input: &str) -> Result<String> {
+    Ok(input.to_uppercase())
+}
+
+fn lower_case(input: &str) -> Result<String> {
+    Ok(input.to_lowercase())
+}
+
+fn title_case(input: &str) -> Result<String> {
+    let mut out = String::with_capacity(input.len());
+    let mut at_word_start = true;
+    for c in input.chars() {
+        if c.is_alphanumeric() {
+            if at_word_start {
+                out.extend(c.to_uppercase());
+            } else {
+                out.extend(c.to_lowercase());
+            }
+            at_word_start = false;
+        } else {
+            out.push(c);
+            at_word_start = c.is_whitespace() || c == '-' || c == '_';
+        }
+    }
+    Ok(out)
+}
+
+fn url_encode(input: &str) -> Result<String> {
+    Ok(utf8_percent_encode(input, URL_COMPONENT).to_string())
+}
+
+fn url_decode(input: &str) -> Result<String> {
+    Ok(percent_decode_str(input)
+        .decode_utf8()
+        .context("decoded bytes are not valid UTF-8")?
+        .into_owned())
+}
+
+fn base64_encode(input: &str) -> Result<String> {
+    Ok(base64::engine::general_purpose::STANDARD.encode(input))
+}
+
+fn base64_decode(input: &str) -> Result<String> {
+    let compact: String = input.chars().filter(|c| !c.is_whitespace()).collect();
+    let unpadded = compact.trim_end_matches('=');
+    let bytes = base64::engine::general_purpose::STANDARD_NO_PAD
+        .decode(unpadded)
+        .or_else(|_| base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(unpadded))
+        .context("input is not valid base64")?;
+    String::from_utf8(bytes).context("decoded bytes are not valid UTF-8")
+}
+
+/// Removes tracking query parameters from every http(s) URL line; other lines are
+/// kept verbatim.
+fn strip_tracking(input: &str) -> Result<String> {
+    let mut found_url = false;
+    let lines: Vec<String> = input
+        .lines()
+        .map(|line| match url::Url::parse(line.trim()) {
+            Ok(mut url) if matches!(url.scheme(), "http" | "https") => {
+                found_url = true;
+                let kept: Vec<(String, String)> = url
+                    .query_pairs()
+                    .filter(|(key, _)| !is_tracking_param(key))
+                    .map(|(k, v)| (k.into_owned(), v.into_owned()))
+                    .collect();
+                if kept.is_empty() {
+                    url.set_query(None);
+                } else {
+                    url.query_pairs_mut().clear().extend_pairs(kept);
+                }
+                url.to_string()
+            }
+            _ => line.to_string(),
+        })
+        .collect();
+
+    if !found_url {
+        bail!("input contains no http(s) URL");
+    }
+    Ok(lines.join("\n"))
+}
+
+fn is_tracking_param(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    key.starts_with("utm_") || TRACKING_PARAMS.contains(&key.as_str())
+}
+
+/// POSIX single-quote escaping: the result is one shell word.
+fn shell_escape(input: &str) -> Result<String> {
+    if !input.is_empty()
+        && input
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,@%+".contains(c))
+    {
+        return Ok(input.to_string());
+    }
+    Ok(format!("'{}'", input.replace('\'', r"'\''")))
+}
+
+fn sort_lines(input: &str) -> Result<String> {
+    let mut lines: Vec<&str> = input.lines().collect();
+    lines.sort_unstable();
+    Ok(lines.join("\n"))
+}
+
+fn dedupe_lines(input: &str) -> Result<String> {
+    let mut seen = std::collections::HashSet::new();
+    let lines: Vec<&str> = input.lines().filter(|line| seen.insert(*line)).collect();
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_transform, TRANSFORMS};
+
+    fn run(id: &str, input: &str) -> String {
+        apply_transform(id, input).expect("transform")
+    }
+
+    #[test]
+    fn transform_ids_are_unique() {
+        let mut ids: Vec<&str> = TRANSFORMS.iter().map(|t| t.id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), TRANSFORMS.len());
+        assert!(apply_transform("missing", "x").is_err());
+    }
+
+    #[test]
+    fn json_pretty_keeps_key_order() {
+        let out = run("json_pretty", r#"{"b":1,"a":[true,null]}"#);
+        assert_eq!(
+            out,
+            "{\n  \"b\": 1,\n  \"a\": [\n    true,\n    null\n  ]\n}"
+        );
+        assert!(apply_transform("json_pretty", "{oops").is_err());
+    }
+
+    #[test]
+    fn json_minify_drops_whitespace() {
+        assert_eq!(
+            run("json_minify", "{\n  \"a\": [1, 2]\n}"),
+            r#"{"a":[1,2]}"#
+        );
+    }
+
+    #[test]
+    fn trim_strips_outer_and_trailing_whitespace() {
+        assert_eq!(
+            run("trim", "  \n  hello  \n  world\t\n\n"),
+            "hello\n  world"
+        );
+    }
+
+    #[test]
+    fn case_transforms() {
+        assert_eq!(run("upper_case", "Grüße"), "GRÜSSE");
+        assert_eq!(run("lower_case", "HeLLo"), "hello");
+        assert_eq!(
+            run("title_case", "hello wORLD-wide web"),
+            "Hello World-Wide Web"
+        );
+    }
+
+    #[test]
+    fn url_encode_round_trips() {
+        let encoded = run("url_encode", "a b&c=d/é");
+        assert_eq!(encoded, "a%20b%26c%3Dd%2F%C3%A9");
+        assert_eq!(run("url_decode", &encoded), "a b&c=d/é");
+    }
+
+    #[test]
+    fn base64_round_trips_and_accepts_unpadded() {
+        assert_eq!(run("base64_encode", "clip it"), "Y2xpcCBpdA==");
+        assert_eq!(run("base64_decode", "Y2xpcCBpdA=="), "clip it");
+        assert_eq!(run("base64_decode", "Y2xpcCBpdA"), "clip it");
+        assert!(apply_transform("base64_decode", "***").is_err());
+    }
+
+    #[test]
+    fn strip_tracking_removes_known_params() {
+        let out = run(
+            "strip_tracking",
+            "https://example.com/a?id=7&utm_source=x&UTM_Medium=y&fbclid=z\nnot a url",
+        );
+        assert_eq!(out, "https://example.com/a?id=7\nnot a url");
+        assert_eq!(
+            run("strip_tracking", "https://example.com/?gclid=1"),
+            "https://example.com/"
+        );
+        assert!(apply_transform("strip_tracking", "plain text").is_err());
+    }
+
+    #[test]
+    fn shell_escape_quotes_when_needed() {
+        assert_eq!(run("shell_escape", "safe-word_1.txt"), "safe-word_1.txt");
+        assert_eq!(run("shell_escape", "it's $HOME"), r"'it'\''s $HOME'");
+        assert_eq!(run("shell_escape", ""), "''");
+    }
+
+    #[test]
+    fn sort_and_dedupe_lines() {
+        assert_eq!(run("sort_lines", "pear\napple\nfig"), "apple\nfig\npear");
+        assert_eq!(run("dedupe_lines", "a\nb\na\nc\nb"), "a\nb\nc");
+    }
+}
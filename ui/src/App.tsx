@@ -25,6 +25,8 @@ type ClipboardItem = {
   kind: 'text' | 'image' | 'file' | string;
   text?: string;
   previewText?: string;
+  subtype?: string | null;
+  language?: string | null;
   imageWidth?: number | null;
   imageHeight?: number | null;
   createdAt: string | number;
@@ -32,6 +34,33 @@ type ClipboardItem = {
   pinned: boolean;
 };
 
+// Payloads of the `clipboard:item_*` lifecycle events (events.rs).
+const ITEM_EVENT_SCHEMA_VERSION = 1;
+
+type ItemUpdatedEvent = {
+  schemaVersion: number;
+  change: 'favorite' | 'pinned' | 'text' | 'tags';
+  item: ClipboardItem;
+};
+
+type ItemDeletedEvent = {
+  schemaVersion: number;
+  id: number;
+};
+
+type ItemsRemovedEvent = {
+  schemaVersion: number;
+  ids: number[];
+};
+
+type CaptureLimitedEvent = {
+  schemaVersion: number;
+  kind: string;
+  size: number;
+  limit: number;
+  action: 'skipped' | 'truncated' | 'compressed';
+};
+
 type SearchResponse = {
   total: number;
   items: ClipboardItem[];
@@ -124,12 +153,39 @@ function shortcutIndexFromEvent(event: KeyboardEvent): number | null {
   }
 }
 
+type RecoveryReport = {
+  reason: string;
+  corruptPath: string;
+  tables: { name: string; recovered: number; lost: number; readable: boolean }[];
+};
+
+function describeRecovery(report: RecoveryReport): string {
+  const items = report.tables.find((table) => table.name === 'items');
+  const kept = items?.recovered ?? 0;
+  const lost = items && !items.readable ? 'the rest' : `${items?.lost ?? 0}`;
+  return `The history database was damaged and has been rebuilt: ${kept} items recovered, ${lost} lost. The damaged file was kept at ${report.corruptPath}.`;
+}
+
+function describeCaptureLimit({ kind, size, limit, action }: CaptureLimitedEvent): string {
+  const unit = kind === 'image' ? 'pixels' : 'bytes';
+  const over = `${size.toLocaleString()} ${unit}, limit ${limit.toLocaleString()}`;
+  switch (action) {
+    case 'skipped':
+      return `Copied ${kind} too large to record (${over})`;
+    case 'truncated':
+      return `Copied ${kind} was truncated (${over})`;
+    default:
+      return `Copied ${kind} was stored compressed (${over})`;
+  }
+}
+
 export default function App() {
   const [query, setQuery] = useState('');
   const [items, setItems] = useState<ClipboardItem[]>([]);
   const [selectedIndex, setSelectedIndex] = useState(0);
   const [filter, setFilter] = useState<FilterType>('all');
   const [toast, setToast] = useState<string | null>(null);
+  const [recovery, setRecovery] = useState<RecoveryReport | null>(null);
   const [previewOpen, setPreviewOpen] = useState(false);
   const [preview, setPreview] = useState<ItemPreview | null>(null);
   const [previewItemId, setPreviewItemId] = useState<number | null>(null);
@@ -141,10 +197,12 @@ export default function App() {
   const loadItems = useCallback(async (nextQuery: string, nextFilter: FilterType) => {
     try {
       const res = await invoke<SearchResponse>('search_items', {
-        query: nextQuery,
-        limit: 200,
-        offset: 0,
-        filter: nextFilter
+        params: {
+          query: nextQuery,
+          limit: 200,
+          offset: 0,
+          filter: nextFilter
+        }
       });
 
       const nextItems = res?.items ?? [];
@@ -178,19 +236,16 @@ export default function App() {
   const handleDelete = async (event: MouseEvent, item: ClipboardItem) => {
     event.stopPropagation();
     await invoke('delete_item', { itemId: item.id });
-    await loadItems(query, filter);
   };
 
   const handleTogglePin = async (event: MouseEvent, item: ClipboardItem) => {
     event.stopPropagation();
     await invoke('pin_item', { itemId: item.id, pinned: !item.pinned });
-    await loadItems(query, filter);
   };
 
   const handleToggleFavorite = async (event: MouseEvent, item: ClipboardItem) => {
     event.stopPropagation();
     await invoke('favorite_item', { itemId: item.id, favorite: !item.favorite });
-    await loadItems(query, filter);
   };
 
   const openSettings = async () => {
@@ -202,7 +257,6 @@ export default function App() {
     const confirmed = window.confirm('Vuoi cancellare tutti gli elementi della cronologia?');
     if (!confirmed) return;
     await invoke('clear_all_history');
-    await loadItems(query, filter);
     showToast('Cronologia svuotata');
   };
 
@@ -240,6 +294,13 @@ export default function App() {
     };
   }, []);
 
+  useEffect(() => {
+    if (isSettingsView) return;
+    invoke<RecoveryReport | null>('take_recovery_report')
+      .then(setRecovery)
+      .catch(() => setRecovery(null));
+  }, []);
+
   useEffect(() => {
     if (isSettingsView) return;
     applyUiSettings();
@@ -272,6 +333,58 @@ export default function App() {
       }
     });
 
+    const onCaptureLimited = listen<CaptureLimitedEvent>('clipboard:capture_limited', (event) => {
+      showToast(describeCaptureLimit(event.payload));
+    });
+
+    const removeItems = (ids: number[]) => {
+      setItems((current) => {
+        const next = current.filter((item) => !ids.includes(item.id));
+        setSelectedIndex((index) => Math.max(0, Math.min(index, next.length - 1)));
+        return next;
+      });
+    };
+
+    const onItemUpdated = listen<ItemUpdatedEvent>('clipboard:item_updated', async (event) => {
+      const { schemaVersion, change, item } = event.payload;
+      // Pinning reorders the list; unknown schemas can't be patched safely.
+      if (schemaVersion !== ITEM_EVENT_SCHEMA_VERSION || change === 'pinned') {
+        await loadItems(query, filter);
+        return;
+      }
+      if (filter === 'favorites' && !item.favorite) {
+        removeItems([item.id]);
+        return;
+      }
+      setItems((current) => current.map((row) => (row.id === item.id ? item : row)));
+    });
+
+    const onItemDeleted = listen<ItemDeletedEvent>('clipboard:item_deleted', (event) => {
+      removeItems([event.payload.id]);
+    });
+
+    const onItemsRemoved = [
+      'clipboard:history_cleared',
+      'clipboard:items_pruned',
+      'clipboard:images_collapsed',
+    ].map((name) =>
+      listen<ItemsRemovedEvent>(name, (event) => {
+        removeItems(event.payload.ids);
+      })
+    );
+
+    const onItemsImported = listen('clipboard:items_imported', async () => {
+      await loadItems(query, filter);
+    });
+
+    const onBackupRestored = listen('backup:restored', async () => {
+      await loadItems(query, filter);
+    });
+
+    const onSettingsChanged = listen('settings:changed', async () => {
+      await applyUiSettings();
+    });
+
     const onBlur = async () => {
       if (blurCloseRef.current) {
         await hideWindow();
@@ -283,6 +396,13 @@ export default function App() {
     return () => {
       onPopupOpened.then((unlisten) => unlisten());
       onItemAdded.then((unlisten) => unlisten());
+      onCaptureLimited.then((unlisten) => unlisten());
+      onItemUpdated.then((unlisten) => unlisten());
+      onItemDeleted.then((unlisten) => unlisten());
+      onItemsRemoved.forEach((pending) => pending.then((unlisten) => unlisten()));
+      onItemsImported.then((unlisten) => unlisten());
+      onBackupRestored.then((unlisten) => unlisten());
+      onSettingsChanged.then((unlisten) => unlisten());
       window.removeEventListener('blur', onBlur);
     };
   }, [filter, loadItems, query]);
@@ -375,6 +495,17 @@ export default function App() {
         </div>
       </div>
 
+      {recovery ? (
+        <div className="flex items-start gap-2 border-b border-amber-200/60 bg-amber-50/90 px-3 py-2 text-[11px] text-amber-800 dark:border-amber-900/50 dark:bg-amber-900/30 dark:text-amber-200">
+          <span className="flex-1" title={recovery.reason}>
+            {describeRecovery(recovery)}
+          </span>
+          <button onClick={() => setRecovery(null)} className="text-amber-500 hover:text-amber-700" title="Dismiss">
+            <X size={12} />
+          </button>
+        </div>
+      ) : null}
+
       <div className="p-2.5 pb-1.5">
         <div className="group relative">
           <Search
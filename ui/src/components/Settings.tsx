@@ -2,6 +2,7 @@ import { useEffect, useRef, useState } from 'react';
 import { invoke } from '@tauri-apps/api/core';
 import { getCurrentWindow } from '@tauri-apps/api/window';
 import { Command } from 'lucide-react';
+import { formatError } from '../errors';
 
 type SettingsType = {
   hotkey: string;
@@ -13,6 +14,19 @@ type SettingsType = {
   colored_icons: boolean;
 };
 
+type Backup = {
+  name: string;
+  path: string;
+  createdAt: number;
+  sizeBytes: number;
+};
+
+type CompressionReport = {
+  items: number;
+  bytesBefore: number;
+  bytesAfter: number;
+};
+
 const MAX_ITEMS_OPTIONS = [10, 15, 25, 50, 100];
 
 function formatHotkeyFromEvent(e: KeyboardEvent): string | null {
@@ -41,6 +55,12 @@ export default function Settings() {
   const [saving, setSaving] = useState(false);
   const [capturing, setCapturing] = useState(false);
   const [error, setError] = useState('');
+  const [exportedPath, setExportedPath] = useState('');
+  const [backups, setBackups] = useState<Backup[]>([]);
+  const [selectedBackup, setSelectedBackup] = useState('');
+  const [backupBusy, setBackupBusy] = useState(false);
+  const [compressing, setCompressing] = useState(false);
+  const [compressResult, setCompressResult] = useState('');
   const captureRef = useRef<HTMLInputElement>(null);
 
   useEffect(() => {
@@ -55,6 +75,10 @@ export default function Settings() {
       .finally(() => setLoading(false));
   }, []);
 
+  useEffect(() => {
+    loadBackups();
+  }, []);
+
   useEffect(() => {
     const opacity = Math.max(35, Math.min(100, windowOpacity));
     document.documentElement.style.setProperty('--window-opacity', (opacity / 100).toFixed(2));
@@ -84,6 +108,71 @@ export default function Settings() {
     return () => window.removeEventListener('keydown', onKeyDown, true);
   }, [capturing]);
 
+  const exportConfig = async () => {
+    try {
+      setExportedPath(await invoke<string>('export_settings_config'));
+    } catch (e) {
+      setError(formatError(e));
+    }
+  };
+
+  const loadBackups = async () => {
+    try {
+      const list = await invoke<Backup[]>('list_backups');
+      setBackups(list);
+      setSelectedBackup((current) =>
+        list.some((b) => b.name === current) ? current : (list[0]?.name ?? ''),
+      );
+    } catch (e) {
+      setError(formatError(e));
+    }
+  };
+
+  const backupNow = async () => {
+    setBackupBusy(true);
+    setError('');
+    try {
+      await invoke('create_backup');
+      await loadBackups();
+    } catch (e) {
+      setError(formatError(e));
+    } finally {
+      setBackupBusy(false);
+    }
+  };
+
+  const restoreBackup = async () => {
+    if (!selectedBackup) return;
+    setBackupBusy(true);
+    setError('');
+    try {
+      await invoke('restore_backup', { name: selectedBackup });
+      await loadBackups();
+    } catch (e) {
+      setError(formatError(e));
+    } finally {
+      setBackupBusy(false);
+    }
+  };
+
+  const compressHistory = async () => {
+    setCompressing(true);
+    setError('');
+    try {
+      const report = await invoke<CompressionReport>('compress_history');
+      const savedKb = Math.round((report.bytesBefore - report.bytesAfter) / 1024);
+      setCompressResult(
+        report.items
+          ? `Compressed ${report.items} texts, saving ${savedKb} KB`
+          : 'Nothing left to compress',
+      );
+    } catch (e) {
+      setError(formatError(e));
+    } finally {
+      setCompressing(false);
+    }
+  };
+
   const save = async () => {
     setSaving(true);
     setError('');
@@ -95,7 +184,7 @@ export default function Settings() {
       await invoke('set_setting', { key: 'colored_icons', value: coloredIcons });
       await getCurrentWindow().hide();
     } catch (e) {
-      setError(String(e));
+      setError(formatError(e));
     } finally {
       setSaving(false);
     }
@@ -211,10 +300,70 @@ export default function Settings() {
           Colored item icons
         </label>
 
+        <div className="flex flex-col gap-2 text-xs font-medium uppercase tracking-wider text-slate-500 dark:text-slate-400">
+          Backups
+          <div className="flex gap-2">
+            <select
+              value={selectedBackup}
+              onChange={(e) => setSelectedBackup(e.target.value)}
+              disabled={!backups.length}
+              className="h-9 min-w-0 flex-1 rounded-lg border border-slate-200/70 bg-slate-100/80 px-2 text-xs normal-case text-slate-700 outline-none dark:border-slate-700 dark:bg-slate-800/80 dark:text-slate-100"
+            >
+              {backups.length ? null : <option value="">No backups yet</option>}
+              {backups.map((b) => (
+                <option key={b.name} value={b.name}>
+                  {new Date(b.createdAt).toLocaleString()} ({Math.ceil(b.sizeBytes / 1024)} KB)
+                </option>
+              ))}
+            </select>
+            <button
+              className="rounded-lg border border-slate-200/80 bg-slate-100/80 px-3 text-xs font-medium normal-case tracking-normal text-slate-600 transition-colors hover:bg-slate-200 disabled:opacity-50 dark:border-slate-700 dark:bg-slate-800 dark:text-slate-300 dark:hover:bg-slate-700"
+              onClick={restoreBackup}
+              disabled={backupBusy || !selectedBackup}
+              title="Replace history, settings and snippets with this backup"
+            >
+              Restore
+            </button>
+            <button
+              className="rounded-lg border border-slate-200/80 bg-slate-100/80 px-3 text-xs font-medium normal-case tracking-normal text-slate-600 transition-colors hover:bg-slate-200 disabled:opacity-50 dark:border-slate-700 dark:bg-slate-800 dark:text-slate-300 dark:hover:bg-slate-700"
+              onClick={backupNow}
+              disabled={backupBusy}
+            >
+              Back up now
+            </button>
+          </div>
+        </div>
+
+        <div className="flex flex-col gap-2 text-xs font-medium uppercase tracking-wider text-slate-500 dark:text-slate-400">
+          Storage
+          <div className="flex items-center gap-2">
+            <button
+              className="rounded-lg border border-slate-200/80 bg-slate-100/80 px-3 py-2 text-xs font-medium normal-case tracking-normal text-slate-600 transition-colors hover:bg-slate-200 disabled:opacity-50 dark:border-slate-700 dark:bg-slate-800 dark:text-slate-300 dark:hover:bg-slate-700"
+              onClick={compressHistory}
+              disabled={compressing}
+              title="Store long texts zstd-compressed now instead of at the next start"
+            >
+              Compress long texts
+            </button>
+            {compressResult ? (
+              <span className="text-[11px] normal-case tracking-normal text-slate-400">
+                {compressResult}
+              </span>
+            ) : null}
+          </div>
+        </div>
+
         {error ? <div className="text-xs text-red-500">{error}</div> : null}
       </div>
 
       <div className="glass-header flex h-12 items-center justify-end gap-2 border-t border-slate-200/50 px-4 dark:border-slate-700/50">
+        <button
+          className="mr-auto rounded-lg px-2 py-1.5 text-xs font-medium text-slate-500 transition-colors hover:text-primary dark:text-slate-400"
+          onClick={exportConfig}
+          title={exportedPath || 'Write the current settings to config.toml'}
+        >
+          {exportedPath ? 'Exported config.toml' : 'Export config.toml'}
+        </button>
         <button
           className="rounded-lg border border-slate-200/80 bg-slate-100/80 px-3 py-1.5 text-xs font-medium text-slate-600 transition-colors hover:bg-slate-200 dark:border-slate-700 dark:bg-slate-800 dark:text-slate-300 dark:hover:bg-slate-700"
           onClick={close}
@@ -0,0 +1,43 @@
+// Mirrors `ClipItError` in src-tauri/src/error.rs. `code` and the context
+// fields are stable; `message` is a readable fallback.
+export type ClipItError = {
+  code:
+    | 'not_found'
+    | 'already_exists'
+    | 'payload_missing'
+    | 'invalid_input'
+    | 'unsupported'
+    | 'invalid_setting'
+    | 'invalid_shortcut'
+    | 'shortcut_conflict'
+    | 'storage_locked'
+    | 'search_timeout'
+    | 'storage_failure'
+    | 'clipboard_unavailable'
+    | 'internal';
+  message: string;
+  entity?: string;
+  id?: number | null;
+  name?: string;
+  key?: string;
+  shortcut?: string;
+  reason?: string;
+};
+
+export function isClipItError(e: unknown): e is ClipItError {
+  return !!e && typeof e === 'object' && 'code' in e && 'message' in e;
+}
+
+export function formatError(e: unknown): string {
+  if (!isClipItError(e)) return String(e);
+  switch (e.code) {
+    case 'shortcut_conflict':
+      return `${e.shortcut} is already in use (${e.reason})`;
+    case 'invalid_shortcut':
+      return `Invalid shortcut ${e.shortcut}: ${e.reason}`;
+    case 'invalid_setting':
+      return `Invalid ${e.key}: ${e.reason}`;
+    default:
+      return e.message;
+  }
+}